@@ -19,6 +19,8 @@
 //! that if question numbers are included in the template, those should be placed outside
 //! of the question itself in order to retain the correct numbering when questions are 
 //! rearranged.
+//! When a test has fewer questions than the template, a question written as `|<q required>` is in every test and the other
+//! questions are chosen randomly to fill the rest of it.
 //! 
 //! ##### Expressions
 //! Expressions are used to add randomness to questions:
@@ -28,28 +30,261 @@
 //! evaluates to a number when generating a test. Variables represent a random number that will be selected
 //! separately for each test generated. A variable in an expression is any identifier that starts with 
 //! a letter followed by a sequence of characters that can contain letters, numbers or underscores.
+//! Letters from any alphabet can be used, so Greek variable names such as `α` work too.
 //! Variables do not have to be declared and will default to be an integer between 0 and 99 when generated.
 //! Math is allowed in these expressions in order to create specific relationships between numbers in the generated
 //! question. Math is supported using the `mexprp` crate. The scope of variables is the question, so you can have
 //! the same variable names in different questions and they will likely have different values (unless they randomly
 //! end up being the same). If you want more fine tuned control of the range of possible values, you can declare
-//! the variable.
+//! the variable. An identifier followed by parentheses, such as `abs(a)`, is a function call rather than a variable. The
+//! `validate` function can be used to check that every function used in a Document is supported by `mexprp`.
+//! An expression that is text rather than math, such as `|<e>The capital is Paris</e>|`, is shown as it was written instead of being
+//! evaluated.
+//! A character escaped with a backslash is shown as it is while the math around it is evaluated, such as `|<e>\$a*2</e>|` for `$6`
+//! or `|<e>a \+ b</e>|` for `3 + 4`. A backslash itself is written as `\\`.
+//! An expression comparing two values with `<`, `>`, `<=`, `>=`, `==` or `!=`, such as `|<e>a > b</e>|`, is shown as `True` or `False`
+//! for the values of the test, such as for the answer to a true or false question. The text can be changed with the `true_text` and
+//! `false_text` of the GenerateOptions, and values that only differ by floating point error are treated as equal.
+//! Numbers are normally rounded half away from zero, and the `rounding` of the GenerateOptions can choose another way to round
+//! them to the decimals they are shown with: `Rounding::HalfUp`, `Rounding::HalfEven` so that `0.0005` is shown as `0` at 3 decimals,
+//! `Rounding::Truncate` or `Rounding::Ceiling`.
+//! If the values of a question make one of its expressions divide by zero or give a result that isn't a finite number, such as
+//! `10/b` when `b` is 0, the values of the question are generated again. `try_generate` returns an error if that keeps happening.
 //! 
 //! ##### Variable Declarations
 //! A variable can be declared anywhere in the question in the following format:
 //! 
 //! `|<v>var_name: type = [min,max]</v>|` where var_name is the name of your variable, type is either int or real, and min and max
-//! are integers representing the lower and upper bounds respectively of the value of your variable. An example declaration would be
+//! are numbers representing the lower and upper bounds respectively of the value of your variable. An example declaration would be
 //! `|<v>a: int = [0,99]</v>|`. This is the declaration assumed for any variable without a declaration, so including this exact
-//! declaration in your code would be unecessary.
+//! declaration in your code would be unecessary. The bounds of a real variable can also be decimals or use scientific notation,
+//! for example `|<v>q: real = [1e-9, 1e-6]</v>|`. Results that are very small or very large are displayed in scientific notation.
+//! A variable can also be held at a single value instead of being randomly generated, such as `|<v>a: int = 7</v>|`.
+//! Several variables can be declared the same way at once by listing their names, such as `|<v>a, b, c: int = [1,10]</v>|`.
+//! Adding a granularity after the range, such as `|<v>x: real = [0,10] grain 0.25</v>|`, makes every value a multiple of it.
+//! A variable can also be chosen from a special set of numbers within the bounds, such as a prime with `|<v>p: int = prime[2,50]</v>|`
+//! or a perfect square with `|<v>s: int = set(square, [1,100])</v>|`. The sets are `prime`, `square`, `cube`, `even` and `odd`.
+//! A variable can also cycle through a list of values in order across the generated tests with `|<v>a: int = cycle[2,5,8]</v>|`,
+//! so the first test uses 2, the second 5, the third 8, the fourth 2 again, and so on.
+//! The bounds can use other variables of the question, such as `|<v>y: real = [x-5, x+5]</v>|`, and adding `clamp [0,10]` after them keeps
+//! the bounds between 0 and 10. If the range of such a variable ends up empty, the variables of the question are generated again.
+//! Several variables can be declared together to add up to a total with `|<v>[a,b,c]: partition(20)</v>|`, which gives each of them a
+//! random non-negative integer value so that `a`, `b` and `c` always add up to 20.
+//! A variable can be chosen from a list of values with weights, such as `|<v>grade: choice = [A:1, B:2, C:3]</v>|`, where each value
+//! is chosen in proportion to its weight, so `B` is twice as likely as `A`. A value without a weight has a weight of 1 and a value with
+//! a weight of 0 is never chosen. If every value is a number the variable can be used in math, and otherwise it is shown as it is.
+//! A question with `|<q summary>` is generated after the other questions of the test and can use how many of them chose each value
+//! of a choice, such as `|<e>count_grade_A</e>|` for the number of questions where `grade` was `A`. These counts are also kept in the
+//! `globals` of the test.
+//! `|<given/>|` inside a question is replaced by the values of its variables in name order, such as `Given: |<given/>|` for
+//! `Given: a = 3, b = 7`, so the values don't have to be listed one by one.
+//! Processing with `try_process` returns an error for a declaration whose range has no possible values, such as `[10,1]`, or for a
+//! variable declared more than once in the same question with different declarations. `process` uses the last declaration instead.
+//! It also returns an error for a tag that isn't closed or doesn't match the tags around it, such as the extra `</q>|` in
+//! `|<q>a</q>|</q>|`, instead of leaving the tag in the text.
+//! 
+//! ##### Global Variables
+//! 
+//! A variable declaration placed outside of every question declares a global variable. Global variables are generated once for each
+//! test and every question that uses the variable without declaring its own version of it gets the same value. A question variable
+//! can also be linked to a global variable with `|<v>x: int linked base ± 2</v>|` (or `+-` instead of `±`), which gives `x` the value
+//! of the global variable `base` plus a random offset between -2 and 2. This allows several questions to use values that are close to
+//! each other without being identical.
 //! 
 //! ##### Answers
 //! 
 //! Answers are used to generate an answer key for each test. Answers should be included for every question when using
 //! `process_with_answers`. They should be in the format `|<a>Answer</a>|` and should appear right after the question. Variables
 //! in answers have the same scope as their corresponding question so you can use expressions in your answers to calculate the
-//! answer in terms of the randomly generated variables in your question.
+//! answer in terms of the randomly generated variables in your question. Every variable used in an answer must also be used in the
+//! question (or be a global variable), and `validate` reports any variable that is only used in an answer. The answer key shows `No Answers Provided`
+//! for a question without an answer, which can be changed or left out with the `missing_answer` of the `GenerateOptions`.
+//! Several acceptable forms of an answer can be given one after the other, such as `|<a>0.5</a>||<a>1/2</a>|`, and the answer key
+//! shows all of them, such as `0.5 or 1/2`.
+//! The range an answer is expected to be in can be given with `|<a range=[0,100]>Answer</a>|`. A generated answer with a value outside
+//! of it is added to the `warnings` of its question, and `try_generate` returns it as an error, which helps to catch mistakes in a template.
+//! An answer can depend on the scenario of the question with a condition, such as `|<a when=north>Up</a>||<a when=south>Down</a>|`
+//! for a question with `|<v>dir: choice = [north, south]</v>|`. `when=north` matches a text variable of the question with that value,
+//! and `when=a:2` matches when the variable `a` is 2. Only the answers whose condition matches are used, or the answers without a
+//! condition if none of them match.
+//! The difference from an answer that is accepted when grading can be given with `|<a tolerance=0.01>Answer</a>|`, and
+//! `Test::grading_spec` lists the answer, tolerance, other acceptable forms and points of each question for an automatic grader.
+//! For self-check worksheets, the `inline_answers` of the `GenerateOptions` places each answer right after its question, such as
+//! `Some(String::from(" [Answer: {answer}]"))`.
+//! Setting `footnote_answers` instead marks each question with a number like `[1]` and lists the answers under `Answers` at the
+//! end of the content of the test.
+//! The `line_ending` of the `GenerateOptions` replaces every line ending in the content and answers of a test, such as with
+//! `Some(String::from("\r\n"))` for printing on Windows, without removing blank lines.
+//! 
+//! 
+//! ##### Parts
+//! 
+//! A question can be split into sub-parts in the following format:
+//! 
+//! `|<part>Part Content</part>|` Parts are shuffled within their question when generating a test while the rest of the
+//! question stays in the same place. Each part is labeled with a letter (`a)`, `b)`, ...) based on its position after shuffling,
+//! so the labels should not be included in the part content. If the answer to the question has the same number of parts, the
+//! answer parts are reordered and labeled the same way so they still match the question.
+//! For a multiple choice question, the correct option can be marked with `|<part correct>`. Setting `rotate_correct` in the
+//! `GenerateOptions` then gives the correct option each letter about as often as the others across the tests of a batch, instead of
+//! leaving it to the shuffle. Parts that show the same text once their numbers are formatted, such as `|<e>10/3</e>|` and
+//! `|<e>3.3334</e>|`, would be the same option twice, so the values of the question are generated again until the parts differ.
+//! A part can be given a number of points with `|<part points=2>`. When the answer has the same number of parts, the answer key shows
+//! the points after the matching answer part, such as `a) 5 [2 pts]`.
+//! For bubble sheets, `generate_bubble_sheets` generates a test from each seed along with its key of correct letters, such as
+//! `1-B 2-D 3-A`, which is also given by `Test::bubble_key`. The letters follow the seeded shuffle, so they are the same whenever a
+//! test is generated again from its seed.
+//! 
+//! ##### Units
+//! 
+//! A unit bundles a question with its answer and declarations so that it can be reused:
+//! 
+//! `|<unit name>|<q>Question</q>||<a>Answer</a>|</unit>|` defines a unit called name. The definition itself does not appear in
+//! the Document. Each `|<use name/>|` is replaced by the contents of the unit, so every use becomes a separate question with its
+//! own randomly generated variables.
+//!
+//! An expression used in many questions can be declared once as a macro, such as `|<macro TAX>price*0.08</macro>|`. Each `TAX`
+//! in an expression, such as `|<e>TAX + price</e>|`, is then replaced by `(price*0.08)`, which uses the `price` of the question it
+//! is in. Macros can use other macros, and `try_process` returns an error for a macro that uses itself.
+//! 
+//! ##### Flags
+//! 
+//! A question can be limited to certain versions of a test with the `if` attribute:
+//! 
+//! `|<q if=honors>Question Content</q>|` is only used when the `honors` flag is active. Flags are activated by listing them in
+//! the `flags` of the `GenerateOptions` passed to `generate_with_options`. Several flags can be given separated by commas, such as
+//! `|<q if=honors,advanced>`, in which case the question is used if any of them is active. Questions without flags are always used.
+//! Questions can also be given tags, such as their topics, with `|<q tags=algebra,fractions>Question Content</q>|`. `generate_filtered`
+//! generates tests using only the questions with at least one of the given tags, so one large template can be used for many quizzes.
+//! A question can be given a difficulty with `|<q difficulty=2>`, where higher numbers are harder. Setting `by_difficulty` in the
+//! `GenerateOptions` puts the questions of each test in order from easiest to hardest instead of shuffling them, such as for practice.
+//! 
+//! ##### Currency
+//! 
+//! An expression can be displayed as an amount of money with the `currency` attribute:
+//! 
+//! `|<e currency>price*qty</e>|` is rounded to two decimals and its digits are separated into groups of three, such as `$1,234.56`. The
+//! rounding only affects how the result is displayed. The symbol and separators can be changed with the `currency_symbol`,
+//! `thousands_separator` and `decimal_separator` of the `GenerateOptions` passed to `generate_with_options`.
+//! 
+//! ##### Hints
+//! 
+//! A question can include optional hints in the following format:
+//! 
+//! `|<hint>Hint Content</hint>|` Hints are left out of the question and the answer key. They are generated with the same variable
+//! values as their question and collected by `Test::hints`, which numbers each hint by the position of its question in the test.
+//! 
+//! ##### Random Functions
+//! 
+//! Expressions can call `rand()`, which gives a random number between 0 and 1, and `randint(min,max)`, which gives a random integer
+//! between min and max. Each call is given one value per question in each test, and a call written the same way anywhere else in the
+//! question, its parts, its hints or its answer gives the same value. For example, `|<e>randint(1,6)</e>|` in a question and
+//! `|<e>randint(1,6)*2</e>|` in its answer always agree. Repeating a call in one expression draws it again, and each repeat is matched
+//! with the repeat of the same call in the other expressions, so `|<e>randint(1,6)+randint(1,6)</e>|` rolls two dice and
+//! the same expression in the answer adds the same two.
+//! 
+//! ##### Metadata
+//! 
+//! Information about the whole document can be given in the following format:
+//! 
+//! `|<meta>title: Algebra Quiz
+//! author: Ms. Smith</meta>|` Each line has a key and a value separated by a colon. The metadata block is removed from the Document and
+//! its values can be read with `Document::meta`. `Document::fill_metadata` replaces each `{{key}}` in a template with its value, which
+//! can be used to build the header passed to `paginate`.
+//! 
+//! ##### Padding
+//! 
+//! The result of an expression can be padded to a fixed width so that values line up in columns:
+//! 
+//! `|<e width=5>a*b</e>|` pads the result with spaces on the left until it is at least 5 characters long. Adding `align=left` puts the
+//! padding after the result instead, and `fill=0` pads with zeros (placed after any minus sign) instead of spaces.
+//! 
+//! ##### Modulo
+//! 
+//! Expressions can call `mod(a,b)` to get the remainder of dividing a by b, such as `|<e>mod(a, 7)</e>|`. Both numbers are rounded to
+//! integers first and the result is never negative, so `mod(-7, 3)` is 2. The arguments can be any expressions, such as
+//! `mod(abs(a), max(b, 2))`.
+//! 
+//! ##### Named Expressions
+//! 
+//! An expression can be given a name, such as `|<e name=total>a+b</e>|`, so that its value can be looked up after generating a test
+//! with `Test::expression_value`, which takes the position of the question in the test and the name of the expression.
+//! 
+//! ##### Shared Questions
 //! 
+//! A question can use the variables of an earlier question, which is useful when a question builds on the one before it:
+//! 
+//! `|<q id=speed>Question Content</q>|` gives the question an id, and `|<q shares=speed>Question Content</q>|` lets a later question
+//! use the variables of the question with that id in its expressions and its answer, with the same values in each test. Variables
+//! declared in the sharing question are still its own. Several ids can be given separated by commas, and `validate` reports a
+//! question that shares an id no earlier question has.
+//! A hint can share the variables of earlier questions on its own with `|<hint shares=speed>Hint Content</hint>|`, such as for a
+//! worked solution that refers back to an earlier question, while the question itself keeps only its own variables. A variable of
+//! the question takes the place of a shared variable with the same name.
+//! 
+//! ##### Random Words
+//! 
+//! An expression can pick one of several words or phrases instead of being evaluated:
+//! 
+//! `|<e pick>cat|dog|bird</e>|` is replaced by one of `cat`, `dog` and `bird`, chosen at random for each test. The alternatives are
+//! separated by `|` and don't contain variables. A pick written the same way anywhere else in the question, its parts, its hints or
+//! its answer gives the same alternative, so the answer can refer to the word chosen for the question.
+//! 
+//! ##### Compact Tags
+//! 
+//! Templates can also be written with shorter tags that are easier to type, such as `{{q}}Question Content{{/q}}`. Every tag has a
+//! compact form with the same name and attributes: `{{e currency}}price*qty{{/e}}`, `{{v}}a: int = [0,9]{{/v}}` and `{{use name/}}`
+//! are the same as `|<e currency>price*qty</e>|`, `|<v>a: int = [0,9]</v>|` and `|<use name/>|`. `expand_compact` turns the compact
+//! tags of a template into the standard ones before it is processed, and standard tags can still be mixed in.
+//! 
+//! ##### Points
+//! 
+//! A question can be given a number of points with the `points` attribute:
+//! 
+//! `|<q points=5>Question Content</q>|` The points stay with the question when it is moved, and `|<points/>|` inside the question is
+//! replaced by its points, such as `|<q points=5>Explain your answer (|<points/>| points)</q>|`. `Test::total_points` adds up the
+//! points of the questions in a test.
+//! The number of attempts allowed for a question taken online can be given the same way with `|<q attempts=3>`. `Test::to_json`
+//! exports a test with the points and attempts of each of its questions, such as to import it into a learning management system.
+//! 
+//! ##### Seeds
+//! 
+//! A template can declare the seed its tests are generated from, so that anyone generating tests from it gets the same tests:
+//! 
+//! `|<seed>12345</seed>|` is removed from the Document and stored as its `seed`. `generate`, `generate_with_options` and the other
+//! functions that don't take an rng of their own then always generate the same batch of tests from the template.
+//! The `random_source` of the GenerateOptions can choose another source of randomness instead: `RandomSource::Thread`,
+//! `RandomSource::Os`, which draws every choice from the secure random number generator of the operating system so that the
+//! tests can't be predicted, or `RandomSource::Seed(n)`. The default, `RandomSource::Document`, uses the seed of the template if it
+//! has one and otherwise a generator seeded once from the operating system, which is faster than `RandomSource::Os`.
+//! The `samplers` of the GenerateOptions map the names of variables to functions `fn(&str, &mut dyn RngCore) -> f64` that give
+//! their values instead of their declarations, such as to draw them from real data. Int variables round the value, and a linked
+//! variable takes it as its offset.
+//! 
+//! ##### Angles
+//! 
+//! The trigonometric functions use radians by default. Adding the `degrees` attribute, such as `|<e degrees>sin(30)</e>|`, makes an
+//! expression use degrees instead: the arguments of `sin`, `cos` and `tan` are in degrees and `asin`, `acos`, `atan` and `atant` give
+//! their results in degrees. Setting `degrees` in the `GenerateOptions` does this for every expression.
+//! 
+//! ##### Symbolic Expressions
+//! 
+//! An expression can be shown without being evaluated with the `symbolic` attribute, which is useful for questions such as "simplify this
+//! expression":
+//! 
+//! `|<e symbolic>a - b*x</e>|` is shown with the values of its variables filled in, such as `3 - (-2)*1.5`, in the question, while the
+//! same expression in the answer is evaluated, such as `6`.
+//! For worked solutions, `|<e show>a + b</e>|` shows the expression as it is written, with its values filled in and its result,
+//! such as `a + b = 3 + 4 = 7`.
+//! 
+//! ##### Tables
+//! 
+//! Values can be drawn from a table of data instead of being random numbers, which is useful for realistic word problems:
+//! 
+//! `|<v>city: row("cities.csv")</v>|` loads the CSV file `cities.csv`, whose first line names its columns, and chooses a random row of
+//! it for each test. Each column can be used in the question and its answer with the name of the variable in front of it, such as
+//! `|<e>city.name</e>|` or `|<e>city.pop/city.area</e>|`. Columns that aren't numbers are shown as they are and can't be used in math.
+//! The path is relative to the directory the program is run from, and `try_process` returns an error if the file can't be read.
 //! 
 //! # Examples
 //! Here is a simple example, you can find more example templates in the `examples` folder of the GitHub repository.
@@ -74,24 +309,115 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 use itertools::Itertools;
-use rand::Rng;
+use rand::{Rng, RngCore, SeedableRng};
+use rand::seq::SliceRandom;
 use std::cmp;
 use std::collections::{HashSet, HashMap};
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
 
 /// A Document is a template to be used to generate filled out tests
 pub struct Document {
     ///This is a list of the questions in the Document, in the order provided
     pub questions: Vec<Question>,
-    ///This is a list of the other content in the Document that should stay in the same place when the questions move
-    pub layout: Vec<String>
+    ///This is a list of the other content in the Document that should stay in the same place when the questions move.
+    ///It always has exactly one more element than questions, since the layout comes before, between and after every question.
+    ///Elements can be empty, for example between two adjacent questions
+    pub layout: Vec<String>,
+    ///This is a list of variables declared outside of the questions. They are generated once per Test and shared by every question
+    pub globals: HashSet<Var>,
+    ///This maps each metadata key of the Document, such as its title, to its value
+    pub metadata: HashMap<String, String>,
+    ///This is the seed declared by the template, if any. Tests generated without an rng of their own are generated from it, so they are the same every time
+    pub seed: Option<u64>,
+    ///This maps the name of each macro declared by the template to the expression it stands for. Macros are already expanded in the expressions of the questions
+    pub macros: HashMap<String, String>
 }
 
+#[derive(Clone)]
 ///A Test is generated from a Document and is ready for use
 pub struct Test {
     ///A String representing the contents of the Test
     pub content: String,
     ///A String representing the answers of a Test
-    pub answers: String
+    pub answers: String,
+    ///This is a list of the questions in the Test, in the order they appear
+    pub questions: Vec<TestQuestion>,
+    ///This maps the name of each global variable to the value it was given in the Test, along with how many questions of the Test
+    ///chose each value of a choice, such as `count_grade_A`
+    pub globals: HashMap<String, f64>
+}
+
+#[derive(Clone)]
+///A TestQuestion holds the generated text of a single question in a Test
+pub struct TestQuestion {
+    ///The index of the question in the Document that it was generated from
+    pub index: usize,
+    ///A String representing the contents of the question
+    pub content: String,
+    ///A String representing the answer to the question
+    pub answer: String,
+    ///This is a list of the acceptable forms of the answer, which the answer shows joined by `or`. It is empty if the question doesn't have an answer
+    pub forms: Vec<String>,
+    ///This is the largest difference from the answer that is accepted when grading, if provided
+    pub tolerance: Option<f64>,
+    ///A String representing the hints for the question, which is empty if it doesn't have any
+    pub hint: String,
+    ///This maps the name of each variable in the question to the value it was given
+    pub values: HashMap<String, f64>,
+    ///This maps the name of each named expression in the question to its generated text
+    pub named: HashMap<String, String>,
+    ///This is the number of points the question is worth, if provided
+    pub points: Option<f64>,
+    ///This is the number of attempts allowed for the question when it is taken online, if provided
+    pub attempts: Option<u32>,
+    ///This is the label of the correct part of a multiple choice question after shuffling, such as `'b'`, if it has one
+    pub correct: Option<char>,
+    ///This is a list of the problems found while generating the question that didn't stop it from being generated, such as an answer outside of its expected range
+    pub warnings: Vec<MorphiusError>
+}
+
+///BatchStats summarizes the values generated across a batch of Tests
+pub struct BatchStats {
+    ///This maps the name of each global variable to a summary of its values
+    pub globals: HashMap<String, VarStats>,
+    ///This maps the index of each question in the Document and the name of each of its variables to a summary of its values
+    pub variables: HashMap<(usize, String), VarStats>,
+    ///This is the number of distinct question orders used by the Tests
+    pub orderings: usize
+}
+
+#[derive(Debug, PartialEq)]
+///QuestionGrade holds what an automatic grader needs to grade a single question of a Test
+pub struct QuestionGrade {
+    ///The index of the question in the Document that it was generated from
+    pub index: usize,
+    ///A String representing the answer to the question, which is its first acceptable form
+    pub answer: String,
+    ///This is the largest difference from the answer that is accepted, if provided
+    pub tolerance: Option<f64>,
+    ///This is a list of the other acceptable forms of the answer
+    pub alternatives: Vec<String>,
+    ///This is the number of points the question is worth, if provided
+    pub points: Option<f64>
+}
+
+#[derive(Debug, PartialEq)]
+///VarStats summarizes the values a single variable was given
+pub struct VarStats {
+    ///This is the smallest value of the variable
+    pub min: f64,
+    ///This is the largest value of the variable
+    pub max: f64,
+    ///This is the average value of the variable
+    pub mean: f64,
+    ///This is the number of values the summary is made from
+    pub count: usize
 }
 
 ///A Question is an object representing a question
@@ -103,7 +429,35 @@ pub struct Question {
     ///This is a list of the other content in the question that does not need to be evaluated
     pub layout: Vec<String>,
     ///This is either the Answer to the question, if provided or None
-    pub answer: Option<Answer>
+    pub answer: Option<Answer>,
+    ///This is a list of the sub-parts of the question, in the order provided. They are shuffled when generating the question text
+    pub parts: Vec<Part>,
+    ///This is a list of flags for the question. If it isn't empty, the question is only used when one of these flags is active
+    pub flags: Vec<String>,
+    ///This is a list of the hints for the question. They are kept out of the question text and generated separately
+    pub hints: Vec<Hint>,
+    ///This is a list of the groups of variables in the question that are generated to add up to a total
+    pub partitions: Vec<Partition>,
+    ///This is the id other questions can use to share the variables of the question, if provided
+    pub id: Option<String>,
+    ///This is a list of the ids of earlier questions whose variables the question shares
+    pub shares: Vec<String>,
+    ///This is the number of points the question is worth, if provided
+    pub points: Option<f64>,
+    ///This is a list of the tags of the question, such as its topics, used to choose which questions a test is made from
+    pub tags: Vec<String>,
+    ///This is the number of attempts allowed for the question when it is taken online, if provided
+    pub attempts: Option<u32>,
+    ///This is a list of the tables the question draws rows from
+    pub tables: Vec<Table>,
+    ///This is a list of the variables of the question that are chosen from a list of weighted values
+    pub choices: Vec<Choice>,
+    ///This is true if the question summarizes the others. It is generated after them and can use how many of them chose each value of a choice
+    pub summary: bool,
+    ///This is true if the question is in every test, even when a test has fewer questions than the Document
+    pub required: bool,
+    ///This is how difficult the question is, if provided, where higher numbers are harder
+    pub difficulty: Option<u32>
 }
 
 ///An Answer is the answer to a question. It is processed very similarly, it just uses the same scope as its parent question
@@ -111,7 +465,69 @@ pub struct Answer {
     ///This is a list of expressions that need to be evaluated using the same variable values as its parent question
     pub expressions: Vec<Expression>,
     ///This is a list of the content in the Answer that doesn't need to be evaluated
-    pub layout: Vec<String>
+    pub layout: Vec<String>,
+    ///This is a list of the sub-parts of the Answer. They are reordered to match the sub-parts of its parent question
+    pub parts: Vec<Part>,
+    ///This is a list of other acceptable forms of the Answer, such as `1/2` for `0.5`. They are shown in the answer key after it
+    pub alternatives: Vec<Answer>,
+    ///This is the range the values of the expressions in the Answer are expected to be in, if provided. Values outside of it are reported as warnings
+    pub range: Option<(f64, f64)>,
+    ///This is the largest difference from the Answer that is accepted when grading, if provided
+    pub tolerance: Option<f64>,
+    ///This is the condition for using the Answer, if provided, such as `north` or `dir:north`. An Answer with a condition is only used
+    ///when a text variable of the question has that value, or when the named variable has that value
+    pub when: Option<String>
+}
+
+///A Part is a sub-part of a question or answer. It uses the same scope as its parent question
+pub struct Part {
+    ///This is a list of expressions that need to be evaluated when generating the part text
+    pub expressions: Vec<Expression>,
+    ///This is a list of the other content in the part that does not need to be evaluated
+    pub layout: Vec<String>,
+    ///This is true if the part is the correct option of a multiple choice question, written as `|<part correct>`
+    pub correct: bool,
+    ///This is the number of points the part is worth, if provided with `|<part points=2>`. It is shown after the matching part of the answer
+    pub points: Option<f64>
+}
+
+///A Partition is a group of variables that are given random non-negative integer values adding up to a total
+pub struct Partition {
+    ///The names of the variables in the group
+    pub names: Vec<String>,
+    ///The total of the variables
+    pub total: i64
+}
+
+///A Choice is a variable whose value is chosen from a list of values, where each value is chosen in proportion to its weight
+pub struct Choice {
+    ///The name of the variable
+    pub name: String,
+    ///The values and their weights. A value with a weight of 0 is never chosen
+    pub options: Vec<(String, f64)>
+}
+
+///A Table is a table of data loaded from a CSV file. A variable declared with `row` is given a random row of it for each Test
+pub struct Table {
+    ///The name of the variable. Its value is the index of the chosen row
+    pub name: String,
+    ///The path of the CSV file the table was loaded from
+    pub path: String,
+    ///The names of the columns, prefixed with the name of the variable, such as `city.pop`
+    pub columns: Vec<String>,
+    ///The rows of the table, not including the header
+    pub rows: Vec<Vec<String>>
+}
+
+///A Hint is an optional hint for a question that is generated separately from the test
+pub struct Hint {
+    ///This is a list of expressions that need to be evaluated when generating the hint text
+    pub expressions: Vec<Expression>,
+    ///This is a list of the other content in the hint that does not need to be evaluated
+    pub layout: Vec<String>,
+    ///This is a list of the ids of earlier questions whose variables the hint can use, written as `|<hint shares=speed>`,
+    ///in addition to the variables of its own question
+    pub shares: Vec<String>
 }
 
 struct Content {
@@ -123,7 +539,45 @@ struct Content {
 ///An Expression represents a mathematical expression to be evaluated
 pub struct Expression {
     ///This is a list of variables/other content that makes up the expression
-    pub expression: Vec<ExpComp>
+    pub expression: Vec<ExpComp>,
+    ///This is the way the result of the expression is displayed
+    pub mode: ExpMode,
+    ///This is the smallest number of characters the result is padded to, or 0 for no padding
+    pub width: usize,
+    ///This is the side of the padded space the result is placed on
+    pub align: Align,
+    ///This is the character used for padding
+    pub fill: char,
+    ///This is the name of the expression, if any, used to look up its value in a generated Test
+    pub name: Option<String>,
+    ///If this is true, the trigonometric functions of the expression use degrees instead of radians
+    pub degrees: bool
+}
+
+#[derive(Debug, PartialEq, Eq)]
+///This is an enum used to choose where a padded expression result is placed
+pub enum Align {
+    ///This places the result on the left, with the padding after it
+    Left,
+    ///This places the result on the right, with the padding before it
+    Right
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///This is an enum used to choose how the result of an expression is displayed
+pub enum ExpMode {
+    ///This displays the result as a number
+    Number,
+    ///This displays the result as an amount of money, like `$1,234.56`
+    Currency,
+    ///This displays one of the alternatives of the expression, separated by `|`, chosen at random instead of evaluating it
+    Pick,
+    ///This displays the expression as it was written, because it is text such as `The capital is Paris` rather than math
+    Text,
+    ///This displays the expression with the values of its variables filled in, such as `3 + 5`, instead of evaluating it. It is evaluated in answers
+    Symbolic,
+    ///This displays the expression as it was written, with the values of its variables filled in and its result, such as `a + b = 3 + 4 = 7`
+    Show
 }
 
 #[derive(PartialEq, Eq, Hash)]
@@ -136,338 +590,5969 @@ pub struct Var {
     ///The minimum value for this variable
     pub min: String,
     ///The maximum value for this variable
-    pub max: String
+    pub max: String,
+    ///The name of the global variable this variable is linked to, if any. A linked variable is the value of the global variable plus an offset between min and max
+    pub base: Option<String>,
+    ///The granularity of the variable, if any. A variable with a granularity is always a multiple of it
+    pub grain: Option<String>,
+    ///The lower and upper limits the bounds of the variable are clamped to, if any. This is used when the bounds depend on other variables
+    pub clamp: Option<(String, String)>,
+    ///The special set the variable is chosen from, if any, such as prime or square. The variable is then a member of the set between min and max
+    pub set: Option<String>,
+    ///The list of values the variable cycles through, if any. The variable takes the values in order across the generated tests, starting over after the last one
+    pub cycle: Option<Vec<String>>
 }
 
+#[derive(Clone)]
 ///This is an enum used to differentiate between variable names and other content of an expression
 pub enum ExpComp {
     ///This denotes a variable name
     Var(String),
-    ///This denotes everything other than variable names
-    Other(String)
+    ///This denotes a function name
+    Func(String),
+    ///This denotes everything other than variable and function names
+    Other(String),
+    ///This denotes text escaped with backslashes, such as `\$`, which is shown as it is while the rest of the expression is evaluated
+    Literal(String)
 }
 
-#[derive(PartialEq, Eq, Hash)]
-struct Num {
-    whole: i64,
-    frac: Option<i64>
+#[derive(Debug, Clone, PartialEq, Eq)]
+///A MorphiusError describes a problem with a template that would prevent tests from being generated
+pub enum MorphiusError {
+    ///An expression calls a function that is not supported
+    UnknownFunction {
+        ///The name of the unknown function
+        name: String,
+        ///The index of the question containing the expression
+        question: usize,
+        ///The text of the expression calling the function
+        expression: String
+    },
+    ///A variable needed to evaluate an expression was not given a value
+    MissingVariable {
+        ///The name of the variable
+        name: String
+    },
+    ///An answer was requested for a question without one
+    MissingAnswer,
+    ///More named forms were asked for than there are letters to label them with
+    TooManyForms {
+        ///The number of forms asked for
+        count: usize
+    },
+    ///There are fewer question orders than named forms, so the forms can't all have a different order
+    NotEnoughOrders {
+        ///The number of forms asked for
+        forms: usize,
+        ///The number of question orders that can be used
+        orders: usize
+    },
+    ///There is no question at a position of a Test
+    InvalidPosition {
+        ///The position that was asked for
+        pos: usize,
+        ///The number of questions in the Test
+        len: usize
+    },
+    ///A Test has a question that isn't in the Document it is used with, such as when the Test was generated from another Document
+    MismatchedDocument {
+        ///The index of the question in the Document the Test was generated from
+        index: usize,
+        ///The number of questions in the Document it is used with
+        len: usize
+    },
+    ///More tests would be generated than allowed
+    TooManyTests {
+        ///The number of tests that would be generated
+        count: usize,
+        ///The largest number of tests allowed
+        cap: usize
+    },
+    ///A variable can't be enumerated because it can take any real value in a range
+    NotDiscrete {
+        ///The name of the variable
+        name: String
+    },
+    ///The primes in the range of a variable can't be listed because the range is too large, such as when generating every test
+    ///from `prime[2,1e12]`. They can still be drawn at random
+    SetTooLarge {
+        ///The name of the variable
+        name: String
+    },
+    ///A variable was declared with a lower bound greater than its upper bound, or an int variable has no whole numbers in its range
+    InvalidBounds {
+        ///The name of the variable
+        name: String,
+        ///The lower bound of the variable
+        min: String,
+        ///The upper bound of the variable
+        max: String
+    },
+    ///An expression uses a variable that isn't a variable of its question or a global variable, such as a variable only used in an answer
+    UnknownVariable {
+        ///The name of the variable
+        name: String,
+        ///The index of the question containing the expression
+        question: usize
+    },
+    ///A variable is declared more than once in the same question with different declarations
+    DuplicateDeclaration {
+        ///The name of the variable
+        name: String,
+        ///The index of the question containing the declarations
+        question: usize,
+        ///The first declaration of the variable
+        first: String,
+        ///The declaration that replaced it
+        second: String
+    },
+    ///Every question order that could be used is in the forbidden orders of the GenerateOptions
+    NoAllowedOrder,
+    ///The deadline of the GenerateOptions passed before the tests were generated
+    DeadlineExceeded,
+    ///A question shares the variables of an id that no earlier question has
+    UnknownQuestion {
+        ///The id of the shared question
+        id: String,
+        ///The index of the question sharing the variables
+        question: usize
+    },
+    ///An expression is longer or more deeply nested than the limits in the GenerateOptions, so it isn't evaluated
+    TooComplex {
+        ///The expression, with the values of its variables filled in
+        expression: String,
+        ///The index of the question containing the expression
+        question: usize
+    },
+    ///The value of an expression in an answer is outside of the range the answer is expected to be in
+    AnswerOutOfRange {
+        ///The index of the question
+        question: usize,
+        ///The value of the expression
+        value: String,
+        ///The lower bound of the expected range
+        min: String,
+        ///The upper bound of the expected range
+        max: String
+    },
+    ///The CSV file of a table couldn't be read
+    MissingTable {
+        ///The path of the file
+        path: String,
+        ///A description of the problem
+        message: String
+    },
+    ///A tag was left in the text because it isn't closed or its closing tag doesn't match, such as `|<q>a</q>|</q>|`
+    MalformedTag {
+        ///The tag
+        tag: String,
+        ///The index of the question containing the tag, or None if it is outside of every question
+        question: Option<usize>
+    },
+    ///A choice variable has a value that can't be read, or none of its values can be chosen because their weights are negative or all 0
+    InvalidChoice {
+        ///The name of the variable
+        name: String,
+        ///The values of the variable as they were written
+        values: String
+    },
+    ///An expression in a question doesn't have a finite result, such as when it divides by a variable that is 0, even after the values of the question were generated again
+    NotFinite {
+        ///The expression being evaluated, with the values of its variables filled in
+        expression: String,
+        ///The index of the question containing the expression
+        question: usize
+    },
+    ///Two parts of a question show the same text, such as two options that round to the same number, even after the values of the
+    ///question were generated again
+    DuplicateParts {
+        ///The text both parts show
+        text: String,
+        ///The index of the question containing the parts
+        question: usize
+    },
+    ///A macro uses itself, directly or through other macros, so it can't be expanded
+    RecursiveMacro {
+        ///The name of the macro
+        name: String
+    },
+    ///An expression couldn't be evaluated, such as when it isn't valid math or divides by zero
+    Math {
+        ///The expression being evaluated, with the values of its variables filled in
+        expression: String,
+        ///A description of the problem
+        message: String
+    }
 }
 
-
-
-
-
-///This function takes an input &str in the desired template format and generates a document. If the document has answers you should use process_with_answers.
-///
-/// # Arguments
-///
-/// * `input` - A string slice that holds the template contents for the Document
-///
-/// # Examples
-///
-/// ```
-/// use morphius;
-/// let doc = morphius::process("Document Contents");
-/// ```
-pub fn process(input: &str) -> Document {
-    lazy_static! {
-        static ref QUESTION: Regex = Regex::new(r"(?s)\|<q>(.*?)</q>\|").unwrap();
+impl fmt::Display for MorphiusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MorphiusError::UnknownFunction { name, question, expression } => write!(f, "Unknown function '{}' in expression '{}' of question {}", name, expression, question + 1),
+            MorphiusError::MissingVariable { name } => write!(f, "No value was given for the variable '{}'", name),
+            MorphiusError::MissingAnswer => write!(f, "The question does not have an answer"),
+            MorphiusError::TooManyTests { count, cap } => write!(f, "{} tests would be generated but at most {} are allowed", count, cap),
+            MorphiusError::NotDiscrete { name } => write!(f, "The variable '{}' can take any real value in its range, so its values can't be listed", name),
+            MorphiusError::TooManyForms { count } => write!(f, "{} forms were asked for, but only 26 can be labeled with letters", count),
+            MorphiusError::NotEnoughOrders { forms, orders } => write!(f, "{} forms were asked for, but there are only {} question orders to give them", forms, orders),
+            MorphiusError::InvalidPosition { pos, len } => write!(f, "There is no question at position {} of a test with {} questions", pos, len),
+            MorphiusError::MismatchedDocument { index, len } => write!(f, "The test has question {}, but the document only has {} questions", index + 1, len),
+            MorphiusError::SetTooLarge { name } => write!(f, "The range of the variable '{}' is too large to list the members of its set", name),
+            MorphiusError::InvalidBounds { name, min, max } => write!(f, "The variable '{}' is declared with the range [{},{}], which has no possible values", name, min, max),
+            MorphiusError::UnknownVariable { name, question } => write!(f, "The variable '{}' is used in question {} but isn't used in the question itself", name, question + 1),
+            MorphiusError::DuplicateDeclaration { name, question, first, second } => write!(f, "The variable '{}' is declared twice in question {}, as '{}' and '{}'", name, question + 1, first, second),
+            MorphiusError::NoAllowedOrder => write!(f, "Every possible question order is forbidden"),
+            MorphiusError::DeadlineExceeded => write!(f, "The deadline passed before the tests were generated"),
+            MorphiusError::UnknownQuestion { id, question } => write!(f, "Question {} shares the variables of '{}', but no earlier question has that id", question + 1, id),
+            MorphiusError::TooComplex { expression, question } => write!(f, "The expression '{}' of question {} is too long or too deeply nested to evaluate", expression, question + 1),
+            MorphiusError::AnswerOutOfRange { question, value, min, max } => write!(f, "The answer to question {} has the value {}, which is outside of its expected range [{},{}]", question + 1, value, min, max),
+            MorphiusError::MissingTable { path, message } => write!(f, "Couldn't read the table '{}': {}", path, message),
+            MorphiusError::MalformedTag { tag, question: Some(question) } => write!(f, "The tag '{}' in question {} isn't closed or doesn't match the tags around it", tag, question + 1),
+            MorphiusError::MalformedTag { tag, question: None } => write!(f, "The tag '{}' outside of the questions isn't closed or doesn't match the tags around it", tag),
+            MorphiusError::InvalidChoice { name, values } => write!(f, "The choice variable '{}' can't be chosen from [{}]", name, values),
+            MorphiusError::NotFinite { expression, question } => write!(f, "The expression '{}' in question {} doesn't have a finite result", expression, question + 1),
+            MorphiusError::DuplicateParts { text, question } => write!(f, "Two parts of question {} both show '{}'", question + 1, text),
+            MorphiusError::RecursiveMacro { name } => write!(f, "The macro '{}' uses itself, so it can't be expanded", name),
+            MorphiusError::Math { expression, message } => write!(f, "Couldn't evaluate '{}': {}", expression, message)
+        }
     }
-    let questions: Vec<Question> = QUESTION.captures_iter(input).map(|cap| process_question(&cap[1], None)).collect();
-    let layout: Vec<String> = QUESTION.split(input).map(String::from).collect();
-    Document{ questions, layout }
 }
 
-///This function takes an input &str in the desired template format and generates a document. The input document must have answers provided for each question.
-///
-/// # Arguments
-///
-/// * `input` - A string slice that holds the template contents for the Document
-///
-/// # Examples
-///
-/// ```
-/// use morphius;
-/// let doc = morphius::process_with_answers("Document Contents with answers");
-/// ```
-pub fn process_with_answers(input: &str) -> Document {
-    lazy_static! {
-        static ref QUESTION: Regex = Regex::new(r"(?s)\|<q>(.*?)</q>\|\s*\|<a>(.*?)</a>\|").unwrap();
-    }
-    let mut questions: Vec<Question> = Vec::new();
-    for cap in QUESTION.captures_iter(input) {
-        questions.push(process_question(&cap[1], Some(process_answer(&cap[2]))));
+impl std::error::Error for MorphiusError {}
+
+impl From<MorphiusError> for io::Error {
+    fn from(error: MorphiusError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
     }
-    let layout: Vec<String> = QUESTION.split(input).map(String::from).collect();
-    Document{ questions, layout }
 }
 
-///This function takes an input Document, the number of tests that you want to generate and optionally the number of question per generated test
-///
-/// # Arguments
-///
-/// * `doc` - A reference to a Document for the template that you want to generate
-/// * `num_results` - The number of tests to generate
-/// * `num_quesitions` - The number of questions per test. Enter None to use all questions in the original order. To include all questions and reorder them, enter `Some(x)` where x is the total number of questions
-///
-/// # Examples
-///
-/// ```
-/// use morphius;
-/// let doc = morphius::process("|<q>Example Question 1</q>||<q>Example Question 2</q>|");
-/// morphius::generate(&doc, 5, Some(2));
-/// ```
-pub fn generate(doc: &Document, num_results: usize, num_questions: Option<usize>) -> Vec<Test> {
-    match num_questions {
-        Some(num_qs) => {
-            let mut rng = rand::thread_rng();
-            let tot_qs_in_doc = doc.questions.len();
-            let num_permutations = cmp::min(num_qs, tot_qs_in_doc);
-            let permutations: Vec<Vec<usize>> = (0..tot_qs_in_doc).permutations(num_permutations).collect();
+#[derive(Clone, Copy)]
+enum Num {
+    Int(i64),
+    Real(f64)
+}
 
-            (0..num_results).map(|_| gen_form(doc, Some(&permutations[rng.gen_range(0..num_permutations)]))).collect()
+impl Num {
+    fn value(self) -> f64 {
+        match self {
+            Num::Int(num) => num as f64,
+            Num::Real(num) => num
         }
-        None => (0..num_results).map(|_| gen_form(doc, None)).collect()
     }
 }
 
-fn gen_form(doc: &Document, order: Option<&Vec<usize>>) -> Test {
-    let mut questions: Vec<String> = Vec::new();
-    let mut answers: Vec<String> = Vec::new();
-    match order {
-        Some(ord) => {
-            for i in ord.iter() {
-                let (content, answer) = gen_question_text(&doc.questions[*i]);
-                questions.push(content);
-                answers.push(answer);
-            }
-        },
-        None => {
-            for q in doc.questions.iter() {
-                let (content, answer) = gen_question_text(&q);
-                questions.push(content);
-                answers.push(answer);
-                ()
-            }
-        }
-    };
-    Test { content: doc.layout.iter().interleave(&questions).join(""), answers: doc.layout.iter().interleave(&answers).join("") }
+//Everything needed to evaluate the expressions of a question: its index, the variable values, the values of the random function calls and the options
+struct Eval<'a> {
+    question: usize,
+    scope: &'a HashMap<&'a str,Num>,
+    draws: HashMap<(String, usize), f64>,
+    picks: HashMap<String, String>,
+    options: &'a GenerateOptions,
+    //Symbolic expressions are only evaluated in answers
+    in_answer: bool,
+    //This maps the columns of the chosen rows of tables that aren't numbers to their text
+    texts: HashMap<&'a str, &'a str>
 }
 
-fn gen_question_text(question: &Question) -> (String, String) {
-    let mut rng = rand::thread_rng();
-    let mut scope:HashMap<&str,Num> = HashMap::new();
-    for var in question.vars.iter() {
-        if var.num_type == "int" {
-            scope.insert(&var.name[..], Num{ whole: rng.gen_range(var.min.parse::<i64>().unwrap()..(var.max.parse::<i64>().unwrap()+1)), frac: None});
-        } else {
-            let whole = rng.gen_range(var.min.parse::<i64>().unwrap()..var.max.parse::<i64>().unwrap());
-            let frac: i64 = rng.gen_range(0..1000);
-            scope.insert(&var.name[..], Num{ whole, frac: Some(frac) });
-        }
-    }
+//Values chosen for variables instead of sampling them, keyed by the index of their question, or None for global variables
+type Assignment<'a> = HashMap<(Option<usize>, &'a str), Num>;
 
+//Identifiers can use letters from any alphabet, such as Greek letters
+const IDENT: &str = r"\p{Alphabetic}\w*";
 
-    let content = question.layout.iter().interleave(&question.expressions.iter().map(|exp| gen_expression_text(exp, &scope)).collect::<Vec<String>>()).join("");
+const NUMBER: &str = r"-?[0-9]+(?:\.[0-9]+)?(?:[eE][-+]?[0-9]+)?";
 
-    let answer: String = match &question.answer {
-        Some(answer) => answer.layout.iter().interleave(&answer.expressions.iter().map(|exp| gen_expression_text(exp, &scope)).collect::<Vec<String>>()).join(""),
-        None => String::from("No Answers Provided")
-    };
+//A bound is a number or an expression using other variables, such as `x-5`
+const BOUND: &str = r"[^\[\],]*[^\[\],\s]";
 
-    (content, answer)
-}
+//This is how many times a balanced selection of questions is shuffled again when its order is forbidden
+const ORDER_RETRIES: usize = 100;
 
-fn gen_expression_text(expression: &Expression, scope: &HashMap<&str,Num>) -> String {
-    let expr = expression.expression.iter().map(|exp_cmp| {
-        match exp_cmp {
-            ExpComp::Var(var_name) => {
-                let num = scope.get(&var_name[..]).unwrap();
-                match &num.frac {
-                    None => num.whole.to_string(),
-                    Some(frac) => (num.whole as f64 + (*frac as f64 / 1000f64)).to_string()
-                }
-            }
-            ExpComp::Other(text) => text.clone()
-        }
-    })
-    .join("");
-    match mexprp::eval::<f64>(&expr).unwrap() {
-        mexprp::Answer::Single(num) => {
-            let rounded = format!("{:.3}", num);
-            let normal = num.to_string();
-            if normal.chars().count() > rounded.chars().count()  {
-                rounded
-            } else {
-                normal
-            }
-        }
-        mexprp::Answer::Multiple(_) => panic!("Unsupported math")
-    }
-}
+//This is how many tests are tried for each distinct test that is still needed, and how many in a row can repeat earlier tests, before giving up
+const DISTINCT_RETRIES: usize = 100;
 
-fn process_question(question: &str, answer: Option<Answer>) -> Question {
-    lazy_static! {
-        static ref VAR: Regex = Regex::new(r"\|<v>([[:alpha:]][[:word:]]*):\s*([[:alpha:]]*)\s*=\s*\[(-?[0-9]+),(-?[0-9]+)\]</v>\|").unwrap();
-    }
-    let mut content = get_content(&VAR.split(question).join(""));
-    for cap in VAR.captures_iter(question) {
-        content.vars.remove(&Var{ name: String::from(&cap[1]), num_type: String::from("int"), min: String::from("0"), max: String::from("99") });
-        content.vars.insert(Var{ name: String::from(&cap[1]), num_type: String::from(&cap[2]), min: String::from(&cap[3]), max: String::from(&cap[4])});
-    }
-    Question { vars: content.vars, expressions: content.expressions, layout: content.layout, answer }
+//These are the default limits on the expressions that are evaluated, which keep mexprp from overflowing the stack
+const MAX_EXPRESSION_LENGTH: usize = 10_000;
+const MAX_EXPRESSION_DEPTH: usize = 100;
+
+//This is how many times the variables of a question are sampled again when the range of a variable that depends on them is empty
+const DEPENDENT_RETRIES: usize = 100;
+
+///A Sampler gives the value of a variable instead of sampling it from its declaration, such as to draw it from a distribution of
+///real data. It is called with the name of the variable and the rng of the test
+pub type Sampler = fn(&str, &mut dyn RngCore) -> f64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///This is an enum used to choose where the randomness of generated tests comes from
+pub enum RandomSource {
+    ///This uses the seed of the document if it has one, or else a generator seeded once from the operating system. It is fast and
+    ///its tests can't be predicted without the seed, but the same seed always gives the same tests
+    Document,
+    ///This uses the thread-local generator of the rand crate, which ignores the seed of the document and reseeds itself from the operating system over time
+    Thread,
+    ///This draws every random choice from the secure random number generator of the operating system, which ignores the seed of the
+    ///document and is the hardest to predict, such as for exams where knowing one test could not reveal the others. It is the slowest
+    Os,
+    ///This uses a generator made from this seed, so the same tests are generated every time. Anyone who knows the seed can generate them too
+    Seed(u64)
 }
 
-fn process_answer(answer: &str) -> Answer {
-    let content = get_content(answer);
-    Answer { expressions: content.expressions, layout: content.layout }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///This is an enum used to choose how numbers are rounded to the decimal places they are shown with
+pub enum Rounding {
+    ///This rounds ties away from zero, so 0.0005 becomes 0.001 at 3 decimal places
+    HalfUp,
+    ///This rounds ties to the even digit, so 0.0005 becomes 0.000 and 0.0015 becomes 0.002 at 3 decimal places
+    HalfEven,
+    ///This drops the extra digits, so 0.0019 becomes 0.001 and -0.0019 becomes -0.001 at 3 decimal places
+    Truncate,
+    ///This rounds up towards positive infinity, so 0.0011 becomes 0.002 and -0.0019 becomes -0.001 at 3 decimal places
+    Ceiling
 }
 
-fn get_content(text: &str) -> Content {
-    lazy_static! {
-        static ref EXP: Regex = Regex::new(r"\|<e>(.*?)</e>\|").unwrap();
-    }
-    let mut vars: HashSet<Var> = HashSet::new();
-    let expressions: Vec<Expression> = EXP.captures_iter(text).map(|cap| process_expression(&cap[1], &mut vars)).collect();
-    let layout: Vec<String> = EXP.split(text).map(String::from).collect();
-    Content{ vars, expressions, layout }
+#[derive(Clone)]
+///GenerateOptions holds the settings used when generating tests
+pub struct GenerateOptions {
+    ///This is the list of active flags. Questions with flags are only used if one of their flags is in this list
+    pub flags: Vec<String>,
+    ///If this is true, the questions for each test are chosen so that every question is used about as often as the others across the whole batch
+    pub balanced: bool,
+    ///If this is true, the correct part of a multiple choice question is given each letter about as often as the others across the whole batch,
+    ///and the questions of a test don't all have it at the same letter
+    pub rotate_correct: bool,
+    ///This is the symbol placed in front of currency expressions
+    pub currency_symbol: String,
+    ///This is the separator placed between each group of three digits in currency expressions
+    pub thousands_separator: String,
+    ///This is the separator placed before the cents in currency expressions
+    pub decimal_separator: String,
+    ///If this is set, expressions using a real variable always show this many decimal places, so a whole number is shown as `5.0` instead of `5`
+    pub real_decimals: Option<usize>,
+    ///This is the text placed in the answer key for a question without an answer. If this is None, nothing is placed in the answer key for it
+    pub missing_answer: Option<String>,
+    ///If this is set, numbers are rounded to at most this many decimal places, so 0 shows every number as a whole number
+    pub max_decimals: Option<usize>,
+    ///This is a list of question orders that are never used, where each order is a list of question indices
+    pub forbidden_orders: Vec<Vec<usize>>,
+    ///If this is true, the questions of each test are put in order from easiest to hardest by their difficulty instead of being
+    ///shuffled, such as for a practice worksheet. Questions without a difficulty come after the others, in their original order
+    pub by_difficulty: bool,
+    ///This is the list of requested tags. If it isn't empty, only questions with one of these tags are used
+    pub tags: Vec<String>,
+    ///If this is true, the trigonometric functions of every expression use degrees instead of radians
+    pub degrees: bool,
+    ///This is the largest number of characters an expression can have once its variables are filled in
+    pub max_expression_length: usize,
+    ///This is the deepest an expression can nest parentheses. Very deeply nested expressions would overflow the stack while being evaluated
+    pub max_expression_depth: usize,
+    ///If this is set, the answer to each question is placed right after it in the content of the test, such as for a self-check worksheet.
+    ///The answer replaces `{answer}` in this text, such as ` [Answer: {answer}]`
+    pub inline_answers: Option<String>,
+    ///If this is true, each question is followed by a marker like `[1]` and the answers are listed as footnotes at the end of the content of the test
+    pub footnote_answers: bool,
+    ///If this is set, every line ending in the content and answers of a test, whether `\n` or `\r\n`, is replaced by it, such as `\r\n`
+    ///for printing on Windows. Blank lines are kept
+    pub line_ending: Option<String>,
+    ///If this is set, generating stops with an error once this time has passed, such as to keep a slow template from holding up a request
+    pub deadline: Option<Instant>,
+    ///This is where the randomness of the tests comes from when the function generating them doesn't take an rng
+    pub random_source: RandomSource,
+    ///This maps the names of variables to the Samplers that give their values instead of their declarations. The value of an int variable
+    ///is rounded, and the value for a linked variable is its offset from its base
+    pub samplers: HashMap<String, Sampler>,
+    ///If this is set, numbers are rounded this way to the decimal places they are shown with. Ties are decided by the digits of the
+    ///number as it is written, so 0.0005 is a tie at 3 decimal places even though it can't be stored exactly
+    pub rounding: Option<Rounding>,
+    ///This is the text shown for an expression comparing two values, such as `a > b`, when the comparison is true
+    pub true_text: String,
+    ///This is the text shown for an expression comparing two values when the comparison is false
+    pub false_text: String
 }
 
-fn process_expression(expression: &str, vars: &mut HashSet<Var>) -> Expression {
-    lazy_static! {
-        static ref VAR: Regex = Regex::new(r"[[:alpha:]][[:word:]]*").unwrap();
-    }
-    let mut vars_list: Vec<ExpComp> = Vec::new();
-    for cap in VAR.captures_iter(expression) {
-        vars.insert(Var{ name: String::from(&cap[0]), num_type: String::from("int"), min: String::from("0"), max: String::from("99") });
-        vars_list.push(ExpComp::Var(String::from(&cap[0])));
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        GenerateOptions {
+            flags: Vec::new(),
+            balanced: false,
+            rotate_correct: false,
+            currency_symbol: String::from("$"),
+            thousands_separator: String::from(","),
+            decimal_separator: String::from("."),
+            real_decimals: None,
+            missing_answer: Some(String::from("No Answers Provided")),
+            max_decimals: None,
+            forbidden_orders: Vec::new(),
+            by_difficulty: false,
+            tags: Vec::new(),
+            degrees: false,
+            max_expression_length: MAX_EXPRESSION_LENGTH,
+            max_expression_depth: MAX_EXPRESSION_DEPTH,
+            inline_answers: None,
+            footnote_answers: false,
+            line_ending: None,
+            deadline: None,
+            random_source: RandomSource::Document,
+            samplers: HashMap::new(),
+            rounding: None,
+            true_text: String::from("True"),
+            false_text: String::from("False")
+        }
     }
-    Expression { expression: VAR.split(expression).map(|text| ExpComp::Other(String::from(text))).interleave(vars_list).collect() }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+const PAGE_BREAK: &str = "\u{000C}";
 
-    const FORM1: &str = "Beginning|<q>Question 1</q>|Middle|<q>Question 2</q>|End";
-    const FORM2: &str = "|<q>1</q>|Middle 1|<q>2</q>|Middle 2|<q>3</q>|";
-    const FORM3: &str = "|<q>1</q>||<q>2</q>||<q>3</q>|";
-    const FORM4: &str = "|<q>1</q>||<a>3</a>|";
-    const FORM5: &str = "|<q>1</q>|\n\n          \t\t|<a>3</a>|";
+const CUT_LINE: &str = "- - - - - - - - - - cut here - - - - - - - - - -";
 
+const PART_SLOT: &str = "\u{0}";
 
-    #[test]
-    fn test_process_1() {
-        let doc = process(FORM1);
-        assert_eq!(doc.layout[0], "Beginning");
-        assert_eq!(doc.layout[1], "Middle");
-        assert_eq!(doc.layout[2], "End");
-        assert_eq!(gen_question_text(&doc.questions[0]).0, "Question 1");
-        assert_eq!(gen_question_text(&doc.questions[1]).0, "Question 2");
-    }
+//This is replaced by the points of the question it is in
+const POINTS: &str = "|<points/>|";
 
-    #[test]
-    fn test_process_2() {
-        let doc = process(FORM2);
-        assert_eq!(doc.layout, vec!["","Middle 1", "Middle 2",""]);
-        assert_eq!(gen_question_text(&doc.questions[0]).0, "1");
-        assert_eq!(gen_question_text(&doc.questions[1]).0, "2");
-        assert_eq!(gen_question_text(&doc.questions[2]).0, "3");
-    }
+//This is replaced by the values of the variables of the question it is in
+const GIVEN: &str = "|<given/>|";
 
-    #[test]
-    fn test_gen_form_original_order() {
-        let doc = process(FORM1);
-        assert_eq!(gen_form(&doc, None).content, "BeginningQuestion 1MiddleQuestion 2End");
-    }
+//The special sets a variable can be chosen from
+const SETS: [&str; 5] = ["prime", "square", "cube", "even", "odd"];
+const FUNCTIONS: [&str; 18] = ["sin", "cos", "tan", "asin", "acos", "atan", "atant", "max", "min", "sqrt", "nrt", "abs", "floor", "round", "log", "rand", "randint", "mod"];
 
-    #[test]
-    fn test_gen_form_different_order() {
-        let doc = process(FORM2);
-        assert_eq!(gen_form(&doc, Some(&vec![1,2,0])).content, "2Middle 13Middle 21");
-        assert_eq!(gen_form(&doc, Some(&vec![2,1,0])).content, "3Middle 12Middle 21");
-        assert_eq!(gen_form(&doc, Some(&vec![0,1,2])).content, "1Middle 12Middle 23");
+impl Document {
+    ///This function returns the layout of the Document with a `{{question}}` placeholder wherever a question would be placed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("Intro|<q>Example Question</q>|Outro");
+    /// assert_eq!(doc.skeleton(), "Intro{{question}}Outro");
+    /// ```
+    pub fn skeleton(&self) -> String {
+        self.layout.join("{{question}}")
     }
 
-    #[test]
-    fn test_generate_no_reorder() {
-        let doc = process(FORM3);
-        assert_eq!(generate(&doc, 2, None)[0].content, "123");
-        assert_eq!(generate(&doc, 2, None)[1].content, "123");
+    ///This function returns the value of a metadata key of the Document, or None if it isn't set
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A string slice that holds the name of the metadata key
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("|<meta>title: Algebra Quiz</meta>||<q>Question</q>|");
+    /// assert_eq!(doc.meta("title"), Some("Algebra Quiz"));
+    /// assert_eq!(doc.meta("author"), None);
+    /// ```
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(|value| &value[..])
     }
 
-    #[test]
-    fn test_generate_reorder() {
-        let doc = process(FORM3);
-        let results = generate(&doc, 3, Some(3));
-        for result in results {
-            assert!(result.content.contains("1") && result.content.contains("2") && result.content.contains("3"));
-        }
-    }
+    ///This function counts the questions that use each variable name, which helps to find names used in several questions that could
+    ///be made global or that weren't meant to be the same. Global variables are only counted for the questions that declare their own
+    ///version of them
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("|<q>|<e>x+y</e>|</q>||<q>|<e>x*2</e>|</q>|");
+    /// let usage = doc.variable_usage();
+    /// assert_eq!(usage["x"], 2);
+    /// assert_eq!(usage["y"], 1);
+    /// ```
+    pub fn variable_usage(&self) -> HashMap<String, usize> {
 
-    #[test]
-    fn test_generate_skip_questions() {
-        let doc = process(FORM3);
-        let results = generate(&doc, 3, Some(1));
-        for result in results {
-            assert!(!result.content.contains("1") || !result.content.contains("2") || !result.content.contains("3"));
+        let mut usage: HashMap<String, usize> = HashMap::new();
+        for var in self.questions.iter().flat_map(|question| question.vars.iter()) {
+            *usage.entry(var.name.clone()).or_insert(0) += 1;
         }
+        usage
     }
 
-    #[test]
-    fn test_generate_var() {
-        let doc = process("|<q>|<e>a</e>|</q>|");
-        let result = generate(&doc, 3, Some(1));
-        let num_re = Regex::new(r"^[[:digit:]]+$").unwrap();
-        assert!(num_re.is_match(&result[0].content));
+    ///This function turns the Document back into a template that processes into an equivalent Document. Global variable declarations
+    ///and metadata are placed at the start, hints are placed at the end of their question and variables without a declaration
+    ///are left undeclared. If any question has an answer, the template should be processed with process_with_answers
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("Quiz|<q if=honors>|<v>a: int = [1,5]</v>|What is |<e>a+b</e>|?</q>|");
+    /// assert_eq!(doc.to_template(), "Quiz|<q if=honors>|<v>a: int = [1,5]</v>|What is |<e>a+b</e>|?</q>|");
+    /// ```
+    pub fn to_template(&self) -> String {
+        let mut header = String::new();
+        if !self.metadata.is_empty() {
+            header += &format!("|<meta>{}</meta>|", self.metadata.iter().sorted().map(|(key, value)| format!("{}: {}", key, value)).join("\n"));
+        }
+        if let Some(seed) = self.seed {
+            header += &format!("|<seed>{}</seed>|", seed);
+        }
+        for (name, body) in self.macros.iter().sorted() {
+            header += &format!("|<macro {}>{}</macro>|", name, body);
+        }
+        header += &declarations_template(self.globals.iter());
+        let questions: Vec<String> = self.questions.iter().map(question_template).collect();
+        header + &self.layout.iter().interleave(questions.iter()).join("")
     }
 
-    #[test]
-    fn test_generate_var_math() {
-        let doc = process("|<q>|<e>(a+b)-c</e>|</q>|");
-        let result = generate(&doc, 3, Some(1));
-        let num_re = Regex::new(r"^-?[[:digit:]]+$").unwrap();
-        println!("{}", result[0].content);
-        assert!(num_re.is_match(&result[0].content));
+    ///This function replaces each `{{key}}` in a template with the value of that metadata key, which is useful for headers such as
+    ///the header template passed to `paginate`. Placeholders that aren't metadata keys are left as they are
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - A string slice that holds the template to fill in
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("|<meta>title: Algebra Quiz</meta>||<q>Question</q>|");
+    /// let tests = morphius::generate(&doc, 1, None);
+    /// let header = doc.fill_metadata("{{title}} - Test {{index}}");
+    /// assert_eq!(morphius::paginate(&tests, &header), "Algebra Quiz - Test 1\nQuestion");
+    /// ```
+    pub fn fill_metadata(&self, template: &str) -> String {
+        self.metadata.iter().fold(String::from(template), |text, (key, value)| text.replace(&format!("{{{{{}}}}}", key), value))
     }
 
-    #[test]
-    fn test_process_with_anwer() {
-        let doc1 = process_with_answers(FORM4);
-        match doc1.questions[0].answer {
-            Some(_) => assert!(true),
-            None => assert!(false)
-        }
-
-        let doc2 = process(FORM4);
-        match doc2.questions[0].answer {
-            Some(_) => assert!(false),
-            None => assert!(true)
+    ///This function estimates how many expressions generating tests from the Document will evaluate, such as to show the progress
+    ///of a large batch. When only some of the questions are used in each test, the estimate uses the average number of expressions
+    ///of a question, so it is approximate
+    ///
+    /// # Arguments
+    ///
+    /// * `num_results` - The number of tests that will be generated
+    /// * `num_questions` - The number of questions per test, like in generate
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process_with_answers("|<q>|<e>a</e>| + |<e>b</e>|</q>||<a>|<e>a+b</e>|</a>||<q>|<e>c</e>|</q>||<a>|<e>c</e>|</a>|");
+    /// assert_eq!(doc.estimate_work(10, None), 50);
+    /// assert_eq!(doc.estimate_work(10, Some(1)), 25);
+    /// ```
+    pub fn estimate_work(&self, num_results: usize, num_questions: Option<usize>) -> usize {
+        let total: usize = self.questions.iter().map(|question| question_expressions(question).len()).sum();
+        let num_selected = cmp::min(num_questions.unwrap_or(self.questions.len()), self.questions.len());
+        if num_selected == 0 {
+            return 0;
         }
+        //The questions of a test are chosen at random, so on average each one has the average number of expressions
+        (total * num_selected * num_results).div_ceil(self.questions.len())
     }
 
-    #[test]
-    fn test_process_with_anwer_newlines_are_ok() {
-        let doc = process_with_answers(FORM5);
-        match doc.questions[0].answer {
-            Some(_) => assert!(true),
-            None => assert!(false)
-        }
+    ///This function returns the indices of the questions that are the same on every test because they have no variables and no
+    ///expressions in their content, parts, hints or answer, such as to check a template for questions that were meant to be randomized.
+    ///Questions with several parts are left out since their parts are shuffled
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("|<q>What is 2 + 2?</q>||<q>What is |<e>a+2</e>|?</q>||<q>Name a prime</q>|");
+    /// assert_eq!(doc.static_questions(), vec![0, 2]);
+    /// ```
+    pub fn static_questions(&self) -> Vec<usize> {
+        self.questions.iter().enumerate().filter(|(_, question)| {
+            question.vars.is_empty() && question_expressions(question).is_empty() && question.parts.len() < 2
+        }).map(|(index, _)| index).collect()
     }
 
-    #[test]
-    fn test_answer_generated_correctly() {
-        let doc = process_with_answers("|<q>|<e>a</e>|</q>||<a>|<e>a</e>|</a>|");
-        for result in generate(&doc, 3, Some(1)) {
-            assert_eq!(result.content, result.answers);
+    ///This function describes how the Document was parsed, which is useful for finding out why a template doesn't behave as
+    ///expected. It lists the layout, global variables and metadata of the Document and, for each question, its variables with their
+    ///declarations, its expressions with the variables and functions found in them, its layout, parts, hints and answer
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("Quiz: |<q>What is |<e>sqrt(a)</e>|?</q>|");
+    /// let dump = doc.debug_dump();
+    /// assert!(dump.contains("a: int = [0,99]"));
+    /// assert!(dump.contains("sqrt(a) [func sqrt, other \"(\", var a, other \")\"]"));
+    /// ```
+    pub fn debug_dump(&self) -> String {
+        let mut out = String::from("Document\n");
+        writeln!(out, "  layout: {:?}", self.layout).unwrap();
+        if let Some(seed) = self.seed {
+            writeln!(out, "  seed: {}", seed).unwrap();
         }
-    }
-
+        dump_vars(&mut out, "  ", "globals", &self.globals);
+        if !self.metadata.is_empty() {
+            writeln!(out, "  metadata:").unwrap();
+            for (key, value) in self.metadata.iter().sorted() {
+                writeln!(out, "    {}: {:?}", key, value).unwrap();
+            }
+        }
+        for (name, body) in self.macros.iter().sorted() {
+            writeln!(out, "  macro {}: {}", name, body).unwrap();
+        }
+        for (index, question) in self.questions.iter().enumerate() {
+            writeln!(out, "  question {}:", index + 1).unwrap();
+            if let Some(id) = &question.id {
+                writeln!(out, "    id: {}", id).unwrap();
+            }
+            for (label, list) in [("flags", &question.flags), ("shares", &question.shares), ("tags", &question.tags)] {
+                if !list.is_empty() {
+                    writeln!(out, "    {}: {}", label, list.join(", ")).unwrap();
+                }
+            }
+            if let Some(points) = question.points {
+                writeln!(out, "    points: {}", format_number(points)).unwrap();
+            }
+            if let Some(attempts) = question.attempts {
+                writeln!(out, "    attempts: {}", attempts).unwrap();
+            }
+            if question.summary {
+                writeln!(out, "    summary").unwrap();
+            }
+            if question.required {
+                writeln!(out, "    required").unwrap();
+            }
+            if let Some(difficulty) = question.difficulty {
+                writeln!(out, "    difficulty: {}", difficulty).unwrap();
+            }
+            dump_vars(&mut out, "    ", "vars", &question.vars);
+            for partition in question.partitions.iter() {
+                writeln!(out, "    partition: [{}] = {}", partition.names.join(", "), partition.total).unwrap();
+            }
+            for choice in question.choices.iter() {
+                writeln!(out, "    choice: {} = [{}]", choice.name, choice.options.iter().map(|(value, weight)| format!("{}:{}", value, format_number(*weight))).join(", ")).unwrap();
+            }
+            for table in question.tables.iter() {
+                writeln!(out, "    table: {} from {:?} with {} rows [{}]", table.name, table.path, table.rows.len(), table.columns.join(", ")).unwrap();
+            }
+            dump_content(&mut out, "    ", &question.layout, &question.expressions);
+            for part in question.parts.iter() {
+                writeln!(out, "    part:{}{}", if part.correct { " correct" } else { "" }, part.points.map_or(String::new(), |points| format!(" points: {}", format_number(points)))).unwrap();
+                dump_content(&mut out, "      ", &part.layout, &part.expressions);
+            }
+            for hint in question.hints.iter() {
+                writeln!(out, "    hint:{}", if hint.shares.is_empty() { String::new() } else { format!(" shares {}", hint.shares.join(", ")) }).unwrap();
+                dump_content(&mut out, "      ", &hint.layout, &hint.expressions);
+            }
+            if let Some(answer) = &question.answer {
+                writeln!(out, "    answer:").unwrap();
+                if let Some((min, max)) = answer.range {
+                    writeln!(out, "      range: [{},{}]", min, max).unwrap();
+                }
+                if let Some(tolerance) = answer.tolerance {
+                    writeln!(out, "      tolerance: {}", format_number(tolerance)).unwrap();
+                }
+                if let Some(when) = &answer.when {
+                    writeln!(out, "      when: {}", when).unwrap();
+                }
+                dump_content(&mut out, "      ", &answer.layout, &answer.expressions);
+                for part in answer.parts.iter() {
+                    writeln!(out, "      part:").unwrap();
+                    dump_content(&mut out, "        ", &part.layout, &part.expressions);
+                }
+                for alternative in answer.alternatives.iter() {
+                    writeln!(out, "      alternative:{}", alternative.when.as_ref().map_or(String::new(), |when| format!(" when {}", when))).unwrap();
+                    dump_content(&mut out, "        ", &alternative.layout, &alternative.expressions);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn dump_vars(out: &mut String, indent: &str, label: &str, vars: &HashSet<Var>) {
+    if vars.is_empty() {
+        return;
+    }
+    writeln!(out, "{}{}:", indent, label).unwrap();
+    for var in vars.iter().sorted_by(|a, b| a.name.cmp(&b.name)) {
+        writeln!(out, "{}  {}", indent, declaration_template(var)).unwrap();
+    }
+}
+
+fn dump_content(out: &mut String, indent: &str, layout: &[String], expressions: &[Expression]) {
+    //Parts are shown where they are placed in the layout, like questions in the skeleton of a Document
+    writeln!(out, "{}layout: {:?}", indent, layout.iter().map(|text| text.replace(PART_SLOT, "{{part}}")).collect::<Vec<String>>()).unwrap();
+    if expressions.is_empty() {
+        return;
+    }
+    writeln!(out, "{}expressions:", indent).unwrap();
+    for expression in expressions.iter() {
+        let comps = expression.expression.iter().filter(|exp_comp| !matches!(exp_comp, ExpComp::Other(text) if text.is_empty())).map(|exp_comp| match exp_comp {
+            ExpComp::Var(name) => format!("var {}", name),
+            ExpComp::Func(name) => format!("func {}", name),
+            ExpComp::Other(text) => format!("other {:?}", text),
+            ExpComp::Literal(text) => format!("literal {:?}", text)
+        }).join(", ");
+        writeln!(out, "{}  {} [{}] {:?}", indent, expression_source(expression), comps, expression.mode).unwrap();
+    }
+}
+
+impl Test {
+    ///This function returns the generated text of a named expression, or None if the question doesn't have an expression with that name
+    ///
+    /// # Arguments
+    ///
+    /// * `question_pos` - The position of the question in the Test, starting from 0
+    /// * `name` - A string slice that holds the name of the expression
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process_with_answers("|<q>|<v>a: int = [2,2]</v>|Double |<e>a</e>|</q>||<a>|<e name=total>a*2</e>|</a>|");
+    /// let tests = morphius::generate(&doc, 1, None);
+    /// assert_eq!(tests[0].expression_value(0, "total"), Some("4"));
+    /// assert_eq!(tests[0].expression_value(1, "total"), None);
+    /// ```
+    pub fn expression_value(&self, question_pos: usize, name: &str) -> Option<&str> {
+        self.questions.get(question_pos)?.named.get(name).map(|value| &value[..])
+    }
+
+    ///This function returns the hints for the Test, numbered by the position of their question. Questions without hints are skipped
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("|<q>First</q>||<q>Second|<hint>Start with |<e>1+1</e>|</hint>|</q>|");
+    /// let tests = morphius::generate(&doc, 1, None);
+    /// assert_eq!(tests[0].content, "FirstSecond");
+    /// assert_eq!(tests[0].hints(), "2. Start with 2");
+    /// ```
+    pub fn hints(&self) -> String {
+        self.questions.iter().enumerate().filter(|(_, q)| !q.hint.is_empty()).map(|(i, q)| format!("{}. {}", i + 1, q.hint)).join("\n")
+    }
+
+    ///This function returns the letters of the correct parts of the multiple choice questions of the Test for a bubble sheet scanner,
+    ///numbered by the position of their question, such as `1-B 2-D 3-A`. Questions without a correct part are skipped
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("|<q>Capital of France |<part correct>Paris</part>| |<part>Rome</part>|</q>|\n|<q>Name</q>|");
+    /// let test = morphius::generate(&doc, 1, None).remove(0);
+    /// let letter = if test.content.contains("a) Paris") { 'A' } else { 'B' };
+    /// assert_eq!(test.bubble_key(), format!("1-{}", letter));
+    /// ```
+    pub fn bubble_key(&self) -> String {
+        self.questions.iter().enumerate().filter_map(|(i, q)| q.correct.map(|label| format!("{}-{}", i + 1, label.to_ascii_uppercase()))).join(" ")
+    }
+
+    ///This function returns the total number of points of the questions in the Test. Questions without points count as 0
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("|<q points=5>First (|<points/>| points)</q>||<q points=2.5>Second</q>||<q>Bonus</q>|");
+    /// let tests = morphius::generate(&doc, 1, Some(3));
+    /// assert_eq!(tests[0].total_points(), 7.5);
+    /// assert!(tests[0].content.contains("First (5 points)"));
+    /// ```
+    pub fn total_points(&self) -> f64 {
+        self.questions.iter().filter_map(|question| question.points).sum()
+    }
+
+    ///This function lists what an automatic grader needs for each question of the Test, in the order they appear: its answer, the
+    ///tolerance given with `|<a tolerance=0.01>`, the other acceptable forms of the answer and its points. Questions without an answer are left out
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process_with_answers("|<q points=2>Half?</q>||<a tolerance=0.01>0.5</a>||<a>1/2</a>|");
+    /// let grades = morphius::generate(&doc, 1, None).remove(0).grading_spec();
+    /// assert_eq!((&grades[0].answer[..], grades[0].tolerance, grades[0].points), ("0.5", Some(0.01), Some(2.0)));
+    /// assert_eq!(grades[0].alternatives, vec![String::from("1/2")]);
+    /// ```
+    pub fn grading_spec(&self) -> Vec<QuestionGrade> {
+        self.questions.iter().filter(|question| !question.forms.is_empty()).map(|question| QuestionGrade {
+            index: question.index,
+            answer: question.forms[0].clone(),
+            tolerance: question.tolerance,
+            alternatives: question.forms[1..].to_vec(),
+            points: question.points
+        }).collect()
+    }
+
+    ///This function exports the Test as JSON, such as to import it into a learning management system. Each question has its index
+    ///in the Document, its content, its answer, and its points and attempts, which are `null` if they weren't provided
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process_with_answers("|<q points=2 attempts=3>1 + 1?</q>||<a>2</a>|");
+    /// let tests = morphius::generate(&doc, 1, None);
+    /// assert_eq!(tests[0].to_json(), r#"{"content":"1 + 1?","answers":"2","questions":[{"index":0,"content":"1 + 1?","answer":"2","points":2,"attempts":3}]}"#);
+    /// ```
+    pub fn to_json(&self) -> String {
+        let questions = self.questions.iter().map(|question| {
+            format!("{{\"index\":{},\"content\":{},\"answer\":{},\"points\":{},\"attempts\":{}}}", question.index, json_string(&question.content), json_string(&question.answer),
+                question.points.map_or(String::from("null"), |points| points.to_string()), question.attempts.map_or(String::from("null"), |attempts| attempts.to_string()))
+        }).join(",");
+        format!("{{\"content\":{},\"answers\":{},\"questions\":[{}]}}", json_string(&self.content), json_string(&self.answers), questions)
+    }
+
+    ///This function generates one question of the Test again with new values, such as when a teacher doesn't like the values it was
+    ///given, and updates the content and answers of the Test to match. The other questions keep their content, and the rerolled question
+    ///keeps the values of the global variables, of its cycle variables and of the questions it shares variables with. The counts of the
+    ///values of choices in the globals of the Test are updated, but summary questions keep their content until they are rerolled too.
+    ///It returns an error if there is no question at pos or the Test has questions that aren't in the Document
+    ///
+    /// # Arguments
+    ///
+    /// * `doc` - A reference to the Document the Test was generated from
+    /// * `pos` - The position of the question in the Test, starting from 0
+    /// * `seed` - The seed to generate the question from, or None to use a random one
+    /// * `options` - The GenerateOptions the Test was generated with
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process_with_answers("|<q>|<v>a: int = [1,1000]</v>|Double |<e>a</e>|</q>||<a>|<e>a*2</e>|</a>|\n|<q>Five</q>||<a>5</a>|");
+    /// let mut test = morphius::generate(&doc, 1, None).remove(0);
+    /// test.reroll_question(&doc, 0, Some(7), &morphius::GenerateOptions::default()).unwrap();
+    /// let a = test.questions[0].values["a"];
+    /// assert_eq!(test.content, format!("Double {}\nFive", a));
+    /// assert_eq!(test.answers, format!("{}\n5", a * 2.0));
+    /// ```
+    pub fn reroll_question(&mut self, doc: &Document, pos: usize, seed: Option<u64>, options: &GenerateOptions) -> Result<(), MorphiusError> {
+        let index = self.questions.get(pos).ok_or(MorphiusError::InvalidPosition { pos, len: self.questions.len() })?.index;
+        //Every question is checked since the whole Test is put together again
+        if let Some(test_question) = self.questions.iter().find(|test_question| test_question.index >= doc.questions.len()) {
+            return Err(MorphiusError::MismatchedDocument { index: test_question.index, len: doc.questions.len() });
+        }
+        let question = &doc.questions[index];
+        let known = |vars: &HashSet<Var>, values: &HashMap<String, f64>| -> Vec<(String, Num)> {
+            vars.iter().filter_map(|var| values.get(&var.name).map(|value| {
+                (var.name.clone(), if var.num_type == "int" { Num::Int(value.round() as i64) } else { Num::Real(*value) })
+            })).collect()
+        };
+        let mut values = known(&doc.globals, &self.globals);
+        //A summary question keeps the counts of the test, even if other questions were rerolled since
+        if question.summary {
+            values.extend(count_names(&doc.questions).into_iter().filter_map(|name| self.globals.get(&name).map(|count| (name, Num::Int(*count as i64)))));
+        }
+        //The variables the hints share are given to the whole question, where its own variables take their place
+        for id in question.shares.iter().chain(question.hints.iter().flat_map(|hint| hint.shares.iter())) {
+            let shared = shared_question(doc, index, id)?;
+            if let Some(test_question) = self.questions.iter().find(|test_question| test_question.index == shared) {
+                values.extend(known(&doc.questions[shared].vars, &test_question.values));
+            }
+        }
+        let globals: HashMap<&str, Num> = values.iter().map(|(name, value)| (&name[..], *value)).collect();
+        //A cycle variable gets its value from the position of the Test in its batch, which stays the same
+        let cycles = known(&question.vars, &self.questions[pos].values);
+        let fixed: HashMap<&str, Num> = cycles.iter().filter(|(name, _)| question.vars.iter().any(|var| var.name == *name && var.cycle.is_some())).map(|(name, value)| (&name[..], *value)).collect();
+        let mut rng = match seed {
+            Some(seed) => rand::rngs::StdRng::seed_from_u64(seed),
+            None => rand::rngs::StdRng::from_entropy()
+        };
+        self.questions[pos] = gen_question_text(question, index, &globals, &fixed, options, &mut rng)?;
+        for name in count_names(&doc.questions) {
+            self.globals.insert(name, 0.0);
+        }
+        for test_question in self.questions.iter().filter(|test_question| !doc.questions[test_question.index].summary) {
+            for choice in doc.questions[test_question.index].choices.iter() {
+                let scope = HashMap::from([(&choice.name[..], Num::Real(test_question.values[&choice.name]))]);
+                if let Some((_, name)) = choice_counts(choice).find(|(pos, _)| *pos == chosen_option(choice, &scope)) {
+                    *self.globals.get_mut(&name).unwrap() += 1.0;
+                }
+            }
+        }
+        (self.content, self.answers) = test_text(doc, &self.questions, options);
+        Ok(())
+    }
+}
+
+impl Question {
+    ///This function gives an id for the Question that stays the same when the template is processed again, such as to match up the
+    ///questions of an edited template with the questions of the previous version. It only depends on the declarations, content, parts
+    ///and hints of the Question, so changing its answer, points or other attributes keeps the same id, while changing its text doesn't
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process_with_answers("|<q>|<v>a: int = [1,9]</v>|Double |<e>a</e>|</q>||<a>|<e>a*2</e>|</a>|");
+    /// let edited = morphius::process_with_answers("Quiz\n|<q points=2>|<v>a: int = [1,9]</v>|Double |<e>a</e>|</q>||<a>|<e>2*a</e>|</a>|");
+    /// assert_eq!(doc.questions[0].stable_id(), edited.questions[0].stable_id());
+    /// let changed = morphius::process("|<q>|<v>a: int = [1,9]</v>|Triple |<e>a</e>|</q>|");
+    /// assert_ne!(doc.questions[0].stable_id(), changed.questions[0].stable_id());
+    /// ```
+    pub fn stable_id(&self) -> String {
+        format!("{:016x}", fnv_hash(&question_content_template(self)))
+    }
+
+    ///This function generates the Question on its own with random variable values, such as to preview it. It returns the text of the
+    ///question and the text of its answer, or an error if the Question has a problem, such as an expression that isn't valid math.
+    ///The Question can't use global variables or the variables of a shared question
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process_with_answers("|<q>|<v>a: int = [1,1]</v>|What is |<e>a</e>| + |<e>a</e>|?</q>||<a>|<e>a+a</e>|</a>|");
+    /// assert_eq!(doc.questions[0].try_sample(), Ok((String::from("What is 1 + 1?"), String::from("2"))));
+    /// assert!(morphius::process("|<q>|<e>(a</e>|</q>|").questions[0].try_sample().is_err());
+    /// ```
+    pub fn try_sample(&self) -> Result<(String, String), MorphiusError> {
+        self.sample_with_rng(&mut rand::thread_rng())
+    }
+
+    ///This function generates the Question on its own like try_sample, but the same seed always gives the same values
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed used to choose the random values
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// let doc = morphius::process("|<q>What is |<e>a</e>| + |<e>b</e>|?</q>|");
+    /// assert_eq!(doc.questions[0].try_sample_seeded(7), doc.questions[0].try_sample_seeded(7));
+    /// ```
+    pub fn try_sample_seeded(&self, seed: u64) -> Result<(String, String), MorphiusError> {
+        self.sample_with_rng(&mut rand::rngs::StdRng::seed_from_u64(seed))
+    }
+
+    ///This function is try_sample, but it panics if the Question has a problem
+    pub fn sample(&self) -> (String, String) {
+        self.try_sample().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    ///This function is try_sample_seeded, but it panics if the Question has a problem
+    pub fn sample_seeded(&self, seed: u64) -> (String, String) {
+        self.try_sample_seeded(seed).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn sample_with_rng<R: Rng>(&self, rng: &mut R) -> Result<(String, String), MorphiusError> {
+        let question = gen_question_text(self, 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), rng)?;
+        Ok((question.content, question.answer))
+    }
+
+    ///This function generates the answer to the Question using the provided variable values instead of random ones. The value
+    ///of a table is the index of its row, and the value of a choice is the index of its option, so their text is shown like in a test.
+    ///Random calls in the answer are given the same values every time
+    ///
+    /// # Arguments
+    ///
+    /// * `assignment` - A map from variable names to the value to use for each variable in the answer
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morphius;
+    /// use std::collections::HashMap;
+    /// let doc = morphius::process_with_answers("|<q>|<e>a</e>| + |<e>b</e>|</q>||<a>|<e>a+b</e>|</a>|");
+    /// let assignment = HashMap::from([(String::from("a"), 1.0), (String::from("b"), 2.5)]);
+    /// assert_eq!(doc.questions[0].evaluate_answer(&assignment).unwrap(), "3.5");
+    /// ```
+    pub fn evaluate_answer(&self, assignment: &HashMap<String, f64>) -> Result<String, MorphiusError> {
+        let answer = self.answer.as_ref().ok_or(MorphiusError::MissingAnswer)?;
+        let mut scope: HashMap<&str,Num> = HashMap::new();
+        let mut texts = HashMap::new();
+        for table in self.tables.iter() {
+            if let Some(row) = assignment.get(&table.name).and_then(|row| table.rows.get(*row as usize)) {
+                for (column, value) in table.columns.iter().zip(row.iter()).filter(|(column, _)| !assignment.contains_key(*column)) {
+                    match value.parse::<f64>() {
+                        Ok(value) => { scope.insert(&column[..], Num::Real(value)); },
+                        Err(_) => { texts.insert(&column[..], &value[..]); }
+                    }
+                }
+            }
+        }
+        for choice in self.choices.iter().filter(|choice| !numeric_choice(choice)) {
+            if let Some((option, _)) = assignment.get(&choice.name).and_then(|pos| choice.options.get(*pos as usize)) {
+                texts.insert(&choice.name[..], &option[..]);
+            }
+        }
+        for expression in answer_expressions(answer) {
+            for exp_comp in expression.expression.iter() {
+                if let ExpComp::Var(name) = exp_comp {
+                    if scope.contains_key(&name[..]) || texts.contains_key(&name[..]) && !assignment.contains_key(name) {
+                        continue;
+                    }
+                    let value = assignment.get(name).ok_or_else(|| MorphiusError::MissingVariable { name: name.clone() })?;
+                    scope.insert(&name[..], Num::Real(*value));
+                }
+            }
+        }
+        let order: Vec<usize> = (0..self.parts.len()).collect();
+        let options = GenerateOptions::default();
+        //The draws come from a fixed seed so that the same assignment always gives the same answer
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut draws = HashMap::new();
+        gen_draws(&mut draws, &answer_expressions(answer), &scope, 0, &mut rng)?;
+        let picks = gen_picks(&answer_expressions(answer), &mut rng);
+        let eval = Eval { question: 0, scope: &scope, draws, picks, options: &options, in_answer: true, texts };
+        Ok(gen_answer_forms(answer, &self.parts, &order, &eval)?.join(" or "))
+    }
+}
+
+
+
+
+
+///A DocumentBuilder builds a Document directly instead of processing a template
+///
+/// # Examples
+///
+/// ```
+/// use morphius::{DocumentBuilder, QuestionBuilder};
+/// let question = QuestionBuilder::new().text("What is ").expression("a+1").text("?").answer_expression("a+1").build();
+/// let doc = DocumentBuilder::new().layout("Intro\n").question(question).layout("\nOutro").build();
+/// assert_eq!(doc.layout, vec!["Intro\n", "\nOutro"]);
+/// assert_eq!(doc.to_template(), "Intro\n|<q>What is |<e>a+1</e>|?</q>||<a>|<e>a+1</e>|</a>|\nOutro");
+/// ```
+pub struct DocumentBuilder {
+    doc: Document
+}
+
+impl Default for DocumentBuilder {
+    fn default() -> Self {
+        DocumentBuilder { doc: Document { questions: Vec::new(), layout: vec![String::new()], globals: HashSet::new(), metadata: HashMap::new(), seed: None, macros: HashMap::new() } }
+    }
+}
+
+impl DocumentBuilder {
+    ///This function creates an empty DocumentBuilder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///This function adds content that stays in the same place when the questions move, after everything added so far
+    pub fn layout(mut self, text: &str) -> Self {
+        self.doc.layout.last_mut().unwrap().push_str(text);
+        self
+    }
+
+    ///This function adds a question after everything added so far
+    pub fn question(mut self, question: Question) -> Self {
+        self.doc.questions.push(question);
+        self.doc.layout.push(String::new());
+        self
+    }
+
+    ///This function adds a global variable, replacing any global variable with the same name
+    pub fn global(mut self, var: Var) -> Self {
+        self.doc.globals.retain(|global| global.name != var.name);
+        self.doc.globals.insert(var);
+        self
+    }
+
+    ///This function sets a metadata key of the Document
+    pub fn meta(mut self, key: &str, value: &str) -> Self {
+        self.doc.metadata.insert(String::from(key), String::from(value));
+        self
+    }
+
+    ///This function sets the seed of the Document
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.doc.seed = Some(seed);
+        self
+    }
+
+    ///This function returns the finished Document
+    pub fn build(self) -> Document {
+        debug_assert_eq!(self.doc.layout.len(), self.doc.questions.len() + 1);
+        self.doc
+    }
+}
+
+///A QuestionBuilder builds a Question directly instead of processing a template
+pub struct QuestionBuilder {
+    question: Question
+}
+
+impl Default for QuestionBuilder {
+    fn default() -> Self {
+        let question = Question {
+            vars: HashSet::new(),
+            expressions: Vec::new(),
+            layout: vec![String::new()],
+            answer: None,
+            parts: Vec::new(),
+            flags: Vec::new(),
+            hints: Vec::new(),
+            partitions: Vec::new(),
+            id: None,
+            shares: Vec::new(),
+            points: None,
+            tags: Vec::new(),
+            attempts: None,
+            tables: Vec::new(),
+            choices: Vec::new(),
+            summary: false,
+            required: false,
+            difficulty: None
+        };
+        QuestionBuilder { question }
+    }
+}
+
+impl QuestionBuilder {
+    ///This function creates an empty QuestionBuilder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///This function adds text to the question after everything added so far
+    pub fn text(mut self, text: &str) -> Self {
+        self.question.layout.last_mut().unwrap().push_str(text);
+        self
+    }
+
+    ///This function adds an expression, such as `a+b`, to the question after everything added so far. Its variables are added to the
+    ///question with the default declaration unless they are declared with var
+    pub fn expression(mut self, expression: &str) -> Self {
+        let mut vars = HashSet::new();
+        self.question.expressions.push(process_expression(expression, &HashMap::new(), &mut vars));
+        self.question.layout.push(String::new());
+        for var in vars {
+            if !self.question.vars.iter().any(|existing| existing.name == var.name) {
+                self.question.vars.insert(var);
+            }
+        }
+        self
+    }
+
+    ///This function declares a variable of the question, replacing any variable with the same name
+    pub fn var(mut self, var: Var) -> Self {
+        self.question.vars.retain(|existing| existing.name != var.name);
+        self.question.vars.insert(var);
+        self
+    }
+
+    ///This function adds a flag to the question, so that it is only used when one of its flags is active
+    pub fn flag(mut self, flag: &str) -> Self {
+        self.question.flags.push(String::from(flag));
+        self
+    }
+
+    ///This function adds a tag to the question, so that it is used when generating tests for that tag
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.question.tags.push(String::from(tag));
+        self
+    }
+
+    ///This function adds text to the answer of the question after everything added to the answer so far
+    pub fn answer_text(mut self, text: &str) -> Self {
+        self.answer().layout.last_mut().unwrap().push_str(text);
+        self
+    }
+
+    ///This function adds an expression to the answer of the question after everything added to the answer so far. Its variables
+    ///should also be used in the question
+    pub fn answer_expression(mut self, expression: &str) -> Self {
+        let expression = process_expression(expression, &HashMap::new(), &mut HashSet::new());
+        let answer = self.answer();
+        answer.expressions.push(expression);
+        answer.layout.push(String::new());
+        self
+    }
+
+    ///This function returns the finished Question
+    pub fn build(self) -> Question {
+        self.question
+    }
+
+    fn answer(&mut self) -> &mut Answer {
+        self.question.answer.get_or_insert_with(|| Answer { expressions: Vec::new(), layout: vec![String::new()], parts: Vec::new(), alternatives: Vec::new(), range: None, tolerance: None, when: None })
+    }
+}
+
+///This function takes an input &str in the desired template format and generates a document. If the document has answers you should use process_with_answers.
+///
+/// # Arguments
+///
+/// * `input` - A string slice that holds the template contents for the Document
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("Document Contents");
+/// ```
+pub fn process(input: &str) -> Document {
+    parse_document(input, false).0
+}
+
+///This function takes an input &str in the desired template format and generates a document. The input document must have answers provided for each question.
+///
+/// # Arguments
+///
+/// * `input` - A string slice that holds the template contents for the Document
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process_with_answers("Document Contents with answers");
+/// ```
+pub fn process_with_answers(input: &str) -> Document {
+    parse_document(input, true).0
+}
+
+///This function processes an input &str like process, but returns an error instead of a Document that would cause a panic when generating tests.
+///
+/// # Arguments
+///
+/// * `input` - A string slice that holds the template contents for the Document
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// assert!(morphius::try_process("|<q>|<v>a: int = [1,10]</v>||<e>a</e>|</q>|").is_ok());
+/// assert!(morphius::try_process("|<q>|<v>a: int = [10,1]</v>||<e>a</e>|</q>|").is_err());
+/// ```
+pub fn try_process(input: &str) -> Result<Document, MorphiusError> {
+    let (doc, duplicate) = parse_document(input, false);
+    if let Some(error) = duplicate {
+        return Err(error);
+    }
+    validate(&doc)?;
+    Ok(doc)
+}
+
+///This function processes an input &str like process_with_answers, but returns an error instead of a Document that would cause a panic when generating tests.
+///
+/// # Arguments
+///
+/// * `input` - A string slice that holds the template contents for the Document
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// assert!(morphius::try_process_with_answers("|<q>|<v>a: real = [2,1]</v>|</q>||<a>|<e>a</e>|</a>|").is_err());
+/// ```
+pub fn try_process_with_answers(input: &str) -> Result<Document, MorphiusError> {
+    let (doc, duplicate) = parse_document(input, true);
+    if let Some(error) = duplicate {
+        return Err(error);
+    }
+    validate(&doc)?;
+    Ok(doc)
+}
+
+///This function takes a template written with compact tags, such as `{{q}}Question{{/q}}`, and returns the same template written with
+///the standard tags so that it can be passed to any of the process functions. Standard tags in the template are kept as they are.
+///
+/// # Arguments
+///
+/// * `input` - A string slice that holds the template contents with compact tags
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let template = morphius::expand_compact("{{q if=honors}}What is {{e}}a+b{{/e}}?{{/q}}{{a}}{{e}}a+b{{/e}}{{/a}}{{use extra/}}");
+/// assert_eq!(template, "|<q if=honors>What is |<e>a+b</e>|?</q>||<a>|<e>a+b</e>|</a>||<use extra/>|");
+/// ```
+pub fn expand_compact(input: &str) -> String {
+    lazy_static! {
+        static ref TAG: Regex = Regex::new(r"\{\{(/?)(q|a|e|v|part|hint|meta|seed|unit|use|points|given|macro)(\s[^}]*?)?\s*(/?)\}\}").unwrap();
+    }
+    TAG.replace_all(input, |cap: &regex::Captures| {
+        let attributes = cap.get(3).map_or("", |attributes| attributes.as_str());
+        match (&cap[1], &cap[4]) {
+            ("/", _) => format!("</{}>|", &cap[2]),
+            (_, "/") => format!("|<{}{}/>|", &cap[2], attributes),
+            _ => format!("|<{}{}>", &cap[2], attributes)
+        }
+    }).into_owned()
+}
+
+///This function checks a Document for problems that would otherwise cause a panic when generating tests, such as calling a function that doesn't exist.
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document to check
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>|<e>logg(a)</e>|</q>|");
+/// assert!(morphius::validate(&doc).is_err());
+/// ```
+pub fn validate(doc: &Document) -> Result<(), MorphiusError> {
+    for var in doc.globals.iter().sorted_by(|a, b| a.name.cmp(&b.name)) {
+        validate_bounds(var)?;
+    }
+    let counts = count_names(&doc.questions);
+    for (i, question) in doc.questions.iter().enumerate() {
+        let shared = shared_questions(doc, i)?;
+        let mut hint_shared: Vec<usize> = Vec::new();
+        for id in question.hints.iter().flat_map(|hint| hint.shares.iter()) {
+            let found = shared_question(doc, i, id)?;
+            hint_shared.push(found);
+            hint_shared.extend(shared_questions(doc, found)?);
+        }
+        let visible: Vec<&Var> = question.vars.iter().chain(doc.globals.iter()).chain(shared.iter().flat_map(|index| doc.questions[*index].vars.iter())).collect();
+        //The hints can also use the variables of the questions they share
+        let hint_visible: Vec<&Var> = visible.iter().copied().chain(hint_shared.iter().flat_map(|index| doc.questions[*index].vars.iter())).collect();
+        let in_hint = |expression: &Expression| question.hints.iter().any(|hint| hint.expressions.iter().any(|other| std::ptr::eq(other, expression)));
+        for var in question.vars.iter().sorted_by(|a, b| a.name.cmp(&b.name)) {
+            validate_bounds(var)?;
+            if bound_names(var).iter().any(|name| !visible.iter().any(|other| other.name == *name && other.name != var.name)) {
+                return Err(MorphiusError::InvalidBounds { name: var.name.clone(), min: var.min.clone(), max: var.max.clone() });
+            }
+            if let Some(base) = &var.base {
+                if !doc.globals.iter().any(|global| &global.name == base) {
+                    return Err(MorphiusError::MissingVariable { name: base.clone() });
+                }
+            }
+        }
+        for expression in question_expressions(question) {
+            let visible = if in_hint(expression) { &hint_visible } else { &visible };
+            for exp_comp in expression.expression.iter() {
+                match exp_comp {
+                    ExpComp::Func(name) if !FUNCTIONS.contains(&&name[..]) => {
+                        return Err(MorphiusError::UnknownFunction { name: name.clone(), question: i, expression: expression_source(expression) });
+                    },
+                    ExpComp::Var(name) if question.summary && counts.contains(name) => (),
+                    ExpComp::Var(name) if !visible.iter().any(|var| &var.name == name) && !question.tables.iter().any(|table| table.columns.contains(name)) => {
+                        return Err(MorphiusError::UnknownVariable { name: name.clone(), question: i });
+                    },
+                    _ => ()
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+///This function takes an input Document, the number of tests that you want to generate and optionally the number of question per generated test
+///
+/// # Arguments
+///
+/// * `doc` - A reference to a Document for the template that you want to generate
+/// * `num_results` - The number of tests to generate
+/// * `num_quesitions` - The number of questions per test. Enter None to use all questions in the original order. To include all questions and reorder them, enter `Some(x)` where x is the total number of questions
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>Example Question 1</q>||<q>Example Question 2</q>|");
+/// morphius::generate(&doc, 5, Some(2));
+/// ```
+pub fn generate(doc: &Document, num_results: usize, num_questions: Option<usize>) -> Vec<Test> {
+    generate_with_rng(doc, num_results, num_questions, &mut doc_rng(doc))
+}
+
+///This function is the same as `generate` except that it uses the provided random number generator for all of the randomness in the generated tests
+///
+/// # Arguments
+///
+/// * `doc` - A reference to a Document for the template that you want to generate
+/// * `num_results` - The number of tests to generate
+/// * `num_quesitions` - The number of questions per test. Enter None to use all questions in the original order. To include all questions and reorder them, enter `Some(x)` where x is the total number of questions
+/// * `rng` - The random number generator to use
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// use rand::SeedableRng;
+/// let doc = morphius::process("|<q>Example Question 1</q>||<q>Example Question 2</q>|");
+/// let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+/// morphius::generate_with_rng(&doc, 5, Some(2), &mut rng);
+/// ```
+pub fn generate_with_rng<R: Rng>(doc: &Document, num_results: usize, num_questions: Option<usize>, rng: &mut R) -> Vec<Test> {
+    generate_tests(doc, num_results, num_questions, &GenerateOptions::default(), rng).unwrap_or_else(|error| panic!("{}", error))
+}
+
+///This function is the same as `generate` except that it uses the provided GenerateOptions
+///
+/// # Arguments
+///
+/// * `doc` - A reference to a Document for the template that you want to generate
+/// * `num_results` - The number of tests to generate
+/// * `num_quesitions` - The number of questions per test. Enter None to use all questions in the original order. To include all questions and reorder them, enter `Some(x)` where x is the total number of questions
+/// * `options` - The settings to use when generating the tests
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q if=honors>Honors Question</q>||<q>Example Question</q>|");
+/// let options = morphius::GenerateOptions { flags: vec![String::from("honors")], ..Default::default() };
+/// morphius::generate_with_options(&doc, 5, Some(2), &options);
+/// ```
+pub fn generate_with_options(doc: &Document, num_results: usize, num_questions: Option<usize>, options: &GenerateOptions) -> Vec<Test> {
+    generate_tests(doc, num_results, num_questions, options, &mut options_rng(doc, options)).unwrap_or_else(|error| panic!("{}", error))
+}
+
+///This function generates tests like generate_with_options, using only the questions with at least one of the given tags. The
+///number of questions per test is limited to the number of questions with those tags
+///
+/// # Arguments
+///
+/// * `doc` - A reference to a Document for the template that you want to generate
+/// * `tags` - The tags of the questions to use
+/// * `num_results` - The number of tests to generate
+/// * `num_questions` - The number of questions per test, or None to use every question with the tags in the original order
+/// * `options` - The settings to use when generating the tests
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q tags=algebra>Solve for x</q>||<q tags=geometry>Find the area</q>||<q tags=algebra,fractions>Add the fractions</q>|");
+/// let tests = morphius::generate_filtered(&doc, &["fractions"], 2, None, &morphius::GenerateOptions::default());
+/// assert_eq!(tests[0].content, "Add the fractions");
+/// ```
+pub fn generate_filtered(doc: &Document, tags: &[&str], num_results: usize, num_questions: Option<usize>, options: &GenerateOptions) -> Vec<Test> {
+    let options = GenerateOptions { tags: tags.iter().map(|tag| String::from(*tag)).collect(), ..options.clone() };
+    generate_with_options(doc, num_results, num_questions, &options)
+}
+
+///This function generates tests like generate, but returns an error instead of panicking if the document has a problem, such as an
+///expression that isn't valid math. It also returns the first warning of the tests as an error, such as an answer outside of its
+///expected range.
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document that will be used to generate the tests
+/// * `num_results` - The number of tests to generate
+/// * `num_questions` - The number of questions in each test, or None to use every question in the original order
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>|<e>a+1</e>|</q>|");
+/// assert_eq!(morphius::try_generate(&doc, 3, None).unwrap().len(), 3);
+/// let doc = morphius::process("|<q>|<e>a/0</e>|</q>|");
+/// assert!(morphius::try_generate(&doc, 3, None).is_err());
+/// ```
+pub fn try_generate(doc: &Document, num_results: usize, num_questions: Option<usize>) -> Result<Vec<Test>, MorphiusError> {
+    validate(doc)?;
+    let mut tests = generate_tests(doc, num_results, num_questions, &GenerateOptions::default(), &mut doc_rng(doc))?;
+    //Warnings don't stop the tests from being generated, but they are reported here since they point to a problem in the template
+    match tests.iter_mut().flat_map(|test| test.questions.iter_mut()).find(|question| !question.warnings.is_empty()) {
+        Some(question) => Err(question.warnings.remove(0)),
+        None => Ok(tests)
+    }
+}
+
+///This function generates a single test like try_generate, returning an error instead of panicking if the document has a problem.
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document that will be used to generate the test
+/// * `num_questions` - The number of questions in the test, or None to use every question in the original order
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>|<v>a: int = [1,3]</v>||<e>a*2</e>|</q>|");
+/// let test = morphius::try_generate_one(&doc, None).unwrap();
+/// assert!(["2", "4", "6"].contains(&&test.content[..]));
+/// ```
+pub fn try_generate_one(doc: &Document, num_questions: Option<usize>) -> Result<Test, MorphiusError> {
+    Ok(try_generate(doc, 1, num_questions)?.remove(0))
+}
+
+///This function generates every distinct Test that can be made from a document instead of sampling them: every order of the questions
+///combined with every combination of variable values. Every variable must be an int or a real with a single value. The Tests are
+///always generated in the same order.
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document that will be used to generate the tests
+/// * `num_questions` - The number of questions in each test, or None to use every question
+/// * `cap` - The largest number of tests to generate. If there are more distinct tests than this an error is returned
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>|<v>a: int = [1,2]</v>||<e>a</e>|</q>||<q>3</q>|");
+/// let tests = morphius::generate_all(&doc, None, 10).unwrap();
+/// let contents: Vec<&str> = tests.iter().map(|test| &test.content[..]).collect();
+/// assert_eq!(contents, vec!["13", "23", "31", "32"]);
+/// assert!(morphius::generate_all(&doc, None, 3).is_err());
+/// ```
+pub fn generate_all(doc: &Document, num_questions: Option<usize>, cap: usize) -> Result<Vec<Test>, MorphiusError> {
+    let options = GenerateOptions::default();
+    let active: Vec<usize> = (0..doc.questions.len()).filter(|i| is_active(&doc.questions[*i], &options)).collect();
+    let num_selected = cmp::min(num_questions.unwrap_or(active.len()), active.len());
+    let orders: Vec<Vec<usize>> = question_orders(doc, &active, num_selected).collect();
+
+    //Each order uses the global variables and the variables of its own questions, in name order
+    let order_vars = |order: &Vec<usize>| -> Vec<(Option<usize>, &Var)> {
+        let globals = doc.globals.iter().sorted_by(|a, b| a.name.cmp(&b.name)).map(|var| (None, var));
+        globals.chain(order.iter().flat_map(|i| doc.questions[*i].vars.iter().sorted_by(|a, b| a.name.cmp(&b.name)).map(move |var| (Some(*i), var)))).collect()
+    };
+    let mut count: usize = 0;
+    for order in orders.iter() {
+        let mut combinations: usize = 1;
+        for (_, var) in order_vars(order) {
+            combinations = combinations.saturating_mul(domain_size(var)?);
+        }
+        count = count.saturating_add(combinations);
+    }
+    if count > cap {
+        return Err(MorphiusError::TooManyTests { count, cap });
+    }
+
+    //Parts are still shuffled, so a fixed seed keeps the Tests the same every time
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let mut tests = Vec::new();
+    for order in orders.iter() {
+        let mut assignments: Vec<Assignment> = vec![HashMap::new()];
+        for (question, var) in order_vars(order) {
+            let values = domain_values(var)?;
+            assignments = assignments.into_iter().flat_map(|assignment| values.iter().map(move |value| {
+                let mut assignment = assignment.clone();
+                assignment.insert((question, &var.name[..]), *value);
+                assignment
+            })).collect();
+        }
+        for assignment in assignments.iter() {
+            tests.push(gen_form(doc, Some(order), assignment, &options, &mut rng)?);
+        }
+    }
+    Ok(tests)
+}
+
+///This function lays out the answer keys of several tests in aligned columns so that they can be printed together
+///
+/// # Arguments
+///
+/// * `tests` - The tests whose answer keys should be included
+/// * `cols` - The number of answer keys to place side by side in each row
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process_with_answers("|<q>What is |<e>a</e>| + 1?</q>||<a>|<e>a+1</e>|</a>|");
+/// let tests = morphius::generate(&doc, 6, None);
+/// println!("{}", morphius::format_answer_grid(&tests, 3));
+/// ```
+pub fn format_answer_grid(tests: &[Test], cols: usize) -> String {
+    let columns: Vec<Vec<String>> = tests.iter().enumerate().map(|(i, test)| {
+        let mut column = vec![format!("Test {}", i + 1)];
+        column.extend(test.questions.iter().enumerate().map(|(j, q)| format!("{}. {}", j + 1, q.answer.split_whitespace().join(" "))));
+        column
+    }).collect();
+    columns.chunks(cmp::max(cols, 1)).map(|row| {
+        let widths: Vec<usize> = row.iter().map(|column| column.iter().map(|cell| cell.chars().count()).max().unwrap_or(0)).collect();
+        let height = row.iter().map(|column| column.len()).max().unwrap_or(0);
+        (0..height).map(|line| {
+            row.iter().zip(widths.iter()).map(|(column, width)| {
+                format!("{:<width$}", column.get(line).map(|cell| &cell[..]).unwrap_or(""), width = width)
+            }).join("  ").trim_end().to_string()
+        }).join("\n")
+    }).join("\n\n")
+}
+
+///This function lays out the contents of several tests next to each other in columns, such as to proofread every form of a batch
+///before printing it. The lines of each test are wrapped to fit their column, and the columns are separated by ` | `
+///
+/// # Arguments
+///
+/// * `tests` - The tests to lay out, one per column
+/// * `width` - The total number of characters in each line, which is shared between the columns
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>What is 1 + 1?</q>|\n|<q>What is 2 + 2?</q>|");
+/// let tests = morphius::generate(&doc, 2, Some(2));
+/// let columns = morphius::side_by_side(&tests, 41);
+/// assert!(columns.starts_with("Test 1              | Test 2\n"));
+/// assert!(columns.lines().all(|line| line.chars().count() <= 41));
+/// ```
+pub fn side_by_side(tests: &[Test], width: usize) -> String {
+    if tests.is_empty() {
+        return String::new();
+    }
+    let separator = " | ";
+    let column_width = cmp::max(width.saturating_sub(separator.len() * (tests.len() - 1)) / tests.len(), 1);
+    let columns: Vec<Vec<String>> = tests.iter().enumerate().map(|(i, test)| {
+        let mut column = vec![format!("Test {}", i + 1)];
+        column.extend(test.content.lines().flat_map(|line| wrap_line(line, column_width)));
+        column
+    }).collect();
+    let height = columns.iter().map(|column| column.len()).max().unwrap_or(0);
+    (0..height).map(|line| {
+        columns.iter().map(|column| format!("{:<width$}", column.get(line).map(|cell| &cell[..]).unwrap_or(""), width = column_width))
+            .join(separator).trim_end().to_string()
+    }).join("\n")
+}
+
+//Lines are broken between words where possible, and words longer than the width are broken wherever they reach it
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in line.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        let length = current.chars().count();
+        if length > 0 && length + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut current));
+        } else if length > 0 {
+            current.push(' ');
+        }
+        while current.chars().count() + word.len() > width {
+            let rest = word.split_off(width - current.chars().count());
+            current.extend(word);
+            lines.push(std::mem::take(&mut current));
+            word = rest;
+        }
+        current.extend(word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+///This function lists which questions each student was given, in the order they were given, so that a test can be traced back
+///to its student later. The questions are numbered by their position in the Document, starting from 1. Tests don't keep the seed
+///they were generated from, so a batch that needs to be regenerated should be generated from recorded seeds with generate_keys
+///
+/// # Arguments
+///
+/// * `assignments` - The name of each student and the test they were given
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>First</q>||<q>Second</q>|");
+/// let tests = morphius::generate(&doc, 2, None);
+/// let assignments: Vec<(String, morphius::Test)> = vec![String::from("Ada"), String::from("Grace")].into_iter().zip(tests).collect();
+/// assert_eq!(morphius::roster_manifest(&assignments), "Name   Questions\nAda    1, 2\nGrace  1, 2");
+/// ```
+pub fn roster_manifest(assignments: &[(String, Test)]) -> String {
+    let rows: Vec<(&str, String)> = assignments.iter().map(|(name, test)| (&name[..], test.questions.iter().map(|question| question.index + 1).join(", "))).collect();
+    let width = rows.iter().map(|(name, _)| name.chars().count()).chain(std::iter::once("Name".len())).max().unwrap_or(0);
+    std::iter::once(("Name", String::from("Questions"))).chain(rows).map(|(name, questions)| format!("{:<width$}  {}", name, questions, width = width)).join("\n")
+}
+
+///This function summarizes the variable values and question orders of a batch of generated Tests, which is useful for checking
+///that a template produces enough variety.
+///
+/// # Arguments
+///
+/// * `tests` - A slice of the Tests to summarize
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>|<v>a: int = [1,5]</v>||<e>a</e>|</q>||<q>2</q>|");
+/// let tests = morphius::generate(&doc, 40, Some(2));
+/// let stats = morphius::batch_stats(&tests);
+/// let a = &stats.variables[&(0, String::from("a"))];
+/// assert!(a.min >= 1.0 && a.max <= 5.0);
+/// assert!(stats.orderings <= 2);
+/// ```
+pub fn batch_stats(tests: &[Test]) -> BatchStats {
+    let mut globals: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut variables: HashMap<(usize, String), Vec<f64>> = HashMap::new();
+    for test in tests {
+        for (name, value) in &test.globals {
+            globals.entry(name.clone()).or_default().push(*value);
+        }
+        for question in &test.questions {
+            for (name, value) in &question.values {
+                variables.entry((question.index, name.clone())).or_default().push(*value);
+            }
+        }
+    }
+    let orderings: HashSet<Vec<usize>> = tests.iter().map(|test| test.questions.iter().map(|q| q.index).collect()).collect();
+    BatchStats {
+        globals: globals.into_iter().map(|(name, values)| (name, summarize(&values))).collect(),
+        variables: variables.into_iter().map(|(key, values)| (key, summarize(&values))).collect(),
+        orderings: orderings.len()
+    }
+}
+
+///This function checks whether a submitted answer matches an expected answer, such as the answer to a question in a generated Test.
+///Whitespace and letter case are ignored. If both answers start with a number they are compared as numbers, which can be written
+///as decimals (with either `.` or `,` as the decimal separator), in scientific notation, or as fractions like `3/4`. A single separator
+///followed by exactly three digits, like in `1,234`, can group thousands or separate decimals, so either reading is accepted. Any text
+///after the numbers, such as a unit, must match when both answers have it.
+///
+/// # Arguments
+///
+/// * `expected` - A string slice that holds the correct answer
+/// * `submitted` - A string slice that holds the answer to check
+/// * `tolerance` - The largest allowed difference between numerical answers, or None to require them to be equal
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// assert!(morphius::check_answer("0.75 cm", "3/4", None));
+/// assert!(morphius::check_answer("3.14159", "3,14", Some(0.01)));
+/// assert!(morphius::check_answer("1234", "1,234", None));
+/// assert!(!morphius::check_answer("12 cm", "12 m", None));
+/// ```
+pub fn check_answer(expected: &str, submitted: &str, tolerance: Option<f64>) -> bool {
+    let expected = expected.split_whitespace().join(" ").to_lowercase();
+    let submitted = submitted.split_whitespace().join(" ").to_lowercase();
+    match (parse_response(&expected), parse_response(&submitted)) {
+        (Some((expected_nums, expected_unit)), Some((submitted_nums, submitted_unit))) => {
+            let units_match = expected_unit.is_empty() || submitted_unit.is_empty() || expected_unit == submitted_unit;
+            //A tiny tolerance is always allowed so that rounding in the generated answer doesn't matter
+            units_match && expected_nums.iter().cartesian_product(submitted_nums.iter())
+                .any(|(expected_num, submitted_num)| (expected_num - submitted_num).abs() <= tolerance.unwrap_or(0.0).max(1e-9))
+        },
+        _ => expected == submitted
+    }
+}
+
+//This returns every reading of the number in the response, along with the text after it
+fn parse_response(response: &str) -> Option<(Vec<f64>, &str)> {
+    lazy_static! {
+        static ref RESPONSE: Regex = Regex::new(r"^(-?)[$€£¥]?\s*([0-9][0-9.,]*(?:e[-+]?[0-9]+)?)(?:\s*/\s*(-?[0-9][0-9.,]*))?\s*(.*)$").unwrap();
+    }
+    let cap = RESPONSE.captures(response)?;
+    let mut values = parse_separated(&cap[2]);
+    if let Some(denominator) = cap.get(3) {
+        values = values.iter().cartesian_product(parse_separated(denominator.as_str())).map(|(value, denominator)| value / denominator).collect();
+    }
+    if !cap[1].is_empty() {
+        values.iter_mut().for_each(|value| *value = -*value);
+    }
+    if values.is_empty() { None } else { Some((values, cap.get(4).unwrap().as_str())) }
+}
+
+fn parse_separated(number: &str) -> Vec<f64> {
+    //The last separator is the decimal separator unless it is repeated, in which case every separator groups thousands
+    let decimal = match (number.rfind('.'), number.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(cmp::max(dot, comma)),
+        (Some(dot), None) if number.matches('.').count() == 1 => Some(dot),
+        (None, Some(comma)) if number.matches(',').count() == 1 => Some(comma),
+        _ => None
+    };
+    let parse = |decimal: Option<usize>| -> Option<f64> {
+        let normalized: String = number.char_indices().filter_map(|(i, c)| match c {
+            '.' | ',' if Some(i) == decimal => Some('.'),
+            '.' | ',' => None,
+            _ => Some(c)
+        }).collect();
+        normalized.parse().ok()
+    };
+    let mut readings: Vec<f64> = parse(decimal).into_iter().collect();
+    //A lone separator after one to three digits and before exactly three more, like in 1,234, could also group thousands
+    if let Some(pos) = decimal.filter(|_| number.matches(['.', ',']).count() == 1) {
+        let (whole, fraction) = (&number[..pos], &number[pos + 1..]);
+        let digits = fraction.split('e').next().unwrap();
+        if (1..=3).contains(&whole.len()) && !whole.starts_with('0') && digits.len() == 3 && digits.chars().all(|c| c.is_ascii_digit()) {
+            readings.extend(parse(None));
+        }
+    }
+    readings
+}
+
+fn summarize(values: &[f64]) -> VarStats {
+    VarStats {
+        min: values.iter().cloned().fold(f64::INFINITY, f64::min),
+        max: values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        mean: values.iter().sum::<f64>() / values.len() as f64,
+        count: values.len()
+    }
+}
+
+///This function combines a batch of Tests into a single printable document, with a page break (a form feed character) between
+///each Test. Each Test starts with a header made from the header template, where `{{index}}` is replaced by the position of the Test
+///starting from 1. An empty header template leaves out the header.
+///
+/// # Arguments
+///
+/// * `tests` - A slice of the Tests to combine
+/// * `header_template` - A string slice that holds the header placed at the top of each Test
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>Question</q>|");
+/// let tests = morphius::generate(&doc, 2, None);
+/// assert_eq!(morphius::paginate(&tests, "Test {{index}}"), "Test 1\nQuestion\u{000C}Test 2\nQuestion");
+/// ```
+pub fn paginate(tests: &[Test], header_template: &str) -> String {
+    tests.iter().enumerate().map(|(i, test)| {
+        let header = header_template.replace("{{index}}", &(i + 1).to_string());
+        if header.is_empty() { test.content.clone() } else { format!("{}\n{}", header, test.content) }
+    }).join(PAGE_BREAK)
+}
+
+///This function combines a batch of Tests and their answer keys into a single printable document. Each Test is followed by its
+///answer key, separated by a line marking where to cut, and there is a page break (a form feed character) between each Test.
+///
+/// # Arguments
+///
+/// * `tests` - A slice of the Tests to combine
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process_with_answers("|<q>1+1?</q>||<a>2</a>|");
+/// let tests = morphius::generate(&doc, 1, None);
+/// let bundle = morphius::bundle(&tests);
+/// assert!(bundle.starts_with("Test 1\n1+1?\n"));
+/// assert!(bundle.ends_with("\nTest 1 Answers\n2"));
+/// ```
+pub fn bundle(tests: &[Test]) -> String {
+    tests.iter().enumerate().map(|(i, test)| {
+        format!("Test {0}\n{1}\n{2}\nTest {0} Answers\n{3}", i + 1, test.content, CUT_LINE, test.answers)
+    }).join(PAGE_BREAK)
+}
+
+//Tests are generated from the seed of the document when it has one, so sharing the template shares the tests
+fn doc_rng(doc: &Document) -> rand::rngs::StdRng {
+    match doc.seed {
+        Some(seed) => rand::rngs::StdRng::seed_from_u64(seed),
+        None => rand::rngs::StdRng::from_entropy()
+    }
+}
+
+fn options_rng(doc: &Document, options: &GenerateOptions) -> Box<dyn RngCore> {
+    match options.random_source {
+        RandomSource::Document => Box::new(doc_rng(doc)),
+        RandomSource::Thread => Box::new(rand::thread_rng()),
+        RandomSource::Os => Box::new(rand::rngs::OsRng),
+        RandomSource::Seed(seed) => Box::new(rand::rngs::StdRng::seed_from_u64(seed))
+    }
+}
+
+fn generate_tests<R: Rng>(doc: &Document, num_results: usize, num_questions: Option<usize>, options: &GenerateOptions, rng: &mut R) -> Result<Vec<Test>, MorphiusError> {
+    let mut tests = Vec::with_capacity(num_results);
+    generate_each(doc, num_results, num_questions, options, true, rng, |test| {
+        tests.push(test);
+        Ok::<(), MorphiusError>(())
+    })?;
+    Ok(tests)
+}
+
+//Each Test is passed to emit as soon as it is generated, so the Tests don't have to be kept in memory
+fn generate_each<R: Rng, E: From<MorphiusError>>(doc: &Document, num_results: usize, num_questions: Option<usize>, options: &GenerateOptions, with_content: bool, rng: &mut R, mut emit: impl FnMut(Test) -> Result<(), E>) -> Result<(), E> {
+    let active: Vec<usize> = (0..doc.questions.len()).filter(|i| is_active(&doc.questions[*i], options)).collect();
+    let allowed = |order: &Vec<usize>| !options.forbidden_orders.contains(order);
+    match num_questions {
+        Some(num_qs) if options.balanced => {
+            let num_selected = cmp::min(num_qs, active.len());
+            let mut uses: Vec<usize> = vec![0; doc.questions.len()];
+            for test in 0..num_results {
+                let mut order = Vec::new();
+                for _ in 0..ORDER_RETRIES {
+                    check_deadline(options.deadline)?;
+                    //Shuffling before the stable sort breaks ties between equally used questions randomly
+                    let mut candidates = active.clone();
+                    candidates.shuffle(rng);
+                    candidates.sort_by_key(|i| (!doc.questions[*i].required, uses[*i]));
+                    order = candidates[..num_selected].to_vec();
+                    order.shuffle(rng);
+                    if allowed(&order) {
+                        break;
+                    }
+                }
+                if !allowed(&order) {
+                    return Err(MorphiusError::NoAllowedOrder.into());
+                }
+                for i in order.iter() {
+                    uses[*i] += 1;
+                }
+                emit(gen_form_with(doc, Some(&order), &cycle_assignment(doc, test), options, with_content, Some(test), rng)?)?;
+            }
+        }
+        Some(num_qs) => {
+            let num_permutations = cmp::min(num_qs, active.len());
+            let permutations: Vec<Vec<usize>> = question_orders(doc, &active, num_permutations).filter(allowed).collect();
+            if permutations.is_empty() && num_results > 0 {
+                return Err(MorphiusError::NoAllowedOrder.into());
+            }
+
+            for test in 0..num_results {
+                let order = &permutations[rng.gen_range(0..permutations.len())];
+                emit(gen_form_with(doc, Some(order), &cycle_assignment(doc, test), options, with_content, Some(test), rng)?)?;
+            }
+        }
+        //Without reordering the only order is the original one, so it can't be replaced by another order
+        None if num_results > 0 && !allowed(&active) => return Err(MorphiusError::NoAllowedOrder.into()),
+        None => {
+            let order = if active.len() < doc.questions.len() { Some(&active) } else { None };
+            for test in 0..num_results {
+                emit(gen_form_with(doc, order, &cycle_assignment(doc, test), options, with_content, Some(test), rng)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+///This function adds new Tests to an existing set of distinct Tests until there are `target_count` Tests with different contents, such as
+///when 5 more unique forms are needed. Only the new Tests are returned, and they are drawn like the tests of generate_with_options, so a
+///template with a seed gives the same new Tests each time. Fewer Tests are returned if no new distinct Test is found after many tries
+///in a row, such as when the template only has a few possible tests. Tests in the existing set with the same content count once
+///
+/// # Arguments
+///
+/// * `existing` - The Tests that are already in the set
+/// * `doc` - A reference to the Document that will be used to generate the tests
+/// * `target_count` - The number of distinct Tests the set should have
+/// * `num_questions` - The number of questions per test, like in generate
+/// * `options` - The settings used when generating the tests
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>|<v>a: int = [1,6]</v>||<e>a</e>|</q>|");
+/// let tests = morphius::generate(&doc, 2, None);
+/// let options = morphius::GenerateOptions::default();
+/// let added = morphius::top_up_distinct(&tests, &doc, 6, None, &options).unwrap();
+/// let mut contents: Vec<&str> = tests.iter().chain(added.iter()).map(|test| &test.content[..]).collect();
+/// contents.sort();
+/// contents.dedup();
+/// assert_eq!(contents.len(), 6);
+/// assert!(morphius::top_up_distinct(&tests, &doc, 10, None, &options).unwrap().len() <= 6);
+/// ```
+pub fn top_up_distinct(existing: &[Test], doc: &Document, target_count: usize, num_questions: Option<usize>, options: &GenerateOptions) -> Result<Vec<Test>, MorphiusError> {
+    let mut seen: HashSet<String> = existing.iter().map(|test| test.content.clone()).collect();
+    let missing = target_count.saturating_sub(seen.len());
+    let mut added = Vec::new();
+    let mut misses = 0;
+    //The tests are drawn from one batch so the question orders are only listed once, and stopping early is an error without a
+    //MorphiusError like in generate_with_callback
+    let result = generate_each(doc, missing.saturating_mul(DISTINCT_RETRIES), num_questions, options, true, &mut options_rng(doc, options), |test| {
+        if seen.insert(test.content.clone()) {
+            added.push(test);
+            misses = 0;
+        } else {
+            misses += 1;
+        }
+        if added.len() < missing && misses < DISTINCT_RETRIES { Ok(()) } else { Err(None) }
+    });
+    match result {
+        Err(Some(error)) => Err(error),
+        _ => Ok(added)
+    }
+}
+
+///This function generates tests like generate until the deadline passes, such as to keep a request handler from waiting on a
+///template that is slow to generate. This returns the tests that were generated before the deadline, along with true if all
+///of them were generated or false if the deadline stopped it early. A test that was still being generated at the deadline is dropped.
+///Passing the deadline isn't an error, but it returns an error if the document has a problem, such as an expression that isn't valid math
+///
+/// # Arguments
+///
+/// * `doc` - A reference to a Document for the template that you want to generate
+/// * `num_results` - The number of tests to generate
+/// * `num_questions` - The number of questions per test, like in generate
+/// * `deadline` - The time to stop generating at
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// use std::time::{Duration, Instant};
+/// let doc = morphius::process("|<q>|<v>a: int = [1,10]</v>||<e>a*2</e>|</q>|");
+/// let (tests, completed) = morphius::generate_until(&doc, 5, None, Instant::now() + Duration::from_secs(10)).unwrap();
+/// assert!(completed);
+/// assert_eq!(tests.len(), 5);
+/// let (tests, completed) = morphius::generate_until(&doc, 5, None, Instant::now()).unwrap();
+/// assert!(!completed);
+/// assert!(tests.is_empty());
+/// ```
+pub fn generate_until(doc: &Document, num_results: usize, num_questions: Option<usize>, deadline: Instant) -> Result<(Vec<Test>, bool), MorphiusError> {
+    let options = GenerateOptions { deadline: Some(deadline), ..Default::default() };
+    let mut tests = Vec::new();
+    let result = generate_each(doc, num_results, num_questions, &options, true, &mut doc_rng(doc), |test| {
+        tests.push(test);
+        Ok::<(), MorphiusError>(())
+    });
+    match result {
+        Ok(()) => Ok((tests, true)),
+        Err(MorphiusError::DeadlineExceeded) => Ok((tests, false)),
+        Err(error) => Err(error)
+    }
+}
+
+///This function generates a test for bubble sheets from each seed along with its bubble key, which lists the letter of the correct part of each
+///multiple choice question like `1-B 2-D 3-A`. The parts are shuffled by the seed, so the correct letters follow a pattern that is
+///hard to copy but is the same whenever the test is generated again from its seed, such as to check the scanned sheets. It returns an
+///error if the document has a problem, such as an expression that isn't valid math
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document to generate the tests from
+/// * `seeds` - The seed of each test
+/// * `num_questions` - The number of questions per test, like in generate
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>2 + 2 |<part>3</part>| |<part correct>4</part>| |<part>5</part>|</q>|\n|<q>Sky |<part correct>Blue</part>| |<part>Red</part>|</q>|");
+/// let sheets = morphius::generate_bubble_sheets(&doc, &[1, 2], None).unwrap();
+/// let again = morphius::generate_bubble_sheets(&doc, &[1, 2], None).unwrap();
+/// assert_eq!((&sheets[1].0.content, &sheets[1].1), (&again[1].0.content, &again[1].1));
+/// assert_eq!(sheets[0].1, sheets[0].0.bubble_key());
+/// ```
+pub fn generate_bubble_sheets(doc: &Document, seeds: &[u64], num_questions: Option<usize>) -> Result<Vec<(Test, String)>, MorphiusError> {
+    seeds.iter().map(|seed| {
+        let test = generate_tests(doc, 1, num_questions, &GenerateOptions::default(), &mut rand::rngs::StdRng::seed_from_u64(*seed))?.remove(0);
+        let key = test.bubble_key();
+        Ok((test, key))
+    }).collect()
+}
+
+///This function generates only the answer keys of tests that were generated from seeds, such as to grade tests that were already
+///printed. The answer key for a seed is the same as the answers of the test generated by generate_with_rng with a `StdRng` made
+///from that seed and `num_results` set to 1, but the content of the test isn't built. It returns an error if the document has a
+///problem, such as an expression that isn't valid math.
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document the tests were generated from
+/// * `seeds` - The seed of each test
+/// * `num_questions` - The number of questions per test, like in generate
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// use rand::SeedableRng;
+/// let doc = morphius::process_with_answers("|<q>What is |<e>a</e>| + |<e>b</e>|?</q>||<a>|<e>a+b</e>|</a>||<q>Double |<e>c</e>|</q>||<a>|<e>c*2</e>|</a>|");
+/// let test = morphius::generate_with_rng(&doc, 1, Some(2), &mut rand::rngs::StdRng::seed_from_u64(42)).remove(0);
+/// assert_eq!(morphius::try_generate_keys(&doc, &[42], Some(2)), Ok(vec![test.answers]));
+/// ```
+pub fn try_generate_keys(doc: &Document, seeds: &[u64], num_questions: Option<usize>) -> Result<Vec<String>, MorphiusError> {
+    seeds.iter().map(|seed| {
+        let mut key = String::new();
+        generate_each(doc, 1, num_questions, &GenerateOptions::default(), false, &mut rand::rngs::StdRng::seed_from_u64(*seed), |test| {
+            key = test.answers;
+            Ok::<(), MorphiusError>(())
+        })?;
+        Ok(key)
+    }).collect()
+}
+
+///This function is try_generate_keys, but it panics if the document has a problem
+pub fn generate_keys(doc: &Document, seeds: &[u64], num_questions: Option<usize>) -> Vec<String> {
+    try_generate_keys(doc, seeds, num_questions).unwrap_or_else(|error| panic!("{}", error))
+}
+
+///This function generates tests like generate, passing each test to a callback as soon as it is generated instead of collecting
+///them, such as to show the progress of a large batch. The callback is given the position of the test in the batch and the test,
+///and returns false to stop generating. This returns the number of tests that were generated, or an error if the document has a
+///problem, in which case the tests before it have already been passed to the callback
+///
+/// # Arguments
+///
+/// * `doc` - A reference to a Document for the template that you want to generate
+/// * `num_results` - The number of tests to generate
+/// * `num_questions` - The number of questions per test, like in generate
+/// * `callback` - The function called with each test
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>|<e>a</e>|</q>|");
+/// let mut contents = Vec::new();
+/// let count = morphius::generate_with_callback(&doc, 10, None, |index, test| {
+///     contents.push(test.content.clone());
+///     index < 2
+/// });
+/// assert_eq!(count, Ok(3));
+/// assert_eq!(contents.len(), 3);
+/// ```
+pub fn generate_with_callback<F: FnMut(usize, &Test) -> bool>(doc: &Document, num_results: usize, num_questions: Option<usize>, mut callback: F) -> Result<usize, MorphiusError> {
+    let mut count = 0;
+    //Stopping early is an error without a MorphiusError, so that no more tests are generated
+    let result = generate_each(doc, num_results, num_questions, &GenerateOptions::default(), true, &mut doc_rng(doc), |test| {
+        count += 1;
+        if callback(count - 1, &test) { Ok(()) } else { Err(None) }
+    });
+    match result {
+        Err(Some(error)) => Err(error),
+        _ => Ok(count)
+    }
+}
+
+///This function generates tests like generate_with_options and writes each one to a file in a directory as soon as it is generated,
+///so only one test is kept in memory at a time. The tests are written to `test_1.txt`, `test_2.txt`, ... with the numbers padded
+///with zeros to the same width, and if any question has an answer, the answer keys are written to `answers_1.txt`, `answers_2.txt`, ...
+///The directory is created if it doesn't exist.
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document that will be used to generate the tests
+/// * `num_results` - The number of tests to generate
+/// * `num_questions` - The number of questions per test, like in generate
+/// * `dir` - The directory the files are written to
+/// * `options` - The settings used when generating the tests
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let dir = std::env::temp_dir().join("morphius_generate_to_dir_example");
+/// let doc = morphius::process_with_answers("|<q>What is |<e>a</e>| + 1?</q>||<a>|<e>a+1</e>|</a>|");
+/// morphius::generate_to_dir(&doc, 12, None, &dir, &morphius::GenerateOptions::default()).unwrap();
+/// assert!(dir.join("test_07.txt").exists() && dir.join("answers_12.txt").exists());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn generate_to_dir(doc: &Document, num_results: usize, num_questions: Option<usize>, dir: &Path, options: &GenerateOptions) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let width = num_results.to_string().len();
+    let has_answers = doc.questions.iter().any(|question| question.answer.is_some());
+    let mut count = 0;
+    generate_each(doc, num_results, num_questions, options, true, &mut options_rng(doc, options), |test| {
+        count += 1;
+        fs::write(dir.join(format!("test_{:0width$}.txt", count, width = width)), &test.content)?;
+        if has_answers {
+            fs::write(dir.join(format!("answers_{:0width$}.txt", count, width = width)), &test.answers)?;
+        }
+        Ok(())
+    })
+}
+
+///This function generates tests like generate_with_options, pairing each test with a simplified version of it for students who need
+///simpler numbers. The simplified version uses the same questions and variable values, but its numbers are rounded to at most
+///max_decimals decimal places. It returns an error if the document has a problem, such as an expression that isn't valid math.
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document that will be used to generate the tests
+/// * `num_results` - The number of pairs of tests to generate
+/// * `num_questions` - The number of questions per test, like in generate
+/// * `options` - The settings used for the standard tests
+/// * `max_decimals` - The largest number of decimal places in the simplified tests
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>|<v>x: real = [3,4]</v>|What is |<e>x/3</e>|?</q>|");
+/// let pairs = morphius::generate_simplified(&doc, 3, None, &morphius::GenerateOptions::default(), 0).unwrap();
+/// assert_eq!(pairs[0].1.content, "What is 1?");
+/// assert_eq!(pairs[0].0.questions[0].values, pairs[0].1.questions[0].values);
+/// ```
+pub fn generate_simplified(doc: &Document, num_results: usize, num_questions: Option<usize>, options: &GenerateOptions, max_decimals: usize) -> Result<Vec<(Test, Test)>, MorphiusError> {
+    let simplified = GenerateOptions { max_decimals: Some(max_decimals), ..options.clone() };
+    //Both batches use the same seed, and formatting doesn't use the rng, so they sample the same questions and values
+    let seed = doc.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let standard = generate_tests(doc, num_results, num_questions, options, &mut rand::rngs::StdRng::seed_from_u64(seed))?;
+    let simple = generate_tests(doc, num_results, num_questions, &simplified, &mut rand::rngs::StdRng::seed_from_u64(seed))?;
+    Ok(standard.into_iter().zip(simple).collect())
+}
+
+///This function generates a small number of versions of a test labeled with letters (A, B, C, ...), such as when every student is
+///given one of a few forms. Each form uses a different question order, and calling this function again with the same arguments gives
+///the same forms. The forms are drawn from the seed of the Document, or from the template itself if it doesn't have one, so different
+///templates get different orders. It returns an error if more than 26 forms are asked for or there aren't enough orders to give
+///each form its own.
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document that will be used to generate the forms
+/// * `num_forms` - The number of forms to generate
+/// * `num_questions` - The number of questions in each form, or None to use every question
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process("|<q>1</q>||<q>2</q>||<q>3</q>|");
+/// let forms = morphius::generate_named_forms(&doc, 4, None).unwrap();
+/// assert_eq!(forms.iter().map(|(label, _)| *label).collect::<String>(), "ABCD");
+/// assert_eq!(forms[2].1.content, morphius::generate_named_forms(&doc, 4, None).unwrap()[2].1.content);
+/// assert!(morphius::generate_named_forms(&doc, 7, None).is_err());
+/// ```
+pub fn generate_named_forms(doc: &Document, num_forms: usize, num_questions: Option<usize>) -> Result<Vec<(char, Test)>, MorphiusError> {
+    if num_forms > 26 {
+        return Err(MorphiusError::TooManyForms { count: num_forms });
+    }
+    let options = GenerateOptions::default();
+    let active: Vec<usize> = (0..doc.questions.len()).filter(|i| is_active(&doc.questions[*i], &options)).collect();
+    let num_selected = cmp::min(num_questions.unwrap_or(active.len()), active.len());
+    //Like question_orders, each order has as many of the required questions as it has room for, but the orders are drawn instead of listed
+    let (required, others): (Vec<usize>, Vec<usize>) = active.iter().partition(|i| doc.questions[**i].required);
+    let num_required = cmp::min(num_selected, required.len());
+    let choose = |n: usize, k: usize| (0..k).fold(1u128, |count, i| count.saturating_mul((n - i) as u128) / (i as u128 + 1));
+    let num_orders = choose(required.len(), num_required).saturating_mul(choose(others.len(), num_selected - num_required))
+        .saturating_mul((1..=num_selected as u128).fold(1u128, |count, i| count.saturating_mul(i)));
+    if num_orders < num_forms as u128 {
+        return Err(MorphiusError::NotEnoughOrders { forms: num_forms, orders: num_orders as usize });
+    }
+    let mut rng = rand::rngs::StdRng::seed_from_u64(doc.seed.unwrap_or_else(|| fnv_hash(&doc.to_template())));
+    let mut orders: Vec<Vec<usize>> = Vec::with_capacity(num_forms);
+    while orders.len() < num_forms {
+        let mut order: Vec<usize> = required.choose_multiple(&mut rng, num_required).chain(others.choose_multiple(&mut rng, num_selected - num_required)).copied().collect();
+        order.shuffle(&mut rng);
+        if !orders.contains(&order) {
+            orders.push(order);
+        }
+    }
+    ('A'..='Z').zip(orders.iter()).map(|(label, order)| Ok((label, gen_form(doc, Some(order), &HashMap::new(), &options, &mut rng)?))).collect()
+}
+
+///This function generates pairs of Tests for alternating seats, where the second Test of each pair has the questions of the first
+///in the reverse order. Both Tests of a pair use the same variable values, so each question has the same answer in both. Random
+///draws inside expressions, picks and the order of parts are drawn again for the second Test. The second Test keeps the reverse
+///order even when the options sort by difficulty
+///
+/// # Arguments
+///
+/// * `doc` - A reference to the Document that will be used to generate the tests
+/// * `num_pairs` - The number of pairs to generate
+/// * `num_questions` - The number of questions per test, like in generate
+/// * `options` - The options used to generate both Tests of each pair
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process_with_answers("|<q>|<v>a: int = [1,99]</v>||<e>a</e>|</q>||<a>|<e>a+1</e>|</a>|\n|<q>Two</q>||<a>2</a>|");
+/// let (test, mirror) = morphius::generate_mirror_pair(&doc, 1, None, &morphius::GenerateOptions::default()).unwrap().remove(0);
+/// let a = test.questions[0].values["a"];
+/// assert_eq!(test.content, format!("{}\nTwo", a));
+/// assert_eq!(mirror.content, format!("Two\n{}", a));
+/// assert_eq!(mirror.answers, format!("2\n{}", a + 1.0));
+/// ```
+pub fn generate_mirror_pair(doc: &Document, num_pairs: usize, num_questions: Option<usize>, options: &GenerateOptions) -> Result<Vec<(Test, Test)>, MorphiusError> {
+    let mut rng = options_rng(doc, options);
+    let tests = generate_tests(doc, num_pairs, num_questions, options, &mut rng)?;
+    let mirror_options = GenerateOptions { by_difficulty: false, ..options.clone() };
+    tests.into_iter().enumerate().map(|(pos, test)| {
+        let order: Vec<usize> = test.questions.iter().rev().map(|test_question| test_question.index).collect();
+        let mirror = gen_form_with(doc, Some(&order), &test_assignment(doc, &test), &mirror_options, true, Some(pos), &mut rng)?;
+        Ok((test, mirror))
+    }).collect()
+}
+
+//The values a Test was generated with, as an Assignment that generates another Test with the same values
+fn test_assignment<'a>(doc: &'a Document, test: &Test) -> Assignment<'a> {
+    let num = |var: &Var, value: f64| if var.num_type == "int" { Num::Int(value.round() as i64) } else { Num::Real(value) };
+    let mut assignment: Assignment = doc.globals.iter().filter_map(|var| test.globals.get(&var.name).map(|value| ((None, &var.name[..]), num(var, *value)))).collect();
+    for test_question in test.questions.iter() {
+        let question = &doc.questions[test_question.index];
+        for var in question.vars.iter() {
+            if let Some(value) = test_question.values.get(&var.name) {
+                let value = match question.choices.iter().find(|choice| choice.name == var.name) {
+                    //A choice is fixed by the position of its option rather than by its value
+                    Some(choice) => Num::Int(chosen_option(choice, &HashMap::from([(&choice.name[..], Num::Real(*value))])) as i64),
+                    None => num(var, *value)
+                };
+                assignment.insert((Some(test_question.index), &var.name[..]), value);
+            }
+        }
+    }
+    assignment
+}
+
+///This function gives the answer key of each form generated by generate_named_forms, labeled with its letter so that it can be
+///handed to whoever grades that form
+///
+/// # Arguments
+///
+/// * `tests` - The forms and their letters
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process_with_answers("|<q>1</q>||<a>One</a>|\n|<q>2</q>||<a>Two</a>|");
+/// let forms = morphius::generate_named_forms(&doc, 2, None).unwrap();
+/// assert_eq!(morphius::keys_by_form(&forms)[&'A'], "Form A Key\nOne\nTwo");
+/// ```
+pub fn keys_by_form(tests: &[(char, Test)]) -> HashMap<char, String> {
+    tests.iter().map(|(label, test)| (*label, format!("Form {} Key\n{}", label, test.answers))).collect()
+}
+
+///This function combines the answer keys of several batches of tests into one document, such as batches that were generated at
+///different times. Each key is labeled with the number of its batch and its number in that batch. Tests don't have ids, so a test
+///with the same content and answers as an earlier test is treated as the same test and its key is only included once
+///
+/// # Arguments
+///
+/// * `batches` - The batches of tests, in the order they were generated
+///
+/// # Examples
+///
+/// ```
+/// use morphius;
+/// let doc = morphius::process_with_answers("|<q>|<v>a: int = [1,9]</v>|What is |<e>a</e>| + 1?</q>||<a>|<e>a+1</e>|</a>|");
+/// let first = morphius::generate(&doc, 2, None);
+/// let second = morphius::generate(&doc, 1, None);
+/// let keys = morphius::consolidate_keys(&[&first, &second]);
+/// assert!(keys.starts_with("Batch 1 Test 1 Key\n"));
+/// ```
+pub fn consolidate_keys(batches: &[&[Test]]) -> String {
+    let mut seen: HashSet<(&str, &str)> = HashSet::new();
+    batches.iter().enumerate().flat_map(|(i, batch)| batch.iter().enumerate().map(move |(j, test)| (i, j, test)))
+        .filter(|(_, _, test)| seen.insert((&test.content, &test.answers)))
+        .map(|(i, j, test)| format!("Batch {} Test {} Key\n{}", i + 1, j + 1, test.answers))
+        .join("\n\n")
+}
+
+fn var_bounds(var: &Var) -> Result<(f64, f64, Option<f64>), MorphiusError> {
+    let invalid = || MorphiusError::InvalidBounds { name: var.name.clone(), min: var.min.clone(), max: var.max.clone() };
+    let min = var.min.parse::<f64>().map_err(|_| invalid())?;
+    let max = var.max.parse::<f64>().map_err(|_| invalid())?;
+    let grain = match &var.grain {
+        Some(grain) => Some(grain.parse::<f64>().map_err(|_| invalid())?),
+        None => None
+    };
+    Ok((min, max, grain))
+}
+
+fn validate_bounds(var: &Var) -> Result<(), MorphiusError> {
+    //Bounds depending on other variables can only be checked once those variables have values
+    if is_dependent(var) {
+        return Ok(());
+    }
+    let (min, max, grain) = var_bounds(var)?;
+    let empty = match grain {
+        _ if var.cycle.is_some() => false,
+        _ if var.set.is_some() => set_range(var, min, max).count() == Some(0),
+        Some(grain) => {
+            let (first, last) = grain_steps(min, max, grain);
+            grain <= 0.0 || first > last
+        },
+        None if var.num_type == "int" => min.ceil() > max.floor(),
+        None => min > max
+    };
+    if empty {
+        return Err(MorphiusError::InvalidBounds { name: var.name.clone(), min: var.min.clone(), max: var.max.clone() });
+    }
+    Ok(())
+}
+
+fn domain_size(var: &Var) -> Result<usize, MorphiusError> {
+    if is_dependent(var) {
+        return Err(MorphiusError::NotDiscrete { name: var.name.clone() });
+    }
+    let (min, max, grain) = var_bounds(var)?;
+    if let Some(values) = &var.cycle {
+        Ok(values.len())
+    } else if var.set.is_some() {
+        set_range(var, min, max).count().map(|count| count as usize).ok_or_else(|| MorphiusError::SetTooLarge { name: var.name.clone() })
+    } else if let Some(grain) = grain {
+        let (first, last) = grain_steps(min, max, grain);
+        Ok(cmp::max(last - first + 1, 0) as usize)
+    } else if var.num_type == "int" {
+        Ok((max.floor() - min.ceil() + 1.0).max(0.0) as usize)
+    } else if min == max {
+        Ok(1)
+    } else {
+        Err(MorphiusError::NotDiscrete { name: var.name.clone() })
+    }
+}
+
+fn domain_values(var: &Var) -> Result<Vec<Num>, MorphiusError> {
+    let (min, max, grain) = var_bounds(var)?;
+    Ok(if var.cycle.is_some() {
+        cycle_values(var)
+    } else if var.set.is_some() {
+        let range = set_range(var, min, max);
+        let count = range.count().ok_or_else(|| MorphiusError::SetTooLarge { name: var.name.clone() })?;
+        (0..count).map(|pos| set_num(var, range.member(pos))).collect()
+    } else if let Some(grain) = grain {
+        let (first, last) = grain_steps(min, max, grain);
+        (first..=last).map(|step| grain_value(step, grain)).map(|value| if var.num_type == "int" { Num::Int(value.round() as i64) } else { Num::Real(value) }).collect()
+    } else if var.num_type == "int" {
+        (min.ceil() as i64..=max.floor() as i64).map(Num::Int).collect()
+    } else {
+        vec![Num::Real(min)]
+    })
+}
+
+//Every order includes as many of the required questions as it has room for, and other questions fill the rest of it
+fn question_orders<'a>(doc: &'a Document, active: &'a [usize], num_selected: usize) -> impl Iterator<Item = Vec<usize>> + 'a {
+    let count_required = |order: &[usize]| order.iter().filter(|i| doc.questions[**i].required).count();
+    let required = cmp::min(num_selected, count_required(active));
+    active.iter().copied().permutations(num_selected).filter(move |order| count_required(order) == required)
+}
+
+fn is_active(question: &Question, options: &GenerateOptions) -> bool {
+    (question.flags.is_empty() || question.flags.iter().any(|flag| options.flags.contains(flag)))
+        && (options.tags.is_empty() || question.tags.iter().any(|tag| options.tags.contains(tag)))
+}
+
+fn preprocess(input: &str) -> String {
+    lazy_static! {
+        static ref UNIT: Regex = Regex::new(&format!(r"(?s)\|<unit\s+({})>(.*?)</unit>\|", IDENT)).unwrap();
+        static ref USE: Regex = Regex::new(&format!(r"\|<use\s+({})\s*/>\|", IDENT)).unwrap();
+    }
+    let units: HashMap<&str, &str> = UNIT.captures_iter(input).map(|cap| (cap.get(1).unwrap().as_str(), cap.get(2).unwrap().as_str())).collect();
+    let input = UNIT.replace_all(input, "");
+    USE.replace_all(&input, |cap: &regex::Captures| match units.get(&cap[1]) {
+        Some(unit) => String::from(*unit),
+        None => String::from(&cap[0])
+    }).into_owned()
+}
+
+fn gen_form<R: Rng>(doc: &Document, order: Option<&Vec<usize>>, assignment: &Assignment, options: &GenerateOptions, rng: &mut R) -> Result<Test, MorphiusError> {
+    gen_form_with(doc, order, assignment, options, true, None, rng)
+}
+
+//Without content, only the answer key is built. Leaving out the content doesn't change how the rng is used, so the answers are the same
+//The position is the position of the test in its batch, if it is generated as part of one
+fn gen_form_with<R: Rng>(doc: &Document, order: Option<&Vec<usize>>, assignment: &Assignment, options: &GenerateOptions, with_content: bool, position: Option<usize>, rng: &mut R) -> Result<Test, MorphiusError> {
+    check_deadline(options.deadline)?;
+    let mut order: Vec<usize> = match order {
+        Some(ord) => ord.clone(),
+        None => (0..doc.questions.len()).collect()
+    };
+    if options.by_difficulty {
+        order.sort_by_key(|index| (doc.questions[*index].difficulty.is_none(), doc.questions[*index].difficulty, *index));
+    }
+    debug_assert_eq!(doc.layout.len(), doc.questions.len() + 1);
+    debug_assert!(order.len() <= doc.questions.len());
+    let mut globals = HashMap::new();
+    gen_scope(&mut globals, &doc.globals, &HashMap::new(), &assigned(assignment, None), options, rng)?;
+    let names = count_names(&doc.questions);
+    let mut counts: HashMap<&str, i64> = names.iter().map(|name| (&name[..], 0)).collect();
+    let mut summary_globals = None;
+    let mut scopes: HashMap<usize, HashMap<&str,Num>> = HashMap::new();
+    //The same scope is cleared and refilled for each question instead of allocating a new one
+    let mut scope = HashMap::new();
+    let mut questions: Vec<Option<TestQuestion>> = order.iter().map(|_| None).collect();
+    //Summary questions are generated after the others, once it is known how many times each value of a choice was chosen
+    let (summaries, others): (Vec<usize>, Vec<usize>) = (0..order.len()).partition(|pos| doc.questions[order[*pos]].summary);
+    for pos in others.into_iter().chain(summaries) {
+        let index = order[pos];
+        let question = &doc.questions[index];
+        if question.summary && summary_globals.is_none() {
+            let mut base = globals.clone();
+            base.extend(counts.iter().map(|(name, count)| (*name, Num::Int(*count))));
+            summary_globals = Some(base);
+        }
+        let base = if question.summary { summary_globals.as_ref().unwrap() } else { &globals };
+        //Values that make an expression divide by zero are generated again, unless another question already used them by sharing them
+        let cached = scopes.contains_key(&index);
+        let mut retries = if cached { 0 } else { DEPENDENT_RETRIES };
+        let test_question = loop {
+            shared_scope(&mut scope, doc, index, base, assignment, &mut scopes, options, rng)?;
+            //The correct part moves along by one letter for each question and each test
+            let slot = position.filter(|_| options.rotate_correct).map(|position| position + pos);
+            let hint_scopes = hint_scopes(doc, index, &scope, base, assignment, &mut scopes, options, rng)?;
+            match gen_question_content(question, index, &scope, &hint_scopes, options, with_content, slot, rng) {
+                Err(MorphiusError::NotFinite { .. } | MorphiusError::DuplicateParts { .. }) if retries > 1 => {
+                    scopes.remove(&index);
+                    retries -= 1;
+                },
+                result => break result?
+            }
+        };
+        if !question.summary {
+            for choice in question.choices.iter() {
+                let chosen = chosen_option(choice, &scope);
+                if let Some((_, name)) = choice_counts(choice).find(|(pos, _)| *pos == chosen) {
+                    *counts.get_mut(&name[..]).unwrap() += 1;
+                }
+            }
+        }
+        questions[pos] = Some(test_question);
+    }
+    let questions: Vec<TestQuestion> = questions.into_iter().map(Option::unwrap).collect();
+    let (content, answers) = test_text(doc, &questions, options);
+    //The content and the answer key are built from the same layout and question order, so numbers written in the layout always match
+    Ok(Test {
+        content: if with_content { content } else { String::new() },
+        answers,
+        questions,
+        globals: globals.iter().map(|(name, value)| (String::from(*name), value.value())).chain(counts.iter().map(|(name, count)| (String::from(*name), *count as f64))).collect()
+    })
+}
+
+//This builds the content and the answer key of a test from its questions
+fn test_text(doc: &Document, questions: &[TestQuestion], options: &GenerateOptions) -> (String, String) {
+    let mut footnotes = Vec::new();
+    let contents: Vec<String> = questions.iter().map(|q| {
+        let mut content = match &options.inline_answers {
+            Some(marker) => format!("{}{}", q.content, marker.replace("{answer}", &q.answer)),
+            None => q.content.clone()
+        };
+        //Questions without an answer in the key don't get a footnote
+        if options.footnote_answers && !q.answer.is_empty() {
+            footnotes.push(format!("[{}] {}", footnotes.len() + 1, q.answer));
+            write!(content, " [{}]", footnotes.len()).unwrap();
+        }
+        content
+    }).collect();
+    let mut content = doc.layout.iter().interleave(contents.iter()).join("");
+    if !footnotes.is_empty() {
+        write!(content, "\n\nAnswers\n{}", footnotes.join("\n")).unwrap();
+    }
+    let mut answers = doc.layout.iter().interleave(questions.iter().map(|q| &q.answer)).join("");
+    if let Some(ending) = &options.line_ending {
+        content = normalize_line_endings(&content, ending);
+        answers = normalize_line_endings(&answers, ending);
+    }
+    (content, answers)
+}
+
+//FNV-1a is used instead of the hasher of the standard library, whose results can change between versions of Rust
+fn fnv_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+fn normalize_line_endings(text: &str, ending: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', ending)
+}
+
+//The count of each value of a choice is named after the choice and the value, such as `count_grade_A`, so only values that can be part of a name are counted
+fn choice_counts(choice: &Choice) -> impl Iterator<Item = (usize, String)> + '_ {
+    choice.options.iter().enumerate().filter(|(_, (value, _))| !value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || c == '_'))
+        .map(move |(pos, (value, _))| (pos, format!("count_{}_{}", choice.name, value)))
+}
+
+fn count_names(questions: &[Question]) -> Vec<String> {
+    questions.iter().filter(|question| !question.summary).flat_map(|question| question.choices.iter().flat_map(choice_counts))
+        .map(|(_, name)| name).unique().collect()
+}
+
+//The scope holds the value of a numeric choice instead of its index, so the index is found by its value
+fn chosen_option(choice: &Choice, scope: &HashMap<&str,Num>) -> usize {
+    let value = scope[&choice.name[..]].value();
+    if numeric_choice(choice) {
+        choice.options.iter().position(|(option, _)| option.parse::<f64>().ok() == Some(value)).unwrap_or_default()
+    } else {
+        value as usize
+    }
+}
+
+fn assigned<'a>(assignment: &Assignment<'a>, question: Option<usize>) -> HashMap<&'a str,Num> {
+    assignment.iter().filter(|((index, _), _)| *index == question).map(|((_, name), value)| (*name, *value)).collect()
+}
+
+fn shared_question(doc: &Document, index: usize, id: &str) -> Result<usize, MorphiusError> {
+    doc.questions[..index].iter().position(|question| question.id.as_deref() == Some(id))
+        .ok_or_else(|| MorphiusError::UnknownQuestion { id: String::from(id), question: index })
+}
+
+//This returns the questions whose variables the question can use, including the ones shared by the questions it shares
+fn shared_questions(doc: &Document, index: usize) -> Result<Vec<usize>, MorphiusError> {
+    let mut shared: Vec<usize> = Vec::new();
+    let mut pending = vec![index];
+    while let Some(index) = pending.pop() {
+        for id in doc.questions[index].shares.iter() {
+            let found = shared_question(doc, index, id)?;
+            shared.push(found);
+            pending.push(found);
+        }
+    }
+    Ok(shared)
+}
+
+//The scope of each question is generated once per test, so questions sharing it get the same values even if it comes later in the test or isn't used
+#[allow(clippy::too_many_arguments)]
+fn shared_scope<'a, R: Rng>(scope: &mut HashMap<&'a str,Num>, doc: &'a Document, index: usize, globals: &HashMap<&'a str,Num>, assignment: &Assignment, scopes: &mut HashMap<usize, HashMap<&'a str,Num>>, options: &GenerateOptions, rng: &mut R) -> Result<(), MorphiusError> {
+    if let Some(shared) = scopes.get(&index) {
+        scope.clone_from(shared);
+        return Ok(());
+    }
+    let question = &doc.questions[index];
+    let fixed = assigned(assignment, Some(index));
+    if question.shares.is_empty() {
+        gen_question_scope(scope, question, globals, &fixed, options, rng)?;
+    } else {
+        let mut base = globals.clone();
+        let mut shared = HashMap::new();
+        for id in question.shares.iter() {
+            shared_scope(&mut shared, doc, shared_question(doc, index, id)?, globals, assignment, scopes, options, rng)?;
+            base.extend(shared.iter().map(|(name, value)| (*name, *value)));
+        }
+        gen_question_scope(scope, question, &base, &fixed, options, rng)?;
+    }
+    //Only a question with an id can be shared, so the scopes of the other questions don't need to be kept
+    if question.id.is_some() {
+        scopes.insert(index, scope.clone());
+    }
+    Ok(())
+}
+
+//The scope of a hint with shares has the variables of the questions it shares, with the variables of its own question taking their place
+#[allow(clippy::too_many_arguments)]
+fn hint_scopes<'a, R: Rng>(doc: &'a Document, index: usize, scope: &HashMap<&'a str,Num>, globals: &HashMap<&'a str,Num>, assignment: &Assignment, scopes: &mut HashMap<usize, HashMap<&'a str,Num>>, options: &GenerateOptions, rng: &mut R) -> Result<Vec<Option<HashMap<&'a str,Num>>>, MorphiusError> {
+    let mut hint_scopes = Vec::new();
+    for hint in doc.questions[index].hints.iter() {
+        if hint.shares.is_empty() {
+            hint_scopes.push(None);
+            continue;
+        }
+        let mut hint_scope = HashMap::new();
+        let mut shared = HashMap::new();
+        for id in hint.shares.iter() {
+            shared_scope(&mut shared, doc, shared_question(doc, index, id)?, globals, assignment, scopes, options, rng)?;
+            hint_scope.extend(shared.iter().map(|(name, value)| (*name, *value)));
+        }
+        hint_scope.extend(scope.iter().map(|(name, value)| (*name, *value)));
+        hint_scopes.push(Some(hint_scope));
+    }
+    Ok(hint_scopes)
+}
+
+fn gen_question_text<'a, R: Rng>(question: &'a Question, index: usize, globals: &HashMap<&'a str,Num>, fixed: &HashMap<&str,Num>, options: &GenerateOptions, rng: &mut R) -> Result<TestQuestion, MorphiusError> {
+    let mut scope = HashMap::new();
+    gen_question_scope(&mut scope, question, globals, fixed, options, rng)?;
+    gen_question_content(question, index, &scope, &[], options, true, None, rng)
+}
+
+fn gen_question_scope<'a, R: Rng>(scope: &mut HashMap<&'a str,Num>, question: &'a Question, globals: &HashMap<&'a str,Num>, fixed: &HashMap<&str,Num>, options: &GenerateOptions, rng: &mut R) -> Result<(), MorphiusError> {
+    gen_scope(scope, &question.vars, globals, fixed, options, rng)?;
+    //A partition whose values are all fixed keeps them
+    for partition in question.partitions.iter().filter(|partition| !partition.names.iter().all(|name| fixed.contains_key(&name[..]))) {
+        for (name, value) in partition.names.iter().zip(gen_partition(partition.names.len(), partition.total, rng)) {
+            scope.insert(&name[..], Num::Int(value));
+        }
+    }
+    for choice in question.choices.iter() {
+        let pos = match fixed.get(&choice.name[..]) {
+            Some(pos) => pos.value() as usize,
+            None => {
+                //Only try_process rejects weights that can't be chosen from, so a Document from process or a DocumentBuilder can still have them
+                let weights = rand::distributions::WeightedIndex::new(choice.options.iter().map(|(_, weight)| *weight)).map_err(|_| MorphiusError::InvalidChoice {
+                    name: choice.name.clone(),
+                    values: choice.options.iter().map(|(value, weight)| format!("{}:{}", value, format_number(*weight))).join(", ")
+                })?;
+                rng.sample(weights)
+            }
+        };
+        let value = &choice.options[pos].0;
+        let value = match (numeric_choice(choice), value.parse::<i64>()) {
+            (true, Ok(value)) => Num::Int(value),
+            (true, Err(_)) => Num::Real(value.parse().unwrap()),
+            (false, _) => Num::Int(pos as i64)
+        };
+        scope.insert(&choice.name[..], value);
+    }
+    for table in question.tables.iter() {
+        let row = &table.rows[scope[&table.name[..]].value() as usize];
+        for (column, value) in table.columns.iter().zip(row.iter()) {
+            if let Ok(value) = value.parse::<i64>() {
+                scope.insert(&column[..], Num::Int(value));
+            } else if let Ok(value) = value.parse::<f64>() {
+                scope.insert(&column[..], Num::Real(value));
+            }
+        }
+    }
+    Ok(())
+}
+
+//The correct part of the question is moved to the letter for the slot, if one is given
+//Each hint with shares is generated with its scope in hint_scopes, and the other hints with the scope of the question
+#[allow(clippy::too_many_arguments)]
+fn gen_question_content<'a, R: Rng>(question: &'a Question, index: usize, scope: &HashMap<&'a str,Num>, hint_scopes: &[Option<HashMap<&'a str,Num>>], options: &GenerateOptions, with_content: bool, slot: Option<usize>, rng: &mut R) -> Result<TestQuestion, MorphiusError> {
+    //A hint that shares the variables of other questions draws its calls with them
+    let mut draws = HashMap::new();
+    let hint_scope = |pos: usize| hint_scopes.get(pos).and_then(Option::as_ref).unwrap_or(scope);
+    gen_draws(&mut draws, &question.expressions.iter().chain(question.parts.iter().flat_map(|part| part.expressions.iter())).collect::<Vec<&Expression>>(), scope, index, rng)?;
+    for (pos, hint) in question.hints.iter().enumerate() {
+        gen_draws(&mut draws, &hint.expressions.iter().collect::<Vec<&Expression>>(), hint_scope(pos), index, rng)?;
+    }
+    if let Some(answer) = &question.answer {
+        gen_draws(&mut draws, &answer_expressions(answer), scope, index, rng)?;
+    }
+    let picks = gen_picks(&question_expressions(question), rng);
+    let mut texts = HashMap::new();
+    for table in question.tables.iter() {
+        let row = &table.rows[scope[&table.name[..]].value() as usize];
+        texts.extend(table.columns.iter().zip(row.iter()).map(|(column, value)| (&column[..], &value[..])).filter(|(name, _)| !scope.contains_key(name)));
+    }
+    for choice in question.choices.iter().filter(|choice| !numeric_choice(choice)) {
+        texts.insert(&choice.name[..], &choice.options[scope[&choice.name[..]].value() as usize].0[..]);
+    }
+    let mut eval = Eval { question: index, scope, draws, picks, options, in_answer: false, texts };
+
+    let mut order: Vec<usize> = (0..question.parts.len()).collect();
+    order.shuffle(rng);
+    if let (Some(slot), Some(correct)) = (slot, order.iter().position(|i| question.parts[*i].correct)) {
+        let len = order.len();
+        order.swap(correct, slot % len);
+    }
+    //Parts that show the same text would be the same option twice, so this is checked even when only the answer is generated,
+    //which keeps the answer key the same as the answers of the whole test
+    let mut shown = HashSet::new();
+    for part in question.parts.iter().filter(|_| question.parts.len() > 1) {
+        let text = gen_text(&part.layout, &part.expressions, &eval)?;
+        if !shown.insert(text.clone()) {
+            return Err(MorphiusError::DuplicateParts { text, question: index });
+        }
+    }
+
+    let content = if with_content {
+        let content = gen_parts_text(&gen_text(&question.layout, &question.expressions, &eval)?, &question.parts, &order, &[], &eval)?;
+        let content = content.replace(POINTS, &question.points.map(format_number).unwrap_or_default());
+        if content.contains(GIVEN) { content.replace(GIVEN, &given_text(question, &eval)) } else { content }
+    } else {
+        String::new()
+    };
+
+    eval.in_answer = true;
+    let forms = match &question.answer {
+        Some(answer) => gen_answer_forms(answer, &question.parts, &order, &eval)?,
+        None => Vec::new()
+    };
+    let answer = if forms.is_empty() { options.missing_answer.clone().unwrap_or_default() } else { forms.join(" or ") };
+    //The tolerance of the first form that has one is used for all of them
+    let tolerance = question.answer.as_ref().and_then(|answer| answer_forms(answer, &eval).into_iter().find_map(|form| form.tolerance));
+    eval.in_answer = false;
+
+    let mut hints = Vec::new();
+    for (pos, hint) in question.hints.iter().enumerate().filter(|_| with_content) {
+        eval.scope = hint_scope(pos);
+        hints.push(gen_text(&hint.layout, &hint.expressions, &eval)?);
+    }
+    eval.scope = scope;
+    let hint = hints.join("\n");
+
+    let values = question.vars.iter().map(|var| (var.name.clone(), eval.scope[&var.name[..]].value())).collect();
+    let mut named = HashMap::new();
+    for expression in question_expressions(question) {
+        if let Some(name) = &expression.name {
+            named.insert(name.clone(), gen_expression_text(expression, &eval)?);
+        }
+    }
+    let warnings = match &question.answer {
+        Some(answer) => answer_warnings(answer, &eval)?,
+        None => Vec::new()
+    };
+    let correct = order.iter().position(|i| question.parts[*i].correct).map(part_label);
+    Ok(TestQuestion { index, content, answer, forms, tolerance, hint, values, named, points: question.points, attempts: question.attempts, correct, warnings })
+}
+
+fn answer_warnings(answer: &Answer, eval: &Eval) -> Result<Vec<MorphiusError>, MorphiusError> {
+    let mut warnings = Vec::new();
+    for form in answer_forms(answer, eval) {
+        if let Some((min, max)) = form.range {
+            let expressions = form.expressions.iter().chain(form.parts.iter().flat_map(|part| part.expressions.iter()));
+            for expression in expressions.filter(|expression| !matches!(expression.mode, ExpMode::Pick | ExpMode::Text)) {
+                let values = if has_literals(expression) {
+                    split_literals(expression).1.iter().map(|piece| expression_value(piece, eval)).collect::<Result<Vec<f64>, _>>()?
+                } else {
+                    vec![expression_value(expression, eval)?]
+                };
+                for value in values.into_iter().filter(|value| *value < min || *value > max) {
+                    warnings.push(MorphiusError::AnswerOutOfRange { question: eval.question, value: format_number(value), min: format_number(min), max: format_number(max) });
+                }
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+//The order is the order of the parts of the question, which the parts of the answer and its alternatives follow when they have as many
+//The points of the question parts are only shown when the answer parts match them
+fn gen_answer_forms(answer: &Answer, question_parts: &[Part], order: &[usize], eval: &Eval) -> Result<Vec<String>, MorphiusError> {
+    let used = answer_forms(answer, eval);
+    let mut forms = Vec::with_capacity(used.len());
+    for form in used {
+        let matched = form.parts.len() == order.len();
+        let form_order: Vec<usize> = if matched { order.to_vec() } else { (0..form.parts.len()).collect() };
+        let marks = if matched { question_parts } else { &[] };
+        forms.push(gen_parts_text(&gen_text(&form.layout, &form.expressions, eval)?, &form.parts, &form_order, marks, eval)?);
+    }
+    Ok(forms)
+}
+
+//The forms whose condition matches the values of the question are used, or the forms without a condition if none of them match
+fn answer_forms<'a>(answer: &'a Answer, eval: &Eval) -> Vec<&'a Answer> {
+    let forms = std::iter::once(answer).chain(answer.alternatives.iter());
+    let matches = |when: &str| match when.split_once(':') {
+        Some((name, value)) => eval.texts.get(name) == Some(&value) || eval.scope.get(name).zip(value.parse::<f64>().ok()).is_some_and(|(num, value)| num.value() == value),
+        None => eval.texts.values().any(|text| *text == when)
+    };
+    let matching: Vec<&Answer> = forms.clone().filter(|form| form.when.as_deref().is_some_and(matches)).collect();
+    if matching.is_empty() { forms.filter(|form| form.when.is_none()).collect() } else { matching }
+}
+
+fn gen_scope<'a, R: Rng>(scope: &mut HashMap<&'a str,Num>, vars: &'a HashSet<Var>, globals: &HashMap<&'a str,Num>, fixed: &HashMap<&str,Num>, options: &GenerateOptions, rng: &mut R) -> Result<(), MorphiusError> {
+    //The values of the samplers are used like values chosen for the variables, so a chosen value still comes first
+    let mut fixed: HashMap<&str,Num> = fixed.iter().map(|(name, value)| (*name, *value)).collect();
+    if !options.samplers.is_empty() {
+        for var in vars.iter().filter(|var| !fixed.contains_key(&var.name[..])).sorted_by(|a, b| a.name.cmp(&b.name)) {
+            if let Some(sampler) = options.samplers.get(&var.name) {
+                let value = sampler(&var.name, rng);
+                fixed.insert(&var.name[..], if var.num_type == "int" { Num::Int(value.round() as i64) } else { Num::Real(value) });
+            }
+        }
+    }
+    let mut empty = None;
+    for _ in 0..DEPENDENT_RETRIES {
+        check_deadline(options.deadline)?;
+        empty = gen_scope_values(scope, vars, globals, &fixed, rng)?;
+        if empty.is_none() {
+            return Ok(());
+        }
+    }
+    Err(empty.unwrap())
+}
+
+fn check_deadline(deadline: Option<Instant>) -> Result<(), MorphiusError> {
+    match deadline {
+        Some(deadline) if Instant::now() >= deadline => Err(MorphiusError::DeadlineExceeded),
+        _ => Ok(())
+    }
+}
+
+//This returns the error for a variable whose bounds depend on other variables if its range was empty, so the variables can be sampled again
+fn gen_scope_values<'a, R: Rng>(scope: &mut HashMap<&'a str,Num>, vars: &'a HashSet<Var>, globals: &HashMap<&'a str,Num>, fixed: &HashMap<&str,Num>, rng: &mut R) -> Result<Option<MorphiusError>, MorphiusError> {
+    scope.clear();
+    scope.extend(globals.iter().map(|(name, value)| (*name, *value)));
+    //Variables are sampled in name order so that a seeded rng always gives the same values, and variables with bounds
+    //depending on other variables are sampled once those variables have values
+    let (mut dependent, independent): (Vec<&Var>, Vec<&Var>) = vars.iter().sorted_by(|a, b| a.name.cmp(&b.name)).partition(|var| is_dependent(var));
+    for var in independent {
+        let value = gen_scope_value(var, globals, fixed, rng)?;
+        scope.insert(&var.name[..], value);
+    }
+    while !dependent.is_empty() {
+        let ready = dependent.iter().position(|var| bound_names(var).iter().all(|name| scope.contains_key(name)));
+        let var = match ready {
+            Some(pos) => dependent.remove(pos),
+            None => return Err(MorphiusError::InvalidBounds { name: dependent[0].name.clone(), min: dependent[0].min.clone(), max: dependent[0].max.clone() })
+        };
+        let bounds = dependent_bounds(var, scope)?;
+        if validate_bounds(&bounds).is_err() {
+            return Ok(Some(MorphiusError::InvalidBounds { name: var.name.clone(), min: bounds.min, max: bounds.max }));
+        }
+        let value = gen_scope_value(&bounds, globals, fixed, rng)?;
+        scope.insert(&var.name[..], value);
+    }
+    Ok(None)
+}
+
+fn gen_scope_value<R: Rng>(var: &Var, globals: &HashMap<&str,Num>, fixed: &HashMap<&str,Num>, rng: &mut R) -> Result<Num, MorphiusError> {
+    //A fixed value replaces the sampled value, so for a linked variable it is the offset from its base
+    let value = match fixed.get(&var.name[..]) {
+        Some(value) => *value,
+        None => gen_var_value(var, rng)?
+    };
+    let base = match &var.base {
+        Some(base) => Some(globals.get(&base[..]).ok_or_else(|| MorphiusError::MissingVariable { name: base.clone() })?),
+        None => None
+    };
+    Ok(match base {
+        Some(Num::Int(base)) => match value {
+            Num::Int(offset) => Num::Int(base + offset),
+            Num::Real(offset) => Num::Real(*base as f64 + offset)
+        },
+        Some(Num::Real(base)) => match value {
+            Num::Int(offset) => Num::Real(base + offset as f64),
+            Num::Real(offset) => Num::Real(base + offset)
+        },
+        None => value
+    })
+}
+
+fn is_dependent(var: &Var) -> bool {
+    var.base.is_none() && (var.min.parse::<f64>().is_err() || var.max.parse::<f64>().is_err())
+}
+
+fn bound_names(var: &Var) -> Vec<&str> {
+    lazy_static! {
+        static ref VAR: Regex = Regex::new(&format!(r"({})\s*(\()?", IDENT)).unwrap();
+    }
+    [&var.min, &var.max].iter().flat_map(|bound| VAR.captures_iter(bound).filter(|cap| cap.get(2).is_none()).map(|cap| cap.get(1).unwrap().as_str())).collect()
+}
+
+//This gives a variable with the bounds of var evaluated using the values in scope, and clamped to its limits
+fn dependent_bounds(var: &Var, scope: &HashMap<&str,Num>) -> Result<Var, MorphiusError> {
+    lazy_static! {
+        static ref VAR: Regex = Regex::new(IDENT).unwrap();
+    }
+    let evaluate = |bound: &str| -> Result<f64, MorphiusError> {
+        let expr = VAR.replace_all(bound, |cap: &regex::Captures| match scope.get(&cap[0]) {
+            Some(value) => format!("({})", value.value()),
+            None => String::from(&cap[0])
+        });
+        eval_number(&expr)
+    };
+    let (mut min, mut max) = (evaluate(&var.min)?, evaluate(&var.max)?);
+    if let Some((low, high)) = &var.clamp {
+        let invalid = || MorphiusError::InvalidBounds { name: var.name.clone(), min: low.clone(), max: high.clone() };
+        min = min.max(low.parse::<f64>().map_err(|_| invalid())?);
+        max = max.min(high.parse::<f64>().map_err(|_| invalid())?);
+    }
+    Ok(Var { name: var.name.clone(), num_type: var.num_type.clone(), min: min.to_string(), max: max.to_string(), base: None, grain: var.grain.clone(), clamp: None, set: var.set.clone(), cycle: var.cycle.clone() })
+}
+
+fn gen_partition<R: Rng>(count: usize, total: i64, rng: &mut R) -> Vec<i64> {
+    if count == 0 {
+        return Vec::new();
+    }
+    //Choosing the positions of count - 1 bars among total + count - 1 slots gives every split of the total the same chance
+    let slots = total as usize + count - 1;
+    let mut bars: Vec<usize> = rand::seq::index::sample(rng, slots, count - 1).into_vec();
+    bars.sort_unstable();
+    let mut values = Vec::with_capacity(count);
+    let mut previous = 0;
+    for bar in bars.iter().chain(std::iter::once(&slots)) {
+        values.push((bar - previous) as i64);
+        previous = bar + 1;
+    }
+    values
+}
+
+fn gen_var_value<R: Rng>(var: &Var, rng: &mut R) -> Result<Num, MorphiusError> {
+    validate_bounds(var)?;
+    let (min, max, grain) = var_bounds(var)?;
+    //Without the index of the test there is no position in the cycle, so any of its values can be used
+    if var.cycle.is_some() {
+        return Ok(*cycle_values(var).choose(rng).unwrap());
+    }
+    if var.set.is_some() {
+        return match set_range(var, min, max) {
+            SetRange::Primes { min, max } => {
+                //A large range always has primes, about one in every 44 numbers or more, so this only fails if the rng is broken
+                (0..PRIME_SPAN).map(|_| rng.gen_range(min..=max)).find(|n| is_prime(*n)).map(|n| set_num(var, n))
+                    .ok_or_else(|| MorphiusError::InvalidBounds { name: var.name.clone(), min: var.min.clone(), max: var.max.clone() })
+            },
+            //This draws the position the same way choosing from a list of the members does
+            range => {
+                let count = range.count().unwrap();
+                let pos = if count <= u32::MAX as u64 { rng.gen_range(0..count as u32) as u64 } else { rng.gen_range(0..count) };
+                Ok(set_num(var, range.member(pos)))
+            }
+        };
+    }
+    if let Some(grain) = grain {
+        let (first, last) = grain_steps(min, max, grain);
+        let value = grain_value(rng.gen_range(first..=last), grain);
+        return Ok(if var.num_type == "int" { Num::Int(value.round() as i64) } else { Num::Real(value) });
+    }
+    Ok(if var.num_type == "int" {
+        Num::Int(rng.gen_range(min.ceil() as i64..=max.floor() as i64))
+    } else if min < max {
+        //Reals are kept to three decimal places, or three significant digits of the range width for narrow ranges
+        let step = 10f64.powf((max - min).log10().floor() - 3.0).min(0.001);
+        let value = (rng.gen_range(min..max) / step).round() * step;
+        Num::Real(value.clamp(min, max))
+    } else {
+        Num::Real(min)
+    })
+}
+
+fn gen_draws<R: Rng>(draws: &mut HashMap<(String, usize), f64>, expressions: &[&Expression], scope: &HashMap<&str,Num>, question: usize, rng: &mut R) -> Result<(), MorphiusError> {
+    for expression in expressions.iter().filter(|expression| calls(expression, &["rand", "randint"])) {
+        let substituted = substitute_vars(expression, scope, question)?;
+        for (cap, key) in random_call_regex().captures_iter(&substituted).zip(random_calls(&substituted)) {
+            if draws.contains_key(&key) {
+                continue;
+            }
+            let value = match (cap.get(1), cap.get(2)) {
+                (Some(min), Some(max)) => {
+                    let min = eval_number(min.as_str())?.ceil() as i64;
+                    let max = eval_number(max.as_str())?.floor() as i64;
+                    rng.gen_range(min..=cmp::max(min, max)) as f64
+                },
+                _ => rng.gen::<f64>()
+            };
+            draws.insert(key, value);
+        }
+    }
+    Ok(())
+}
+
+//Calls are identified by their text once the variables are filled in and by how many times the expression already made the same call.
+//So the nth call written the same way in any expression of a question gives the same value, while repeating a call in one expression
+//draws it again
+fn random_calls(expr: &str) -> Vec<(String, usize)> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    random_call_regex().find_iter(expr).map(|mat| {
+        let count = seen.entry(mat.as_str()).or_insert(0);
+        *count += 1;
+        (String::from(mat.as_str()), *count - 1)
+    }).collect()
+}
+
+fn gen_picks<R: Rng>(expressions: &[&Expression], rng: &mut R) -> HashMap<String, String> {
+    let mut picks: HashMap<String, String> = HashMap::new();
+    for expression in expressions.iter().filter(|expression| expression.mode == ExpMode::Pick) {
+        //Like random calls, a pick written the same way anywhere in the question gives the same alternative
+        if let Entry::Vacant(entry) = picks.entry(expression_source(expression)) {
+            let pick = String::from(*entry.key().split('|').collect::<Vec<&str>>().choose(rng).unwrap());
+            entry.insert(pick);
+        }
+    }
+    picks
+}
+
+//Most expressions don't call these functions, so checking the parsed expression first avoids searching its text
+fn calls(expression: &Expression, functions: &[&str]) -> bool {
+    expression.expression.iter().any(|exp_comp| matches!(exp_comp, ExpComp::Func(name) if functions.contains(&&name[..])))
+}
+
+fn random_call_regex() -> &'static Regex {
+    lazy_static! {
+        static ref RANDOM_CALL: Regex = Regex::new(r"\brand\(\s*\)|\brandint\(([^(),]*),([^(),]*)\)").unwrap();
+    }
+    &RANDOM_CALL
+}
+
+fn apply_mod(expr: &str) -> Result<String, MorphiusError> {
+    lazy_static! {
+        static ref MOD: Regex = Regex::new(r"\bmod\s*\(").unwrap();
+    }
+    //mexprp doesn't have integer modulo, so calls are replaced by their value. The arguments are found by matching parentheses like in
+    //to_radians, so they can hold other calls, and calls of mod inside them are replaced first
+    let mut result = String::new();
+    let mut last = 0;
+    for mat in MOD.find_iter(expr) {
+        if mat.start() < last {
+            continue;
+        }
+        let mut depth = 1;
+        let mut comma = None;
+        let end = expr[mat.end()..].char_indices().find(|(i, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 1 => { comma.get_or_insert(mat.end() + i); },
+                _ => ()
+            }
+            depth == 0
+        }).map(|(i, _)| mat.end() + i);
+        let call = &expr[mat.start()..end.map_or(expr.len(), |end| end + 1)];
+        let error = |message: &str| MorphiusError::Math { expression: String::from(call), message: String::from(message) };
+        let (Some(end), Some(comma)) = (end, comma) else {
+            return Err(error("mod needs two numbers"));
+        };
+        let a = eval_number(&apply_mod(&expr[mat.end()..comma])?)?.round() as i64;
+        let b = eval_number(&apply_mod(&expr[comma + 1..end])?)?.round() as i64;
+        if b == 0 {
+            return Err(error("Attempted to divide by zero"));
+        }
+        result += &expr[last..mat.start()];
+        result += &a.rem_euclid(b).to_string();
+        last = end + 1;
+    }
+    Ok(result + &expr[last..])
+}
+
+//mexprp measures angles in radians, so the arguments of sin, cos and tan are converted from degrees and the results of the inverse
+//functions are converted to degrees
+fn to_radians(expr: &str) -> String {
+    lazy_static! {
+        static ref TRIG: Regex = Regex::new(r"\b(asin|acos|atant|atan|sin|cos|tan)\s*\(").unwrap();
+    }
+    let mut result = String::new();
+    let mut last = 0;
+    for mat in TRIG.find_iter(expr) {
+        if mat.start() < last {
+            continue;
+        }
+        //The argument ends at the parenthesis closing the call, or at the end of the expression if it is never closed
+        let mut depth = 1;
+        let end = expr[mat.end()..].char_indices().find(|(_, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => ()
+            }
+            depth == 0
+        }).map_or(expr.len(), |(i, _)| mat.end() + i);
+        let name = mat.as_str().trim_end_matches('(').trim_end();
+        let argument = to_radians(&expr[mat.end()..end]);
+        result += &expr[last..mat.start()];
+        result += &match name {
+            "sin" | "cos" | "tan" => format!("{}(({})*{})", name, argument, std::f64::consts::PI / 180.0),
+            _ => format!("({}({})*{})", name, argument, 180.0 / std::f64::consts::PI)
+        };
+        last = cmp::min(end + 1, expr.len());
+    }
+    result + &expr[last..]
+}
+
+fn nesting_depth(expr: &str) -> usize {
+    let mut depth: usize = 0;
+    let mut deepest = 0;
+    for c in expr.chars() {
+        match c {
+            '(' => {
+                depth += 1;
+                deepest = cmp::max(deepest, depth);
+            },
+            ')' => depth = depth.saturating_sub(1),
+            _ => ()
+        }
+    }
+    deepest
+}
+
+fn eval_number(expr: &str) -> Result<f64, MorphiusError> {
+    let error = |message: String| MorphiusError::Math { expression: String::from(expr), message };
+    match mexprp::eval::<f64>(expr).map_err(|e| error(e.to_string()))? {
+        mexprp::Answer::Single(num) => Ok(num),
+        mexprp::Answer::Multiple(_) => Err(error(String::from("The expression has more than one result")))
+    }
+}
+
+fn cycle_values(var: &Var) -> Vec<Num> {
+    var.cycle.iter().flatten().map(|value| {
+        let value: f64 = value.parse().unwrap_or_default();
+        if var.num_type == "int" { Num::Int(value.round() as i64) } else { Num::Real(value) }
+    }).collect()
+}
+
+//The variables cycling through lists of values are fixed to the value for the position of the test in the batch
+fn cycle_assignment(doc: &Document, test: usize) -> Assignment<'_> {
+    let globals = doc.globals.iter().map(|var| (None, var));
+    let questions = doc.questions.iter().enumerate().flat_map(|(index, question)| question.vars.iter().map(move |var| (Some(index), var)));
+    globals.chain(questions).filter(|(_, var)| var.cycle.is_some()).map(|(index, var)| {
+        let values = cycle_values(var);
+        ((index, &var.name[..]), values[test % values.len()])
+    }).collect()
+}
+
+//Primes are listed in ranges up to this size and drawn by testing random numbers in larger ones
+const PRIME_SPAN: i64 = 100_000;
+
+//The members of a special set in a range, which are counted and found by their position instead of being listed, except for primes in small ranges
+enum SetRange {
+    //The members are first, first + step, first + 2 * step and so on
+    Steps { first: i64, step: i64, count: u64 },
+    //The members are the powers of first, first + 1 and so on
+    Powers { first: i64, power: u32, count: u64 },
+    Listed(Vec<i64>),
+    //The primes of a range too large to list
+    Primes { min: i64, max: i64 }
+}
+
+impl SetRange {
+    //The primes of a large range aren't counted
+    fn count(&self) -> Option<u64> {
+        match self {
+            SetRange::Steps { count, .. } | SetRange::Powers { count, .. } => Some(*count),
+            SetRange::Listed(members) => Some(members.len() as u64),
+            SetRange::Primes { .. } => None
+        }
+    }
+
+    fn member(&self, pos: u64) -> i64 {
+        match self {
+            SetRange::Steps { first, step, .. } => (*first as i128 + *step as i128 * pos as i128) as i64,
+            SetRange::Powers { first, power, .. } => (first + pos as i64).pow(*power),
+            SetRange::Listed(members) => members[pos as usize],
+            SetRange::Primes { .. } => unreachable!()
+        }
+    }
+}
+
+//The members of the special set of var between min and max
+fn set_range(var: &Var, min: f64, max: f64) -> SetRange {
+    let (min, max) = (min.ceil().max(i64::MIN as f64) as i64, max.floor().min(i64::MAX as f64) as i64);
+    let steps = |first: i64| SetRange::Steps { first, step: 2, count: if first > max { 0 } else { ((max as i128 - first as i128) / 2 + 1) as u64 } };
+    let powers = |power: u32| {
+        let fits = |root: i64| (root as i128).pow(power) <= max as i128;
+        let mut first = (min.max(0) as f64).powf(1.0 / power as f64).floor() as i64;
+        while (first as i128).pow(power) < min as i128 {
+            first += 1;
+        }
+        let mut last = (max.max(0) as f64).powf(1.0 / power as f64).floor() as i64;
+        while last > 0 && !fits(last) {
+            last -= 1;
+        }
+        while fits(last + 1) {
+            last += 1;
+        }
+        SetRange::Powers { first, power, count: if max < 0 || first > last { 0 } else { (last - first + 1) as u64 } }
+    };
+    match var.set.as_deref() {
+        Some("prime") if max as i128 - (min.max(2) as i128) < PRIME_SPAN as i128 => SetRange::Listed((min.max(2)..=max).filter(|n| is_prime(*n)).collect()),
+        Some("prime") => SetRange::Primes { min: min.max(2), max },
+        Some("square") => powers(2),
+        Some("cube") => powers(3),
+        Some("even") => steps(min.saturating_add(min.rem_euclid(2))),
+        Some("odd") => steps(min.saturating_add(1 - min.rem_euclid(2))),
+        _ => SetRange::Listed(Vec::new())
+    }
+}
+
+fn set_num(var: &Var, value: i64) -> Num {
+    if var.num_type == "int" { Num::Int(value) } else { Num::Real(value as f64) }
+}
+
+//This is the Miller-Rabin test with bases that are enough to be exact for every 64 bit number
+fn is_prime(n: i64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    let n = n as u64;
+    if let Some(base) = BASES.iter().find(|base| n.is_multiple_of(**base)) {
+        return n == *base;
+    }
+    let mul = |a: u64, b: u64| (a as u128 * b as u128 % n as u128) as u64;
+    let pow = |mut base: u64, mut exp: u64| {
+        let mut result = 1;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = mul(result, base);
+            }
+            base = mul(base, base);
+            exp >>= 1;
+        }
+        result
+    };
+    let (d, s) = ((n - 1) >> (n - 1).trailing_zeros(), (n - 1).trailing_zeros());
+    BASES.iter().all(|base| {
+        let mut x = pow(*base, d);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = mul(x, x);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+fn grain_steps(min: f64, max: f64, grain: f64) -> (i64, i64) {
+    ((min / grain).ceil() as i64, (max / grain).floor() as i64)
+}
+
+fn grain_value(step: i64, grain: f64) -> f64 {
+    //Rounding to the decimal places of the grain removes floating point noise like 0.30000000000000004
+    let decimals = grain.to_string().split_once('.').map(|(_, decimals)| decimals.len()).unwrap_or(0);
+    let scale = 10f64.powi(decimals as i32);
+    (step as f64 * grain * scale).round() / scale
+}
+
+fn gen_text(layout: &[String], expressions: &[Expression], eval: &Eval) -> Result<String, MorphiusError> {
+    let mut text = String::new();
+    for (i, segment) in layout.iter().enumerate() {
+        text.push_str(segment);
+        if let Some(expression) = expressions.get(i) {
+            text.push_str(&gen_expression_text(expression, eval)?);
+        }
+    }
+    Ok(text)
+}
+
+//Each part is followed by the points of the part of marks with the same index, if it has them
+fn gen_parts_text(text: &str, parts: &[Part], order: &[usize], marks: &[Part], eval: &Eval) -> Result<String, MorphiusError> {
+    if parts.is_empty() {
+        return Ok(String::from(text));
+    }
+    let parts_text: Vec<String> = order.iter().enumerate().map(|(pos, i)| {
+        let points = marks.get(*i).and_then(|part| part.points).map_or(String::new(), |points| format!(" [{} {}]", format_number(points), if points == 1.0 { "pt" } else { "pts" }));
+        Ok(format!("{}) {}{}", part_label(pos), gen_text(&parts[*i].layout, &parts[*i].expressions, eval)?, points))
+    }).collect::<Result<_, MorphiusError>>()?;
+    Ok(text.split(PART_SLOT).map(String::from).interleave(parts_text).join(""))
+}
+
+//The variables are listed in name order, leaving out the indices of the rows of tables, and choices that aren't numbers are shown as their text
+fn given_text(question: &Question, eval: &Eval) -> String {
+    question.vars.iter().filter(|var| !question.tables.iter().any(|table| table.name == var.name)).sorted_by(|a, b| a.name.cmp(&b.name)).map(|var| {
+        let value = eval.texts.get(&var.name[..]).map_or_else(|| format_number(eval.scope[&var.name[..]].value()), |text| String::from(*text));
+        format!("{} = {}", var.name, value)
+    }).join(", ")
+}
+
+fn part_label(pos: usize) -> char {
+    char::from(b'a' + (pos % 26) as u8)
+}
+
+fn gen_expression_text(expression: &Expression, eval: &Eval) -> Result<String, MorphiusError> {
+    match expression.mode {
+        ExpMode::Pick => return Ok(pad(eval.picks[&expression_source(expression)].clone(), expression)),
+        ExpMode::Text => return Ok(pad(expression_source(expression), expression)),
+        _ => ()
+    }
+    if expression.mode == ExpMode::Symbolic && !eval.in_answer {
+        return Ok(pad(symbolic_text(expression, eval)?, expression));
+    }
+    if has_literals(expression) {
+        let (layout, expressions) = split_literals(expression);
+        return Ok(pad(gen_text(&layout, &expressions, eval)?, expression));
+    }
+    //A column of a table that isn't a number is shown as it is, but can't be used in math
+    if let Some(text) = eval.texts.get(expression_source(expression).trim()) {
+        return Ok(pad(String::from(*text), expression));
+    }
+    let num = expression_value(expression, eval)?;
+    //A worked step keeps the formula and the values filled into it in front of the result
+    let shown = |text: String| -> Result<String, MorphiusError> {
+        if expression.mode != ExpMode::Show {
+            return Ok(text);
+        }
+        Ok(format!("{} = {} = {}", expression_source(expression).trim(), symbolic_text(expression, eval)?.trim(), text))
+    };
+    if comparison(&expression_source(expression)).is_some() {
+        let text = if num != 0.0 { &eval.options.true_text } else { &eval.options.false_text };
+        return Ok(pad(shown(text.clone())?, expression));
+    }
+    let uses_real = expression.expression.iter().any(|exp_comp| matches!(exp_comp, ExpComp::Var(name) if matches!(eval.scope.get(&name[..]), Some(Num::Real(_)))));
+    let round = |num: f64, decimals: usize| eval.options.rounding.map_or(num, |rounding| round_decimal(num, decimals, rounding));
+    let text = match (&expression.mode, eval.options.real_decimals) {
+        (ExpMode::Number | ExpMode::Show, Some(decimals)) if uses_real => {
+            let decimals = eval.options.max_decimals.map_or(decimals, |max| cmp::min(decimals, max));
+            format!("{:.*}", decimals, round(num, decimals) + 0.0)
+        },
+        (ExpMode::Number, _) | (ExpMode::Symbolic, _) | (ExpMode::Show, _) => match eval.options.max_decimals {
+            //Adding 0 turns -0 into 0 so that small negative numbers aren't shown as -0
+            Some(max) if eval.options.rounding.is_some() => format_number(round(num, max) + 0.0),
+            Some(max) => format_number((num * 10f64.powi(max as i32)).round() / 10f64.powi(max as i32) + 0.0),
+            //Numbers shown in scientific notation keep their significant digits instead
+            None if (1e-3..1e15).contains(&num.abs()) => format_number(round(num, 3) + 0.0),
+            None => format_number(num)
+        },
+        (ExpMode::Currency, _) => format_currency(round(num, 2), eval.options),
+        (ExpMode::Pick, _) | (ExpMode::Text, _) => unreachable!()
+    };
+    Ok(pad(shown(text)?, expression))
+}
+
+fn expression_value(expression: &Expression, eval: &Eval) -> Result<f64, MorphiusError> {
+    let mut expr = substitute_vars(expression, eval.scope, eval.question)?;
+    if calls(expression, &["rand", "randint"]) {
+        let mut draws = random_calls(&expr).into_iter().map(|key| eval.draws[&key]).collect::<Vec<f64>>().into_iter();
+        expr = random_call_regex().replace_all(&expr, |_: &regex::Captures| format!("({})", draws.next().unwrap_or_default())).into_owned();
+    }
+    if calls(expression, &["mod"]) {
+        expr = apply_mod(&expr)?;
+    }
+    if expression.degrees || eval.options.degrees {
+        expr = to_radians(&expr);
+    }
+    if expr.len() > eval.options.max_expression_length || nesting_depth(&expr) > eval.options.max_expression_depth {
+        return Err(MorphiusError::TooComplex { expression: expr, question: eval.question });
+    }
+    match comparison(&expr) {
+        Some((pos, operator)) => {
+            let (left, right) = (eval_finite(&expr[..pos], eval.question)?, eval_finite(&expr[pos + operator.len()..], eval.question)?);
+            //Values this close are treated as equal, so that 0.1 + 0.2 == 0.3 is true
+            let equal = (left - right).abs() <= 1e-9 * left.abs().max(right.abs()).max(1.0);
+            let result = match operator {
+                "==" => equal,
+                "!=" => !equal,
+                "<=" => left < right || equal,
+                ">=" => left > right || equal,
+                "<" => left < right && !equal,
+                _ => left > right && !equal
+            };
+            Ok(if result { 1.0 } else { 0.0 })
+        },
+        None => eval_finite(&expr, eval.question)
+    }
+}
+
+//A result that isn't a finite number would be shown as NaN or inf, so it is an error like dividing by zero
+fn eval_finite(expr: &str, question: usize) -> Result<f64, MorphiusError> {
+    match mexprp::eval::<f64>(expr) {
+        Ok(mexprp::Answer::Single(num)) if num.is_finite() => Ok(num),
+        Ok(mexprp::Answer::Single(_)) | Err(mexprp::EvalError::MathError { error: mexprp::MathError::DivideByZero }) => {
+            Err(MorphiusError::NotFinite { expression: String::from(expr), question })
+        },
+        _ => eval_number(expr)
+    }
+}
+
+//This finds the first comparison operator outside of parentheses, which splits the expression into the two values being compared
+fn comparison(expr: &str) -> Option<(usize, &'static str)> {
+    let mut depth = 0usize;
+    for (pos, c) in expr.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => {
+                if let Some(operator) = ["<=", ">=", "==", "!=", "<", ">"].into_iter().find(|operator| expr[pos..].starts_with(operator)) {
+                    return Some((pos, operator));
+                }
+            },
+            _ => ()
+        }
+    }
+    None
+}
+
+//The values are formatted like the results of expressions, with negative values in parentheses so that `a - b` doesn't become `3 - -2`
+fn symbolic_text(expression: &Expression, eval: &Eval) -> Result<String, MorphiusError> {
+    let format_value = |value: f64| if value < 0.0 { format!("({})", format_number(value)) } else { format_number(value) };
+    let mut text = String::new();
+    for exp_comp in expression.expression.iter() {
+        match exp_comp {
+            ExpComp::Var(name) => {
+                let value = eval.scope.get(&name[..]).ok_or_else(|| MorphiusError::UnknownVariable { name: name.clone(), question: eval.question })?;
+                text.push_str(&format_value(value.value()));
+            },
+            ExpComp::Func(piece) | ExpComp::Other(piece) | ExpComp::Literal(piece) => text.push_str(piece)
+        }
+    }
+    if calls(expression, &["rand", "randint"]) {
+        //Draws are keyed by the call with the raw values filled in, so the call is found in the substituted expression
+        let substituted = substitute_vars(expression, eval.scope, eval.question)?;
+        let draws: Vec<String> = random_calls(&substituted).iter().map(|key| format_value(eval.draws[key])).collect();
+        let mut draws = draws.into_iter();
+        text = random_call_regex().replace_all(&text, |_: &regex::Captures| draws.next().unwrap_or_default()).into_owned();
+    }
+    Ok(text)
+}
+
+fn pad(text: String, expression: &Expression) -> String {
+    let padding = expression.width.saturating_sub(text.chars().count());
+    let fill: String = std::iter::repeat_n(expression.fill, padding).collect();
+    match expression.align {
+        Align::Left => text + &fill,
+        //Zeros go between the sign and the digits so the result still reads as the same number
+        Align::Right if expression.fill == '0' && text.starts_with('-') => format!("-{}{}", fill, &text[1..]),
+        Align::Right => fill + &text
+    }
+}
+
+fn substitute_vars(expression: &Expression, scope: &HashMap<&str,Num>, question: usize) -> Result<String, MorphiusError> {
+    let mut text = String::new();
+    for exp_cmp in expression.expression.iter() {
+        match exp_cmp {
+            ExpComp::Var(var_name) => {
+                match scope.get(&var_name[..]).ok_or_else(|| MorphiusError::UnknownVariable { name: var_name.clone(), question })? {
+                    Num::Int(num) => write!(text, "{}", num),
+                    Num::Real(num) => write!(text, "{}", num)
+                }.unwrap();
+            }
+            ExpComp::Func(piece) | ExpComp::Other(piece) => text.push_str(piece),
+            ExpComp::Literal(_) => ()
+        }
+    }
+    Ok(text)
+}
+
+fn format_currency(num: f64, options: &GenerateOptions) -> String {
+    let cents = format!("{:.2}", num.abs());
+    let (whole, frac) = cents.split_once('.').unwrap();
+    let digits: Vec<char> = whole.chars().collect();
+    let grouped = digits.rchunks(3).rev().map(|group| group.iter().collect::<String>()).join(&options.thousands_separator);
+    let sign = if num < 0.0 && cents.chars().any(|c| c != '0' && c != '.') { "-" } else { "" };
+    format!("{}{}{}{}{}", sign, options.currency_symbol, grouped, options.decimal_separator, frac)
+}
+
+//The digits are rounded as they are written in decimal, so ties such as 0.0005 aren't decided by how the number is stored in binary
+fn round_decimal(num: f64, decimals: usize, rounding: Rounding) -> f64 {
+    let written = num.abs().to_string();
+    let (whole, frac) = written.split_once('.').unwrap_or((&written, ""));
+    if frac.len() <= decimals || !num.is_finite() {
+        return num;
+    }
+    let (kept, rest) = frac.split_at(decimals);
+    let mut digits: Vec<u8> = whole.bytes().chain(kept.bytes()).map(|digit| digit - b'0').collect();
+    let first = rest.as_bytes()[0] - b'0';
+    let beyond = rest[1..].bytes().any(|digit| digit != b'0');
+    let up = match rounding {
+        Rounding::HalfUp => first >= 5,
+        Rounding::HalfEven => first > 5 || (first == 5 && (beyond || digits.last().is_some_and(|digit| digit % 2 == 1))),
+        Rounding::Truncate => false,
+        Rounding::Ceiling => num > 0.0 && (first > 0 || beyond)
+    };
+    if up {
+        let mut pos = digits.len();
+        loop {
+            if pos == 0 {
+                digits.insert(0, 1);
+                break;
+            }
+            pos -= 1;
+            if digits[pos] == 9 {
+                digits[pos] = 0;
+            } else {
+                digits[pos] += 1;
+                break;
+            }
+        }
+    }
+    let split = digits.len() - decimals;
+    let text: String = digits.iter().map(|digit| char::from(b'0' + digit)).collect();
+    let rounded: f64 = format!("{}.{}", &text[..split], &text[split..]).parse().unwrap();
+    if num < 0.0 { -rounded } else { rounded }
+}
+
+fn format_number(num: f64) -> String {
+    if num != 0.0 && (num.abs() < 1e-3 || num.abs() >= 1e15) {
+        let scientific = format!("{:.3e}", num);
+        let (mantissa, exponent) = scientific.split_once('e').unwrap();
+        return format!("{}e{}", mantissa.trim_end_matches('0').trim_end_matches('.'), exponent);
+    }
+    let rounded = format!("{:.3}", num);
+    let normal = num.to_string();
+    if normal.chars().count() > rounded.chars().count()  {
+        rounded
+    } else {
+        normal
+    }
+}
+
+fn question_template(question: &Question) -> String {
+    let mut attributes = String::new();
+    if let Some(id) = &question.id {
+        attributes.push_str(&format!(" id={}", id));
+    }
+    if !question.shares.is_empty() {
+        attributes.push_str(&format!(" shares={}", question.shares.join(",")));
+    }
+    if !question.flags.is_empty() {
+        attributes.push_str(&format!(" if={}", question.flags.join(",")));
+    }
+    if let Some(points) = question.points {
+        attributes.push_str(&format!(" points={}", points));
+    }
+    if !question.tags.is_empty() {
+        attributes.push_str(&format!(" tags={}", question.tags.join(",")));
+    }
+    if let Some(attempts) = question.attempts {
+        attributes.push_str(&format!(" attempts={}", attempts));
+    }
+    if question.summary {
+        attributes.push_str(" summary");
+    }
+    if question.required {
+        attributes.push_str(" required");
+    }
+    if let Some(difficulty) = question.difficulty {
+        attributes.push_str(&format!(" difficulty={}", difficulty));
+    }
+    let content = question_content_template(question);
+    let answer = match &question.answer {
+        Some(answer) => std::iter::once(answer).chain(answer.alternatives.iter()).map(|form| {
+            let range = form.range.map_or(String::new(), |(min, max)| format!(" range=[{},{}]", min, max));
+            let tolerance = form.tolerance.map_or(String::new(), |tolerance| format!(" tolerance={}", tolerance));
+            let when = form.when.as_ref().map_or(String::new(), |when| format!(" when={}", when));
+            format!("|<a{}{}{}>{}</a>|", range, tolerance, when, parts_template(&content_template(&form.layout, &form.expressions), &form.parts))
+        }).join(""),
+        None => String::new()
+    };
+    format!("|<q{}>{}</q>|{}", attributes, content, answer)
+}
+
+//This is the template for what is inside the question tag: its declarations, its content, its parts and its hints
+fn question_content_template(question: &Question) -> String {
+    let hints = question.hints.iter().map(|hint| {
+        let shares = if hint.shares.is_empty() { String::new() } else { format!(" shares={}", hint.shares.join(",")) };
+        format!("|<hint{}>{}</hint>|", shares, content_template(&hint.layout, &hint.expressions))
+    }).join("");
+    let partitions = question.partitions.iter().map(|partition| format!("|<v>[{}]: partition({})</v>|", partition.names.join(","), partition.total)).join("");
+    let tables = question.tables.iter().map(|table| format!("|<v>{}: row(\"{}\")</v>|", table.name, table.path)).join("");
+    let choices = question.choices.iter().map(|choice| {
+        format!("|<v>{}: choice = [{}]</v>|", choice.name, choice.options.iter().map(|(value, weight)| format!("{}:{}", value, weight)).join(", "))
+    }).join("");
+    let vars = question.vars.iter().filter(|var| !question.tables.iter().any(|table| table.name == var.name) && !question.choices.iter().any(|choice| choice.name == var.name));
+    format!("{}{}{}{}{}{}", tables, partitions, choices, declarations_template(vars), parts_template(&content_template(&question.layout, &question.expressions), &question.parts), hints)
+}
+
+fn declarations_template<'a>(vars: impl Iterator<Item = &'a Var>) -> String {
+    let default = |var: &Var| *var == default_var(&var.name);
+    vars.filter(|var| !default(var)).sorted_by(|a, b| a.name.cmp(&b.name)).map(|var| format!("|<v>{}</v>|", declaration_template(var))).join("")
+}
+
+fn declaration_template(var: &Var) -> String {
+    let declaration = match &var.base {
+        None if var.cycle.is_some() => format!("{}: {} = cycle[{}]", var.name, var.num_type, var.cycle.as_ref().unwrap().join(",")),
+        None if var.set.is_some() => format!("{}: {} = {}[{},{}]", var.name, var.num_type, var.set.as_ref().unwrap(), var.min, var.max),
+        Some(base) => format!("{}: {} linked {} ± {}", var.name, var.num_type, base, var.max),
+        None if var.min == var.max => format!("{}: {} = {}", var.name, var.num_type, var.min),
+        None => format!("{}: {} = [{},{}]", var.name, var.num_type, var.min, var.max)
+    };
+    let declaration = match &var.grain {
+        Some(grain) => format!("{} grain {}", declaration, grain),
+        None => declaration
+    };
+    match &var.clamp {
+        Some((low, high)) => format!("{} clamp [{},{}]", declaration, low, high),
+        None => declaration
+    }
+}
+
+fn parts_template(text: &str, parts: &[Part]) -> String {
+    let parts: Vec<String> = parts.iter().map(|part| {
+        let points = part.points.map_or(String::new(), |points| format!(" points={}", points));
+        format!("|<part{}{}>{}</part>|", if part.correct { " correct" } else { "" }, points, content_template(&part.layout, &part.expressions))
+    }).collect();
+    text.split(PART_SLOT).map(String::from).interleave(parts).join("")
+}
+
+fn content_template(layout: &[String], expressions: &[Expression]) -> String {
+    layout.iter().map(String::from).interleave(expressions.iter().map(|expression| {
+        let mut attributes = String::new();
+        match expression.mode {
+            ExpMode::Currency => attributes += " currency",
+            ExpMode::Pick => attributes += " pick",
+            ExpMode::Symbolic => attributes += " symbolic",
+            ExpMode::Show => attributes += " show",
+            ExpMode::Number | ExpMode::Text => ()
+        }
+        if expression.width > 0 {
+            attributes += &format!(" width={}", expression.width);
+        }
+        if expression.align == Align::Left {
+            attributes += " align=left";
+        }
+        if expression.fill != ' ' {
+            attributes += &format!(" fill={}", expression.fill);
+        }
+        if let Some(name) = &expression.name {
+            attributes += &format!(" name={}", name);
+        }
+        if expression.degrees {
+            attributes += " degrees";
+        }
+        format!("|<e{}>{}</e>|", attributes, expression_source(expression))
+    })).join("")
+}
+
+fn expression_source(expression: &Expression) -> String {
+    expression.expression.iter().map(|exp_comp| match exp_comp {
+        ExpComp::Var(text) | ExpComp::Func(text) | ExpComp::Other(text) => text.clone(),
+        ExpComp::Literal(text) => text.chars().map(|c| format!("\\{}", c)).collect()
+    }).join("")
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn has_literals(expression: &Expression) -> bool {
+    expression.expression.iter().any(|exp_comp| matches!(exp_comp, ExpComp::Literal(_)))
+}
+
+//An expression with escaped text is split like content, into the text and the expressions between it, which are evaluated separately
+fn split_literals(expression: &Expression) -> (Vec<String>, Vec<Expression>) {
+    let mut layout = vec![String::new()];
+    let mut expressions = Vec::new();
+    let mut run: Vec<ExpComp> = Vec::new();
+    let mut flush = |run: &mut Vec<ExpComp>, layout: &mut Vec<String>| {
+        if run.iter().all(|exp_comp| matches!(exp_comp, ExpComp::Other(text) if text.trim().is_empty())) {
+            let spaces: String = run.drain(..).map(|exp_comp| if let ExpComp::Other(text) = exp_comp { text } else { String::new() }).collect();
+            layout.last_mut().unwrap().push_str(&spaces);
+            return;
+        }
+        //Spaces around the math are kept next to the text, so `a \+ b` is shown as `3 + 4`
+        let space = |exp_comp: Option<&ExpComp>, leading: bool| match exp_comp {
+            Some(ExpComp::Other(text)) if leading => text[..text.len() - text.trim_start().len()].to_string(),
+            Some(ExpComp::Other(text)) => text[text.trim_end().len()..].to_string(),
+            _ => String::new()
+        };
+        layout.last_mut().unwrap().push_str(&space(run.first(), true));
+        let trailing = space(run.last(), false);
+        expressions.push(Expression { expression: std::mem::take(run), mode: expression.mode, width: 0, align: Align::Right, fill: ' ', name: None, degrees: expression.degrees });
+        layout.push(trailing);
+    };
+    for exp_comp in expression.expression.iter() {
+        match exp_comp {
+            ExpComp::Literal(text) => {
+                flush(&mut run, &mut layout);
+                layout.last_mut().unwrap().push_str(text);
+            },
+            _ => run.push(exp_comp.clone())
+        }
+    }
+    flush(&mut run, &mut layout);
+    (layout, expressions)
+}
+
+fn question_expressions(question: &Question) -> Vec<&Expression> {
+    let mut expressions: Vec<&Expression> = question.expressions.iter().chain(question.parts.iter().flat_map(|part| part.expressions.iter())).collect();
+    expressions.extend(question.hints.iter().flat_map(|hint| hint.expressions.iter()));
+    if let Some(answer) = &question.answer {
+        expressions.extend(answer_expressions(answer));
+    }
+    expressions
+}
+
+fn answer_expressions(answer: &Answer) -> Vec<&Expression> {
+    let mut expressions: Vec<&Expression> = answer.expressions.iter().chain(answer.parts.iter().flat_map(|part| part.expressions.iter())).collect();
+    expressions.extend(answer.alternatives.iter().flat_map(answer_expressions));
+    expressions
+}
+
+fn parse_attributes(attributes: &str) -> HashMap<String, String> {
+    attributes.split_whitespace().map(|attribute| match attribute.split_once('=') {
+        Some((key, value)) => (String::from(key), String::from(value)),
+        None => (String::from(attribute), String::new())
+    }).collect()
+}
+
+fn get_seed(input: &str) -> (String, Option<u64>) {
+    lazy_static! {
+        static ref SEED: Regex = Regex::new(r"\|<seed>\s*([0-9]+)\s*</seed>\|").unwrap();
+    }
+    let seed = SEED.captures(input).and_then(|cap| cap[1].parse().ok());
+    (SEED.replace_all(input, "").into_owned(), seed)
+}
+
+//Macros are expanded in the text of every expression before it is processed, so the variables they use belong to the question using them
+fn get_macros(input: &str) -> (String, HashMap<String, String>, Option<MorphiusError>) {
+    lazy_static! {
+        static ref MACRO: Regex = Regex::new(&format!(r"(?s)\|<macro\s+({})>(.*?)</macro>\|", IDENT)).unwrap();
+        static ref EXP: Regex = Regex::new(r"\|<e((?:\s[^>]*)?)>(.*?)</e>\|").unwrap();
+    }
+    let macros: HashMap<String, String> = MACRO.captures_iter(input).map(|cap| (String::from(&cap[1]), String::from(cap[2].trim()))).collect();
+    let input = MACRO.replace_all(input, "");
+    let mut error = None;
+    if macros.is_empty() {
+        return (input.into_owned(), macros, error);
+    }
+    let input = EXP.replace_all(&input, |cap: &regex::Captures| {
+        format!("|<e{}>{}</e>|", &cap[1], expand_macros(&cap[2], &macros, &mut Vec::new(), &mut error))
+    }).into_owned();
+    (input, macros, error)
+}
+
+//Each macro is put in parentheses so that `TAX*2` doubles all of it. A macro that uses itself is left as it is where it uses itself
+fn expand_macros(text: &str, macros: &HashMap<String, String>, expanding: &mut Vec<String>, error: &mut Option<MorphiusError>) -> String {
+    lazy_static! {
+        static ref NAME: Regex = Regex::new(&format!(r"{0}(?:\.{0})*", IDENT)).unwrap();
+    }
+    NAME.replace_all(text, |cap: &regex::Captures| {
+        let name = &cap[0];
+        match macros.get(name) {
+            Some(_) if expanding.iter().any(|other| other == name) => {
+                error.get_or_insert(MorphiusError::RecursiveMacro { name: String::from(name) });
+                String::from(name)
+            },
+            Some(body) => {
+                expanding.push(String::from(name));
+                let expanded = format!("({})", expand_macros(body, macros, expanding, error));
+                expanding.pop();
+                expanded
+            },
+            None => String::from(name)
+        }
+    }).into_owned()
+}
+
+fn get_metadata(input: &str) -> (String, HashMap<String, String>) {
+    lazy_static! {
+        static ref META: Regex = Regex::new(r"(?s)\|<meta>(.*?)</meta>\|").unwrap();
+    }
+    let mut metadata: HashMap<String, String> = HashMap::new();
+    for cap in META.captures_iter(input) {
+        for line in cap[1].lines() {
+            if let Some((key, value)) = line.split_once(':') {
+                metadata.insert(String::from(key.trim()), String::from(value.trim()));
+            }
+        }
+    }
+    (META.replace_all(input, "").into_owned(), metadata)
+}
+
+fn process_layout<'a>(layout: impl Iterator<Item = &'a str>) -> (Vec<String>, HashSet<Var>) {
+    let mut globals: HashSet<Var> = HashSet::new();
+    let layout: Vec<String> = layout.map(|text| {
+        let (text, declarations) = get_declarations(text);
+        globals.extend(declarations);
+        text
+    }).collect();
+    (layout, globals)
+}
+
+//The error is for the first table that couldn't be loaded, whose declaration is left in the text
+fn get_tables(text: &str) -> (String, Vec<Table>, Option<MorphiusError>) {
+    lazy_static! {
+        static ref ROW: Regex = Regex::new(&format!(r#"\|<v>\s*({})\s*:\s*row\(\s*"([^"]*)"\s*\)\s*</v>\|"#, IDENT)).unwrap();
+    }
+    let mut tables = Vec::new();
+    let mut error = None;
+    let text = ROW.replace_all(text, |cap: &regex::Captures| match fs::read_to_string(&cap[2]) {
+        Ok(csv) => {
+            let mut records = parse_csv(&csv).into_iter();
+            let columns = records.next().unwrap_or_default().iter().map(|column| format!("{}.{}", &cap[1], column)).collect();
+            tables.push(Table { name: String::from(&cap[1]), path: String::from(&cap[2]), columns, rows: records.collect() });
+            String::new()
+        },
+        Err(e) => {
+            error.get_or_insert(MorphiusError::MissingTable { path: String::from(&cap[2]), message: e.to_string() });
+            String::from(&cap[0])
+        }
+    }).into_owned();
+    (text, tables, error)
+}
+
+//Fields can be quoted to contain commas, quotes (written twice) or line breaks. Empty lines are skipped
+fn parse_csv(csv: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(String::from(std::mem::take(&mut field).trim())),
+            '\n' if !quoted => {
+                record.push(String::from(std::mem::take(&mut field).trim()));
+                if record.iter().any(|field| !field.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            },
+            '\r' if !quoted => (),
+            _ => field.push(c)
+        }
+    }
+    record.push(String::from(field.trim()));
+    if record.iter().any(|field| !field.is_empty()) {
+        records.push(record);
+    }
+    records
+}
+
+fn get_partitions(text: &str) -> (String, Vec<Partition>) {
+    lazy_static! {
+        static ref PARTITION: Regex = Regex::new(&format!(r"\|<v>\s*\[\s*({0}(?:\s*,\s*{0})*)\s*\]\s*:\s*partition\(\s*([0-9]+)\s*\)\s*</v>\|", IDENT)).unwrap();
+    }
+    let partitions: Vec<Partition> = PARTITION.captures_iter(text).filter_map(|cap| {
+        let names = cap[1].split(',').map(|name| String::from(name.trim())).collect();
+        Some(Partition { names, total: cap[2].parse().ok()? })
+    }).collect();
+    (PARTITION.replace_all(text, "").into_owned(), partitions)
+}
+
+//The weight of a value defaults to 1. A choice whose weights are negative or all 0 is an error, since none of its values could be chosen
+fn get_choices(text: &str) -> (String, Vec<Choice>, Option<MorphiusError>) {
+    lazy_static! {
+        static ref CHOICE: Regex = Regex::new(&format!(r"\|<v>\s*({})\s*:\s*choice\s*=\s*\[([^\]]*)\]\s*</v>\|", IDENT)).unwrap();
+        static ref OPTION: Regex = Regex::new(&format!(r"^\s*([^,:]*[^,:\s])\s*(?::\s*({}))?\s*$", NUMBER)).unwrap();
+    }
+    let mut choices = Vec::new();
+    let mut error = None;
+    let text = CHOICE.replace_all(text, |cap: &regex::Captures| {
+        let options: Option<Vec<(String, f64)>> = cap[2].split(',').map(|option| {
+            let option = OPTION.captures(option)?;
+            let weight = option.get(2).map_or(Some(1.0), |weight| weight.as_str().parse().ok())?;
+            Some((String::from(&option[1]), weight))
+        }).collect();
+        match options {
+            Some(options) if options.iter().all(|(_, weight)| *weight >= 0.0) && options.iter().any(|(_, weight)| *weight > 0.0) => {
+                choices.push(Choice { name: String::from(&cap[1]), options });
+                String::new()
+            },
+            _ => {
+                error.get_or_insert(MorphiusError::InvalidChoice { name: String::from(&cap[1]), values: String::from(cap[2].trim()) });
+                String::from(&cap[0])
+            }
+        }
+    }).into_owned();
+    (text, choices, error)
+}
+
+//A choice whose values are all numbers can be used in math, while the values of other choices are shown as they are
+fn numeric_choice(choice: &Choice) -> bool {
+    choice.options.iter().all(|(value, _)| value.parse::<f64>().is_ok())
+}
+
+fn get_declarations(text: &str) -> (String, Vec<Var>) {
+    lazy_static! {
+        static ref DECLARATION: Regex = Regex::new(r"\|<v>(.*?)</v>\|").unwrap();
+    }
+    let mut declarations: Vec<Var> = Vec::new();
+    let text = DECLARATION.replace_all(text, |cap: &regex::Captures| match parse_declarations(&cap[1]) {
+        Some(vars) => {
+            declarations.extend(vars);
+            String::new()
+        },
+        None => String::from(&cap[0])
+    }).into_owned();
+    (text, declarations)
+}
+
+//A declaration can start with several names separated by commas, such as `a, b, c: int = [1,10]`, which declares each of them the same way
+fn parse_declarations(declaration: &str) -> Option<Vec<Var>> {
+    lazy_static! {
+        static ref NAMES: Regex = Regex::new(&format!(r"^\s*({0}(?:\s*,\s*{0})+)\s*:(.*)$", IDENT)).unwrap();
+    }
+    match NAMES.captures(declaration) {
+        Some(cap) => cap[1].split(',').map(|name| parse_declaration(&format!("{}:{}", name.trim(), &cap[2]))).collect(),
+        None => parse_declaration(declaration).map(|var| vec![var])
+    }
+}
+
+fn parse_declaration(declaration: &str) -> Option<Var> {
+    lazy_static! {
+        static ref RANGE: Regex = Regex::new(&format!(r"^({1}):\s*([[:alpha:]]*)\s*=\s*\[\s*({2})\s*,\s*({2})\s*\](?:\s+grain\s+({0}))?(?:\s+clamp\s+\[\s*({0})\s*,\s*({0})\s*\])?$", NUMBER, IDENT, BOUND)).unwrap();
+        static ref FIXED: Regex = Regex::new(&format!(r"^({1}):\s*([[:alpha:]]*)\s*=\s*({0})$", NUMBER, IDENT)).unwrap();
+        static ref SET: Regex = Regex::new(&format!(r"^({1}):\s*([[:alpha:]]*)\s*=\s*(?:({3})\s*\[\s*({2})\s*,\s*({2})\s*\]|set\(\s*({3})\s*,\s*\[\s*({2})\s*,\s*({2})\s*\]\s*\))(?:\s+clamp\s+\[\s*({0})\s*,\s*({0})\s*\])?$", NUMBER, IDENT, BOUND, SETS.join("|"))).unwrap();
+        static ref CYCLE: Regex = Regex::new(&format!(r"^({1}):\s*([[:alpha:]]*)\s*=\s*cycle\s*\[\s*({0}(?:\s*,\s*{0})*)\s*\]$", NUMBER, IDENT)).unwrap();
+        static ref LINKED: Regex = Regex::new(&format!(r"^({1}):\s*([[:alpha:]]*)\s+linked\s+({1})\s*(?:±|\+-)\s*({0})$", NUMBER, IDENT)).unwrap();
+    }
+    if let Some(cap) = RANGE.captures(declaration) {
+        let clamp = cap.get(6).zip(cap.get(7)).map(|(low, high)| (String::from(low.as_str()), String::from(high.as_str())));
+        return Some(Var{ name: String::from(&cap[1]), num_type: String::from(&cap[2]), min: String::from(&cap[3]), max: String::from(&cap[4]), base: None, grain: cap.get(5).map(|grain| String::from(grain.as_str())), clamp, set: None, cycle: None });
+    }
+    if let Some(cap) = SET.captures(declaration) {
+        let set = cap.get(3).or_else(|| cap.get(6)).unwrap();
+        let (min, max) = if cap.get(3).is_some() { (&cap[4], &cap[5]) } else { (&cap[7], &cap[8]) };
+        let clamp = cap.get(9).zip(cap.get(10)).map(|(low, high)| (String::from(low.as_str()), String::from(high.as_str())));
+        return Some(Var{ name: String::from(&cap[1]), num_type: String::from(&cap[2]), min: String::from(min), max: String::from(max), base: None, grain: None, clamp, set: Some(String::from(set.as_str())), cycle: None });
+    }
+    if let Some(cap) = CYCLE.captures(declaration) {
+        let values: Vec<String> = cap[3].split(',').map(|value| String::from(value.trim())).collect();
+        //The bounds are the smallest and largest values, so the variable can be summarized like any other
+        let numbers: Vec<f64> = values.iter().map(|value| value.parse().unwrap()).collect();
+        let min = values[numbers.iter().position_min_by(|a, b| a.total_cmp(b)).unwrap()].clone();
+        let max = values[numbers.iter().position_max_by(|a, b| a.total_cmp(b)).unwrap()].clone();
+        return Some(Var{ name: String::from(&cap[1]), num_type: String::from(&cap[2]), min, max, base: None, grain: None, clamp: None, set: None, cycle: Some(values) });
+    }
+    if let Some(cap) = FIXED.captures(declaration) {
+        return Some(Var{ name: String::from(&cap[1]), num_type: String::from(&cap[2]), min: String::from(&cap[3]), max: String::from(&cap[3]), base: None, grain: None, clamp: None, set: None, cycle: None });
+    }
+    if let Some(cap) = LINKED.captures(declaration) {
+        let offset = cap[4].trim_start_matches('-');
+        return Some(Var{ name: String::from(&cap[1]), num_type: String::from(&cap[2]), min: format!("-{}", offset), max: String::from(offset), base: Some(String::from(&cap[3])), grain: None, clamp: None, set: None, cycle: None });
+    }
+    None
+}
+
+fn default_var(name: &str) -> Var {
+    Var{ name: String::from(name), num_type: String::from("int"), min: String::from("0"), max: String::from("99"), base: None, grain: None, clamp: None, set: None, cycle: None }
+}
+
+//The error is the first variable declared more than once in a question, which process ignores by using the last declaration
+fn parse_document(input: &str, with_answers: bool) -> (Document, Option<MorphiusError>) {
+    lazy_static! {
+        static ref QUESTION: Regex = Regex::new(r"(?s)\|<q((?:\s[^>]*)?)>(.*?)</q>\|").unwrap();
+        static ref QUESTION_WITH_ANSWER: Regex = Regex::new(r"(?s)\|<q((?:\s[^>]*)?)>(.*?)</q>\|\s*(\|<a(?:\s[^>]*)?>.*?</a>\|(?:\s*\|<a(?:\s[^>]*)?>.*?</a>\|)*)").unwrap();
+    }
+    let question = if with_answers { &*QUESTION_WITH_ANSWER } else { &*QUESTION };
+    let (input, metadata) = get_metadata(&preprocess(input));
+    let (input, seed) = get_seed(&input);
+    let (input, macros, recursive) = get_macros(&input);
+    let input = &input;
+    let (layout, globals) = process_layout(question.split(input));
+    let (questions, duplicate) = process_questions(question.captures_iter(input).map(|cap| {
+        (cap.get(2).unwrap().as_str(), parse_attributes(&cap[1]), cap.get(3).map(|answers| process_answers(answers.as_str())))
+    }), &globals);
+    debug_assert_eq!(layout.len(), questions.len() + 1);
+    let doc = Document{ questions, layout, globals, metadata, seed, macros };
+    let error = recursive.or(duplicate).or_else(|| stray_tag(&doc, with_answers));
+    (doc, error)
+}
+
+//Text that is left over from parsing shouldn't contain tags. A tag left in it means the template didn't split the way it was meant to
+fn stray_tag(doc: &Document, with_answers: bool) -> Option<MorphiusError> {
+    lazy_static! {
+        static ref TAG: Regex = Regex::new(r"\|<(q|a|e|v|part|hint|meta|seed|unit|use|points|macro)(?:\s[^>]*)?/?>|</(q|a|e|v|part|hint|meta|seed|unit|macro)>\|").unwrap();
+        static ref ANSWER: Regex = Regex::new(r"(?s)\|<a(?:\s[^>]*)?>.*?</a>\|").unwrap();
+    }
+    let find = |text: &str, allowed: &[&str]| TAG.captures_iter(text)
+        .find(|cap| !allowed.contains(&cap.get(1).or_else(|| cap.get(2)).unwrap().as_str()) && !text[cap.get(0).unwrap().start()..].starts_with(POINTS))
+        .map(|cap| String::from(&cap[0]));
+    //Without answers, answers are left in the layout as they are, along with the tags inside them
+    let outside: &[&str] = if with_answers { &[] } else { &["a"] };
+    let layout: Vec<String> = doc.layout.iter().map(|text| if with_answers { text.clone() } else { ANSWER.replace_all(text, "").into_owned() }).collect();
+    if let Some(tag) = layout.iter().find_map(|text| find(text, outside)) {
+        return Some(MorphiusError::MalformedTag { tag, question: None });
+    }
+    for (index, question) in doc.questions.iter().enumerate() {
+        let mut texts: Vec<&str> = question.layout.iter().map(|text| &text[..]).collect();
+        texts.extend(question.parts.iter().chain(question.answer.iter().flat_map(|answer| answer.parts.iter())).flat_map(|part| part.layout.iter().map(|text| &text[..])));
+        texts.extend(question.hints.iter().flat_map(|hint| hint.layout.iter().map(|text| &text[..])));
+        let mut answers: Vec<&Answer> = question.answer.iter().collect();
+        answers.extend(question.answer.iter().flat_map(|answer| answer.alternatives.iter()));
+        texts.extend(answers.iter().flat_map(|answer| answer.layout.iter().map(|text| &text[..])));
+        let sources: Vec<String> = question_expressions(question).into_iter().map(expression_source).collect();
+        texts.extend(sources.iter().map(|source| &source[..]));
+        if let Some(tag) = texts.into_iter().find_map(|text| find(text, &[])) {
+            return Some(MorphiusError::MalformedTag { tag, question: Some(index) });
+        }
+    }
+    None
+}
+
+fn process_questions<'a>(questions: impl Iterator<Item = (&'a str, HashMap<String, String>, Option<Answer>)>, globals: &HashSet<Var>) -> (Vec<Question>, Option<MorphiusError>) {
+    let mut processed: Vec<Question> = Vec::new();
+    let mut duplicate: Option<MorphiusError> = None;
+    //This is the names of the variables each processed question can use, including the ones it shares
+    let mut visible: Vec<HashSet<String>> = Vec::new();
+    for (question, attributes, answer) in questions {
+        let mut names: HashSet<String> = globals.iter().map(|var| var.name.clone()).collect();
+        for id in attributes.get("shares").into_iter().flat_map(|ids| ids.split(',')) {
+            if let Some(pos) = processed.iter().position(|question| question.id.as_deref() == Some(id)) {
+                names.extend(visible[pos].iter().cloned());
+            }
+        }
+        let mut hint_names = names.clone();
+        for id in get_hints(question).1.into_iter().flat_map(|(shares, _)| shares) {
+            if let Some(pos) = processed.iter().position(|question| question.id.as_deref() == Some(&id[..])) {
+                hint_names.extend(visible[pos].iter().cloned());
+            }
+        }
+        let (question, error) = process_question(processed.len(), question, &attributes, &names.iter().map(|name| &name[..]).collect(), &hint_names.iter().map(|name| &name[..]).collect(), answer);
+        duplicate = duplicate.or(error);
+        names.extend(question.vars.iter().map(|var| var.name.clone()));
+        visible.push(names);
+        processed.push(question);
+    }
+    //The counts used by a summary question aren't known until every question is processed, so they are first given the variables of names that aren't declared
+    let counts = count_names(&processed);
+    for question in processed.iter_mut().filter(|question| question.summary) {
+        question.vars.retain(|var| !counts.contains(&var.name) || *var != default_var(&var.name));
+    }
+    (processed, duplicate)
+}
+
+//This returns the text without its hints, and the shares and content of each hint
+fn get_hints(text: &str) -> (String, Vec<(Vec<String>, &str)>) {
+    lazy_static! {
+        static ref HINT: Regex = Regex::new(r"(?s)\|<hint((?:\s[^>]*)?)>(.*?)</hint>\|").unwrap();
+    }
+    let hints = HINT.captures_iter(text).map(|cap| {
+        let shares = match parse_attributes(&cap[1]).get("shares") {
+            Some(ids) => ids.split(',').filter(|id| !id.is_empty()).map(String::from).collect(),
+            None => Vec::new()
+        };
+        (shares, cap.get(2).unwrap().as_str())
+    }).collect();
+    (HINT.replace_all(text, "").into_owned(), hints)
+}
+
+//The names of the variables visible to the hints through their shares aren't also made variables of the question
+fn process_question(index: usize, question: &str, attributes: &HashMap<String, String>, globals: &HashSet<&str>, hint_globals: &HashSet<&str>, answer: Option<Answer>) -> (Question, Option<MorphiusError>) {
+    let (question, tables, missing) = get_tables(question);
+    let (question, partitions) = get_partitions(&question);
+    let (question, choices, invalid) = get_choices(&question);
+    let (question, declarations) = get_declarations(&question);
+    let (without_hints, hints) = get_hints(&question);
+    let (mut content, parts) = get_parts(&without_hints);
+    let hints: Vec<Hint> = hints.into_iter().map(|(shares, text)| {
+        let hint = get_content(text);
+        content.vars.extend(hint.vars.into_iter().filter(|var| !hint_globals.contains(&var.name[..])));
+        Hint { expressions: hint.expressions, layout: hint.layout, shares }
+    }).collect();
+    //Undeclared variables with the name of a global variable, or of a variable of a shared question, refer to that variable
+    content.vars.retain(|var| !globals.contains(&var.name[..]));
+    let mut declared: HashMap<String, String> = HashMap::new();
+    let mut duplicate: Option<MorphiusError> = None;
+    for declaration in declarations {
+        let text = declaration_template(&declaration);
+        match declared.insert(declaration.name.clone(), text.clone()) {
+            Some(first) if first != text && duplicate.is_none() => {
+                duplicate = Some(MorphiusError::DuplicateDeclaration { name: declaration.name.clone(), question: index, first, second: text });
+            },
+            _ => ()
+        }
+        content.vars.retain(|var| var.name != declaration.name);
+        content.vars.insert(declaration);
+    }
+    //A variable used in the bounds of another variable needs a value first, even if it isn't used anywhere else
+    let bound_vars: Vec<String> = content.vars.iter().flat_map(|var| bound_names(var)).map(String::from).collect();
+    for name in bound_vars {
+        if !globals.contains(&name[..]) && !content.vars.iter().any(|var| var.name == name) {
+            content.vars.insert(default_var(&name));
+        }
+    }
+    //The row of a table is chosen by sampling its index, and its columns are given their values from the row instead of being sampled
+    for table in tables.iter() {
+        content.vars.retain(|var| var.name != table.name && !table.columns.contains(&var.name));
+        let max = table.rows.len() as i64 - 1;
+        content.vars.insert(Var { max: max.to_string(), ..default_var(&table.name) });
+    }
+    //A choice is sampled as the index of its value, like the row of a table
+    for choice in choices.iter() {
+        content.vars.retain(|var| var.name != choice.name);
+        let max = choice.options.len() as i64 - 1;
+        content.vars.insert(Var { max: max.to_string(), ..default_var(&choice.name) });
+    }
+    let duplicate = missing.or(invalid).or(duplicate);
+    //The values of the variables in a partition replace their sampled values, so they only need to be variables of the question
+    for name in partitions.iter().flat_map(|partition| partition.names.iter()) {
+        if !content.vars.iter().any(|var| &var.name == name) {
+            content.vars.insert(default_var(name));
+        }
+    }
+    let flags: Vec<String> = match attributes.get("if") {
+        Some(flags) => flags.split(',').filter(|flag| !flag.is_empty()).map(String::from).collect(),
+        None => Vec::new()
+    };
+    let shares: Vec<String> = match attributes.get("shares") {
+        Some(ids) => ids.split(',').filter(|id| !id.is_empty()).map(String::from).collect(),
+        None => Vec::new()
+    };
+    let tags: Vec<String> = match attributes.get("tags") {
+        Some(tags) => tags.split(',').filter(|tag| !tag.is_empty()).map(String::from).collect(),
+        None => Vec::new()
+    };
+    let id = attributes.get("id").filter(|id| !id.is_empty()).cloned();
+    let points = attributes.get("points").and_then(|points| points.parse().ok());
+    let attempts = attributes.get("attempts").and_then(|attempts| attempts.parse().ok());
+    let summary = attributes.contains_key("summary");
+    let required = attributes.contains_key("required");
+    let difficulty = attributes.get("difficulty").and_then(|difficulty| difficulty.parse().ok());
+    (Question { vars: content.vars, expressions: content.expressions, layout: content.layout, answer, parts, flags, hints, partitions, id, shares, points, tags, attempts, tables, choices, summary, required, difficulty }, duplicate)
+}
+
+//The first answer after a question is its answer and the others are alternatives to it
+fn process_answers(answers: &str) -> Answer {
+    lazy_static! {
+        static ref ANSWER: Regex = Regex::new(r"(?s)\|<a((?:\s[^>]*)?)>(.*?)</a>\|").unwrap();
+    }
+    let mut forms = ANSWER.captures_iter(answers).map(|cap| process_answer(&cap[2], &parse_attributes(&cap[1])));
+    let mut answer = forms.next().unwrap();
+    answer.alternatives = forms.collect();
+    answer
+}
+
+fn process_answer(answer: &str, attributes: &HashMap<String, String>) -> Answer {
+    let (content, parts) = get_parts(answer);
+    let range = attributes.get("range").and_then(|range| {
+        let (min, max) = range.trim_start_matches('[').trim_end_matches(']').split_once(',')?;
+        Some((min.trim().parse().ok()?, max.trim().parse().ok()?))
+    });
+    let tolerance = attributes.get("tolerance").and_then(|tolerance| tolerance.parse().ok());
+    let when = attributes.get("when").filter(|when| !when.is_empty()).cloned();
+    Answer { expressions: content.expressions, layout: content.layout, parts, alternatives: Vec::new(), range, tolerance, when }
+}
+
+fn get_parts(text: &str) -> (Content, Vec<Part>) {
+    lazy_static! {
+        static ref PART: Regex = Regex::new(r"(?s)\|<part((?:\s[^>]*)?)>(.*?)</part>\|").unwrap();
+    }
+    let mut content = get_content(&PART.replace_all(text, PART_SLOT));
+    let parts: Vec<Part> = PART.captures_iter(text).map(|cap| {
+        let part = get_content(&cap[2]);
+        content.vars.extend(part.vars);
+        let attributes = parse_attributes(&cap[1]);
+        Part { expressions: part.expressions, layout: part.layout, correct: attributes.contains_key("correct"), points: attributes.get("points").and_then(|points| points.parse().ok()) }
+    }).collect();
+    (content, parts)
+}
+
+fn get_content(text: &str) -> Content {
+    lazy_static! {
+        static ref EXP: Regex = Regex::new(r"\|<e((?:\s[^>]*)?)>(.*?)</e>\|").unwrap();
+    }
+    let mut vars: HashSet<Var> = HashSet::new();
+    let expressions: Vec<Expression> = EXP.captures_iter(text).map(|cap| process_expression(&cap[2], &parse_attributes(&cap[1]), &mut vars)).collect();
+    let layout: Vec<String> = EXP.split(text).map(String::from).collect();
+    Content{ vars, expressions, layout }
+}
+
+fn process_expression(expression: &str, attributes: &HashMap<String, String>, vars: &mut HashSet<Var>) -> Expression {
+    lazy_static! {
+        static ref VAR: Regex = Regex::new(&format!(r"{0}(?:\.{0})*", IDENT)).unwrap();
+    }
+    let mode = if attributes.contains_key("currency") {
+        ExpMode::Currency
+    } else if attributes.contains_key("pick") {
+        ExpMode::Pick
+    } else if attributes.contains_key("symbolic") {
+        ExpMode::Symbolic
+    } else if attributes.contains_key("show") {
+        ExpMode::Show
+    } else {
+        ExpMode::Number
+    };
+    let width = attributes.get("width").and_then(|width| width.parse().ok()).unwrap_or(0);
+    let align = if attributes.get("align").map(|align| &align[..]) == Some("left") { Align::Left } else { Align::Right };
+    let fill = attributes.get("fill").and_then(|fill| fill.chars().next()).unwrap_or(' ');
+    let name = attributes.get("name").cloned();
+    let degrees = attributes.contains_key("degrees");
+    //The alternatives of a pick are literal text, so they don't contain variables
+    if mode == ExpMode::Pick {
+        return Expression { expression: vec![ExpComp::Other(String::from(expression))], mode, width, align, fill, name, degrees };
+    }
+    //The math between escaped characters is processed on its own, and math that turns out to be text is kept as it is too
+    if expression.contains('\\') {
+        let mut exp_comps: Vec<ExpComp> = Vec::new();
+        let mut code = String::new();
+        let mut flush = |code: &mut String, exp_comps: &mut Vec<ExpComp>| {
+            if !code.is_empty() {
+                let processed = process_expression(&std::mem::take(code), attributes, vars);
+                match processed.mode {
+                    ExpMode::Text => exp_comps.push(ExpComp::Literal(expression_source(&processed))),
+                    _ => exp_comps.extend(processed.expression)
+                }
+            }
+        };
+        let mut chars = expression.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                code.push(c);
+                continue;
+            }
+            let escaped = chars.next().unwrap_or('\\');
+            flush(&mut code, &mut exp_comps);
+            match exp_comps.last_mut() {
+                Some(ExpComp::Literal(text)) => text.push(escaped),
+                _ => exp_comps.push(ExpComp::Literal(escaped.to_string()))
+            }
+        }
+        flush(&mut code, &mut exp_comps);
+        return Expression { expression: exp_comps, mode, width, align, fill, name, degrees };
+    }
+    let mut vars_list: Vec<ExpComp> = Vec::new();
+    let mut names: Vec<&str> = Vec::new();
+    for mat in VAR.find_iter(expression) {
+        if expression[mat.end()..].trim_start().starts_with('(') {
+            vars_list.push(ExpComp::Func(String::from(mat.as_str())));
+        } else {
+            names.push(mat.as_str());
+            vars_list.push(ExpComp::Var(String::from(mat.as_str())));
+        }
+    }
+    //Text such as `The capital is Paris` has values next to each other, which can't happen in math whatever the values of its variables are.
+    //Every function is parsed as max because mexprp doesn't know about the functions added by morphius
+    let probe = VAR.replace_all(expression, |cap: &regex::Captures| if names.contains(&&cap[0]) { "1" } else { "max" });
+    //Expressions that are too complex to parse are left as math, so that generating them reports the problem
+    let too_complex = probe.len() > MAX_EXPRESSION_LENGTH || nesting_depth(&probe) > MAX_EXPRESSION_DEPTH;
+    if !too_complex {
+        if let Err(mexprp::ParseError::UnexpectedToken { token }) = mexprp::Expression::<f64>::parse(&probe) {
+            if token == "," || token.parse::<f64>().is_ok() {
+                return Expression { expression: vec![ExpComp::Other(String::from(expression))], mode: ExpMode::Text, width, align, fill, name, degrees };
+            }
+        }
+    }
+    for name in names {
+        vars.insert(default_var(name));
+    }
+    Expression { expression: VAR.split(expression).map(|text| ExpComp::Other(String::from(text))).interleave(vars_list).collect(), mode, width, align, fill, name, degrees }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORM1: &str = "Beginning|<q>Question 1</q>|Middle|<q>Question 2</q>|End";
+    const FORM2: &str = "|<q>1</q>|Middle 1|<q>2</q>|Middle 2|<q>3</q>|";
+    const FORM3: &str = "|<q>1</q>||<q>2</q>||<q>3</q>|";
+    const FORM4: &str = "|<q>1</q>||<a>3</a>|";
+    const FORM5: &str = "|<q>1</q>|\n\n          \t\t|<a>3</a>|";
+
+
+    #[test]
+    fn test_process_1() {
+        let doc = process(FORM1);
+        assert_eq!(doc.layout[0], "Beginning");
+        assert_eq!(doc.layout[1], "Middle");
+        assert_eq!(doc.layout[2], "End");
+        assert_eq!(gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "Question 1");
+        assert_eq!(gen_question_text(&doc.questions[1], 1, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "Question 2");
+    }
+
+    #[test]
+    fn test_process_2() {
+        let doc = process(FORM2);
+        assert_eq!(doc.layout, vec!["","Middle 1", "Middle 2",""]);
+        assert_eq!(gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "1");
+        assert_eq!(gen_question_text(&doc.questions[1], 1, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "2");
+        assert_eq!(gen_question_text(&doc.questions[2], 2, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "3");
+    }
+
+    #[test]
+    fn test_skeleton() {
+        assert_eq!(process(FORM1).skeleton(), "Beginning{{question}}Middle{{question}}End");
+        assert_eq!(process(FORM3).skeleton(), "{{question}}{{question}}{{question}}");
+    }
+
+    #[test]
+    fn test_gen_form_original_order() {
+        let doc = process(FORM1);
+        assert_eq!(gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "BeginningQuestion 1MiddleQuestion 2End");
+    }
+
+    #[test]
+    fn test_gen_form_different_order() {
+        let doc = process(FORM2);
+        assert_eq!(gen_form(&doc, Some(&vec![1,2,0]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "2Middle 13Middle 21");
+        assert_eq!(gen_form(&doc, Some(&vec![2,1,0]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "3Middle 12Middle 21");
+        assert_eq!(gen_form(&doc, Some(&vec![0,1,2]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "1Middle 12Middle 23");
+    }
+
+    #[test]
+    fn test_generate_no_reorder() {
+        let doc = process(FORM3);
+        assert_eq!(generate(&doc, 2, None)[0].content, "123");
+        assert_eq!(generate(&doc, 2, None)[1].content, "123");
+    }
+
+    #[test]
+    fn test_generate_reorder() {
+        let doc = process(FORM3);
+        let results = generate(&doc, 3, Some(3));
+        for result in results {
+            assert!(result.content.contains("1") && result.content.contains("2") && result.content.contains("3"));
+        }
+    }
+
+    #[test]
+    fn test_generate_skip_questions() {
+        let doc = process(FORM3);
+        let results = generate(&doc, 3, Some(1));
+        for result in results {
+            assert!(!result.content.contains("1") || !result.content.contains("2") || !result.content.contains("3"));
+        }
+    }
+
+    #[test]
+    fn test_generate_var() {
+        let doc = process("|<q>|<e>a</e>|</q>|");
+        let result = generate(&doc, 3, Some(1));
+        let num_re = Regex::new(r"^[[:digit:]]+$").unwrap();
+        assert!(num_re.is_match(&result[0].content));
+    }
+
+    #[test]
+    fn test_generate_var_math() {
+        let doc = process("|<q>|<e>(a+b)-c</e>|</q>|");
+        let result = generate(&doc, 3, Some(1));
+        let num_re = Regex::new(r"^-?[[:digit:]]+$").unwrap();
+        println!("{}", result[0].content);
+        assert!(num_re.is_match(&result[0].content));
+    }
+
+    #[test]
+    fn test_process_with_anwer() {
+        let doc1 = process_with_answers(FORM4);
+        assert!(doc1.questions[0].answer.is_some());
+
+        let doc2 = process(FORM4);
+        assert!(doc2.questions[0].answer.is_none());
+    }
+
+    #[test]
+    fn test_process_with_anwer_newlines_are_ok() {
+        let doc = process_with_answers(FORM5);
+        assert!(doc.questions[0].answer.is_some());
+    }
+
+    #[test]
+    fn test_answer_generated_correctly() {
+        let doc = process_with_answers("|<q>|<e>a</e>|</q>||<a>|<e>a</e>|</a>|");
+        for result in generate(&doc, 3, Some(1)) {
+            assert_eq!(result.content, result.answers);
+        }
+    }
+
+    #[test]
+    fn test_var_bounds_are_processed() {
+        let doc = process("|<q>|<v>x: real = [5,55]</v>||<e>x/x</e>|</q>|");
+        for result in generate(&doc, 3, Some(1)) {
+            assert!(result.content == "1");
+        }
+    }
+
+    #[test]
+    fn test_parts_are_shuffled_and_relabeled() {
+        let doc = process("|<q>Stem|<part>One</part>||<part>Two</part>|</q>|");
+        let mut seen = HashSet::new();
+        for _ in 0..50 {
+            let content = gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content;
+            assert!(content == "Stema) Oneb) Two" || content == "Stema) Twob) One");
+            seen.insert(content);
+        }
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn test_answer_parts_follow_question_parts() {
+        let doc = process_with_answers("|<q>|<part>|<e>a</e>|</part>||<part>|<e>b</e>|</part>|</q>||<a>|<part>|<e>a</e>|</part>||<part>|<e>b</e>|</part>|</a>|");
+        for result in generate(&doc, 10, None) {
+            assert_eq!(result.content, result.answers);
+        }
+    }
+
+    #[test]
+    fn test_scientific_bounds_for_tiny_reals() {
+        let doc = process("|<q>|<v>q: real = [1e-9, 1e-6]</v>||<e>q</e>|</q>|");
+        for result in generate(&doc, 20, None) {
+            let value = result.content.parse::<f64>().unwrap();
+            assert!(result.content.contains('e'));
+            assert!((1e-9..=1e-6).contains(&value));
+        }
+    }
+
+    #[test]
+    fn test_scientific_bounds_for_huge_reals() {
+        let doc = process("|<q>|<v>n: real = [-2.5E20,-1e18]</v>||<e>n</e>|</q>|");
+        for result in generate(&doc, 20, None) {
+            let value = result.content.parse::<f64>().unwrap();
+            assert!(result.content.contains('e'));
+            assert!((-2.5e20..=-1e18).contains(&value));
+        }
+    }
+
+    #[test]
+    fn test_decimal_bounds_for_reals() {
+        let doc = process("|<q>|<v>x: real = [0.5,0.75]</v>||<e>x</e>|</q>|");
+        for result in generate(&doc, 20, None) {
+            assert!((0.5..=0.75).contains(&result.content.parse::<f64>().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_functions_are_not_variables() {
+        let doc = process("|<q>|<v>a: int = [4,4]</v>||<e>abs(0 - a) + max (a, 1)</e>|</q>|");
+        assert_eq!(doc.questions[0].vars.len(), 1);
+        assert!(validate(&doc).is_ok());
+        assert_eq!(generate(&doc, 1, None)[0].content, "8");
+    }
+
+    #[test]
+    fn test_validate_unknown_function() {
+        let doc = process_with_answers("|<q>1</q>||<a>1</a>||<q>|<e>a</e>|</q>||<a>|<e>logg(a)</e>|</a>|");
+        assert_eq!(validate(&doc), Err(MorphiusError::UnknownFunction { name: String::from("logg"), question: 1, expression: String::from("logg(a)") }));
+    }
+
+    #[test]
+    fn test_units_expand_into_independent_questions() {
+        let doc = process_with_answers("|<unit sum>|<q>|<v>a: int = [1,9]</v>|What is |<e>a</e>|+1?</q>||<a>|<e>a+1</e>|</a>|</unit>|1. |<use sum/>| 2. |<use sum />|");
+        assert_eq!(doc.questions.len(), 2);
+        assert_eq!(doc.layout, vec!["1. ", " 2. ", ""]);
+        assert!(doc.questions.iter().all(|q| q.answer.is_some()));
+    }
+
+    #[test]
+    fn test_unknown_units_are_left_alone() {
+        let doc = process("|<use missing/>|");
+        assert_eq!(doc.layout, vec!["|<use missing/>|"]);
+    }
+
+    #[test]
+    fn test_generate_with_rng_is_reproducible() {
+        let doc = process("|<q>|<e>a</e>|</q>||<q>|<e>b*2</e>|</q>||<q>|<v>c: real = [0,1]</v>||<e>c</e>|</q>|");
+        let first = generate_with_rng(&doc, 5, Some(3), &mut rand::rngs::StdRng::seed_from_u64(42));
+        let second = generate_with_rng(&doc, 5, Some(3), &mut rand::rngs::StdRng::seed_from_u64(42));
+        for (a, b) in first.iter().zip(second.iter()) {
+            assert_eq!(a.content, b.content);
+        }
+    }
+
+    #[test]
+    fn test_generate_uses_every_ordering() {
+        let doc = process(FORM3);
+        let orders: HashSet<String> = generate(&doc, 200, Some(1)).into_iter().map(|test| test.content).collect();
+        assert_eq!(orders.len(), 3);
+    }
+
+    #[test]
+    fn test_flagged_questions_need_an_active_flag() {
+        let doc = process("|<q if=honors>H</q>|-|<q>R</q>|-|<q if=honors,advanced>A</q>|");
+        assert_eq!(doc.questions[2].flags, vec!["honors", "advanced"]);
+        assert_eq!(generate(&doc, 1, None)[0].content, "R--");
+        let options = GenerateOptions { flags: vec![String::from("honors")], ..Default::default() };
+        assert_eq!(generate_with_options(&doc, 1, None, &options)[0].content, "H-R-A");
+        let options = GenerateOptions { flags: vec![String::from("advanced")], ..Default::default() };
+        for result in generate_with_options(&doc, 10, Some(3), &options) {
+            assert!(!result.content.contains('H'));
+        }
+    }
+
+    #[test]
+    fn test_evaluate_answer_with_assignment() {
+        let doc = process_with_answers("|<q>|<e>a</e>|</q>||<a>Twice is |<e>2*a</e>|</a>||<q>1</q>||<a>1</a>|");
+        let assignment = HashMap::from([(String::from("a"), 21.0)]);
+        assert_eq!(doc.questions[0].evaluate_answer(&assignment), Ok(String::from("Twice is 42")));
+        assert_eq!(doc.questions[0].evaluate_answer(&HashMap::new()), Err(MorphiusError::MissingVariable { name: String::from("a") }));
+        assert_eq!(process("|<q>1</q>|").questions[0].evaluate_answer(&HashMap::new()), Err(MorphiusError::MissingAnswer));
+        //Only the answer needs its variables, and its random calls give the same value every time
+        let doc = process_with_answers("|<q>Roll |<e>randint(1,n)</e>|</q>||<a>|<e>a + rand()</e>|</a>|");
+        let assignment = HashMap::from([(String::from("a"), 1.0)]);
+        let answer = doc.questions[0].evaluate_answer(&assignment).unwrap();
+        assert!((0..10).all(|_| doc.questions[0].evaluate_answer(&assignment).unwrap() == answer));
+        //A choice that isn't numeric is given by the index of its option and shows its text
+        let doc = process_with_answers("|<q>|<v>c: choice = [red, blue]</v>|Color?</q>||<a>|<e>c</e>|</a>|");
+        assert_eq!(doc.questions[0].evaluate_answer(&HashMap::from([(String::from("c"), 1.0)])).unwrap(), "blue");
+    }
+
+    #[test]
+    fn test_tests_keep_their_questions() {
+        let doc = process_with_answers("|<q>A</q>||<a>1</a>||<q>B</q>||<a>2</a>|");
+        let test = gen_form(&doc, Some(&vec![1, 0]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+        assert_eq!(test.questions.iter().map(|q| q.index).collect::<Vec<usize>>(), vec![1, 0]);
+        assert_eq!(test.questions[0].content, "B");
+        assert_eq!(test.questions[0].answer, "2");
+    }
+
+    #[test]
+    fn test_format_answer_grid() {
+        let doc = process_with_answers("|<q>A</q>||<a>1</a>||<q>B</q>||<a>22\n</a>|");
+        let mut rng = rand::thread_rng();
+        let tests = vec![gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rng).unwrap(), gen_form(&doc, Some(&vec![1, 0]), &HashMap::new(), &GenerateOptions::default(), &mut rng).unwrap(), gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rng).unwrap()];
+        assert_eq!(format_answer_grid(&tests, 2), "Test 1  Test 2\n1. 1    1. 22\n2. 22   2. 1\n\nTest 3\n1. 1\n2. 22");
+    }
+
+    #[test]
+    fn test_global_variables_are_shared() {
+        let doc = process("|<v>g: int = [1,1000]</v>||<q>|<e>g</e>|</q>|-|<q>|<e>g</e>|</q>|-|<q>|<v>g: int = [0,0]</v>||<e>g</e>|</q>|");
+        assert_eq!(doc.layout, vec!["", "-", "-", ""]);
+        assert_eq!(doc.globals.len(), 1);
+        assert!(doc.questions[0].vars.is_empty());
+        for result in generate(&doc, 5, None) {
+            let values: Vec<&str> = result.content.split('-').collect();
+            assert_eq!(values[0], values[1]);
+            assert_eq!(values[2], "0");
+        }
+    }
+
+    #[test]
+    fn test_linked_variables_stay_near_their_base() {
+        let doc = process("|<v>base: int = [10,90]</v>||<q>|<v>x: int linked base ± 2</v>||<e>x - base</e>|</q>||<q>|<v>y: real linked base +- 0.5</v>||<e>y - base</e>|</q>|");
+        assert!(validate(&doc).is_ok());
+        for result in generate(&doc, 20, None) {
+            let x = result.questions[0].content.parse::<f64>().unwrap();
+            let y = result.questions[1].content.parse::<f64>().unwrap();
+            assert!((-2.0..=2.0).contains(&x));
+            assert!((-0.5..=0.5).contains(&y));
+        }
+        let doc = process("|<q>|<v>x: int linked missing ± 2</v>||<e>x</e>|</q>|");
+        assert_eq!(validate(&doc), Err(MorphiusError::MissingVariable { name: String::from("missing") }));
+    }
+
+    #[test]
+    fn test_balanced_selection_uses_questions_equally() {
+        let doc = process(FORM3);
+        let options = GenerateOptions { balanced: true, ..Default::default() };
+        let results = generate_with_options(&doc, 30, Some(1), &options);
+        for question in ["1", "2", "3"] {
+            assert_eq!(results.iter().filter(|result| result.content == question).count(), 10);
+        }
+        for result in generate_with_options(&doc, 5, Some(3), &options) {
+            assert_eq!(result.questions.iter().map(|q| q.index).sorted().collect::<Vec<usize>>(), vec![0, 1, 2]);
+        }
+    }
+
+    #[test]
+    fn test_currency_expressions() {
+        let doc = process("|<q>|<v>price: real = [1234.5,1234.5]</v>||<e currency>price*qty</e>|</q>||<q>|<e currency>0-0.126</e>| |<e currency>999.999</e>|</q>|");
+        assert!(doc.questions[0].vars.iter().any(|var| var.name == "qty"));
+        let options = GenerateOptions::default();
+        let mut rng = rand::thread_rng();
+        let content = gen_question_text(&doc.questions[1], 1, &HashMap::new(), &HashMap::new(), &options, &mut rng).unwrap().content;
+        assert_eq!(content, "-$0.13 $1,000.00");
+        let options = GenerateOptions { currency_symbol: String::from("€"), thousands_separator: String::from("."), decimal_separator: String::from(","), ..Default::default() };
+        let globals = HashMap::from([("qty", Num::Int(1000))]);
+        let doc = process("|<q>|<v>price: real = [1234.5,1234.5]</v>||<e currency>price*qty</e>|</q>|");
+        let mut question = doc.questions.into_iter().next().unwrap();
+        question.vars.retain(|var| var.name != "qty");
+        assert_eq!(gen_question_text(&question, 0, &globals, &HashMap::new(), &options, &mut rng).unwrap().content, "€1.234.500,00");
+    }
+
+    #[test]
+    fn test_layout_has_one_more_element_than_questions() {
+        let templates = [
+            "",
+            "no questions",
+            FORM3,
+            "|<q>1</q>||<q if=honors>2</q>|end",
+            "|<v>g: int = [1,2]</v>||<q>|<e>g</e>|</q>||<q>|<part>a</part>||<part>b</part>|</q>|",
+            "|<unit u>|<q>1</q>|</unit>||<use u/>||<use u/>||<q>3</q>|",
+        ];
+        for template in templates {
+            let doc = process(template);
+            assert_eq!(doc.layout.len(), doc.questions.len() + 1);
+        }
+        let templates = [
+            "",
+            "|<q>1</q>||<a>1</a>||<q>2</q>||<a>2</a>|",
+            "start|<q>1</q>| |<a>1</a>|middle|<q if=honors>2</q>|\n|<a>2</a>|",
+            "|<unit u>|<q>1</q>||<a>1</a>|</unit>||<use u/>||<use u/>|",
+        ];
+        for template in templates {
+            let doc = process_with_answers(template);
+            assert_eq!(doc.layout.len(), doc.questions.len() + 1);
+        }
+        let doc = process(FORM3);
+        assert_eq!(doc.layout, vec!["", "", "", ""]);
+        assert_eq!(gen_form(&doc, Some(&vec![2, 0, 1]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "312");
+    }
+
+    #[test]
+    fn test_fixed_value_declarations() {
+        let doc = process("|<q>|<v>a: int = 7</v>||<v>b: real = -2.5</v>||<e>a</e>| |<e>b</e>| |<e>a*b</e>|</q>|");
+        let var = doc.questions[0].vars.iter().find(|var| var.name == "a").unwrap();
+        assert_eq!((&var.min[..], &var.max[..]), ("7", "7"));
+        for _ in 0..10 {
+            assert_eq!(gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "7 -2.5 -17.5");
+        }
+    }
+
+    #[test]
+    fn test_batch_stats() {
+        let doc = process("|<v>g: int = [10,10]</v>||<q>|<v>a: int = [1,3]</v>||<e>a</e>|</q>||<q>|<v>x: int linked g ± 1</v>||<e>x</e>|</q>|");
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let tests = generate_with_rng(&doc, 40, Some(2), &mut rng);
+        let stats = batch_stats(&tests);
+        assert_eq!(stats.globals["g"], VarStats { min: 10.0, max: 10.0, mean: 10.0, count: 40 });
+        let a = &stats.variables[&(0, String::from("a"))];
+        assert_eq!((a.min, a.max, a.count), (1.0, 3.0, 40));
+        assert!(a.mean > 1.0 && a.mean < 3.0);
+        let x = &stats.variables[&(1, String::from("x"))];
+        assert_eq!((x.min, x.max), (9.0, 11.0));
+        assert_eq!(stats.orderings, 2);
+        for question in tests.iter().flat_map(|test| test.questions.iter()).filter(|q| q.index == 0) {
+            assert_eq!(question.content, question.values["a"].to_string());
+        }
+    }
+
+    #[test]
+    fn test_check_answer() {
+        assert!(check_answer("42", " 42 ", None));
+        assert!(check_answer("1.5", "3/2", None));
+        assert!(check_answer("-$1,234.56", "-1234,56", None));
+        assert!(check_answer("1.000.000", "1e6", None));
+        assert!(check_answer("9.81 m/s", "9.8 M/S", Some(0.05)));
+        assert!(check_answer("9.81 m/s", "9.8", Some(0.05)));
+        assert!(!check_answer("9.81", "9.8", None));
+        assert!(!check_answer("9.81 m/s", "9.81 km/s", None));
+        assert!(check_answer("The  answer", "the answer", None));
+        assert!(!check_answer("yes", "no", None));
+        //A lone separator before three digits can be read either way
+        assert!(check_answer("1234", "1,234", None));
+        assert!(check_answer("1234", "1.234", None));
+        assert!(check_answer("1.234", "1,234", None));
+        assert!(check_answer("1,234 kg", "1234 kg", None));
+        assert!(check_answer("-1234", "-1.234", None));
+        assert!(!check_answer("1234", "1,23", None));
+        assert!(!check_answer("1234", "0,234", None));
+        assert!(!check_answer("12340", "12,3400", None));
+    }
+
+    #[test]
+    fn test_generate_all() {
+        let doc = process("|<v>g: int = [1,2]</v>||<q>|<e>g</e>|</q>|,|<q>|<v>a: int = [0,1]</v>||<v>r: real = [0.5,0.5]</v>||<e>a+r</e>|</q>|,|<q>x</q>|");
+        let tests = generate_all(&doc, Some(2), 100).unwrap();
+        //Every order has both values of g, and the orders using the second question also have both values of a
+        assert_eq!(tests.len(), 2 * 4 + 2 * 2 + 2 * 4);
+        let contents: HashSet<&str> = tests.iter().map(|test| &test.content[..]).collect();
+        assert!(contents.contains("1,0.5,") && contents.contains("x,2,") && contents.contains("1.5,x,"));
+        assert_eq!(generate_all(&doc, Some(2), 19).err(), Some(MorphiusError::TooManyTests { count: 20, cap: 19 }));
+        let doc = process("|<q>|<v>r: real = [0,1]</v>||<e>r</e>|</q>|");
+        assert!(matches!(generate_all(&doc, None, 100), Err(MorphiusError::NotDiscrete { .. })));
+    }
+
+    #[test]
+    fn test_generate_named_forms() {
+        let doc = process("|<q>|<e>a</e>|</q>|,|<q>2</q>|,|<q>3</q>|");
+        let forms = generate_named_forms(&doc, 6, Some(3)).unwrap();
+        let orders: HashSet<Vec<usize>> = forms.iter().map(|(_, test)| test.questions.iter().map(|q| q.index).collect()).collect();
+        assert_eq!(orders.len(), 6);
+        let again = generate_named_forms(&doc, 6, Some(3)).unwrap();
+        for ((label, form), (other_label, other)) in forms.iter().zip(again.iter()) {
+            assert_eq!((label, &form.content), (other_label, &other.content));
+        }
+        assert_eq!(generate_named_forms(&doc, 30, Some(1)).err(), Some(MorphiusError::TooManyForms { count: 30 }));
+        assert_eq!(generate_named_forms(&doc, 4, Some(1)).err(), Some(MorphiusError::NotEnoughOrders { forms: 4, orders: 3 }));
+
+        //Forms are drawn without listing every order, and the seed of the template chooses them
+        let many = process(&(0..12).map(|i| format!("|<q>{}</q>|", i)).join(","));
+        let forms = generate_named_forms(&many, 26, None).unwrap();
+        assert_eq!(forms.last().unwrap().0, 'Z');
+        let orders: HashSet<Vec<usize>> = forms.iter().map(|(_, test)| test.questions.iter().map(|q| q.index).collect()).collect();
+        assert_eq!(orders.len(), 26);
+        let first = |doc: &Document| generate_named_forms(doc, 1, None).unwrap().remove(0).1.content;
+        let seeded = |seed: u64| process(&format!("|<seed>{}</seed>|{}", seed, many.to_template()));
+        assert_eq!(first(&seeded(1)), first(&seeded(1)));
+        assert!((2..6).any(|seed| first(&seeded(seed)) != first(&seeded(1))));
+        let required = process("|<q required>r</q>|,|<q>a</q>|,|<q>b</q>|");
+        assert!(generate_named_forms(&required, 4, Some(1)).is_err());
+        assert!(generate_named_forms(&required, 4, Some(2)).unwrap().iter().all(|(_, test)| test.questions.iter().any(|q| q.index == 0)));
+    }
+
+    #[test]
+    fn test_unicode_variable_names() {
+        let doc = process("|<v>Δ: int = [3,3]</v>||<q>|<v>α: int = [2,2]</v>||<v>β_1: real = [0.5,0.5]</v>||<v>x: int linked Δ ± 0</v>||<e>α*β_1 + x</e>| |<e>abs(0-α)</e>|</q>|");
+        let names: HashSet<&str> = doc.questions[0].vars.iter().map(|var| &var.name[..]).collect();
+        assert_eq!(names, HashSet::from(["α", "β_1", "x"]));
+        assert_eq!(validate(&doc), Ok(()));
+        assert_eq!(gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content, "4 2");
+        let doc = process("|<unit ü>|<q>|<e>ω</e>|</q>|</unit>||<use ü/>|");
+        assert_eq!(doc.questions.len(), 1);
+    }
+
+    #[test]
+    fn test_reversed_bounds_are_rejected() {
+        let error = try_process("|<q>|<v>a: int = [10,1]</v>||<e>a</e>|</q>|").err();
+        assert_eq!(error, Some(MorphiusError::InvalidBounds { name: String::from("a"), min: String::from("10"), max: String::from("1") }));
+        assert_eq!(error.unwrap().to_string(), "The variable 'a' is declared with the range [10,1], which has no possible values");
+        assert!(try_process("|<v>g: real = [1.5,-1.5]</v>||<q>|<e>g</e>|</q>|").is_err());
+        assert!(try_process("|<q>|<v>a: int = [1.2,1.8]</v>||<e>a</e>|</q>|").is_err());
+        assert!(try_process("|<q>|<v>a: int = [1,1]</v>||<v>b: real = [-1e3,-2.5]</v>||<e>a+b</e>|</q>|").is_ok());
+        assert!(try_process_with_answers("|<q>|<v>a: int = [5,4]</v>||<e>a</e>|</q>||<a>|<e>a</e>|</a>|").is_err());
+    }
+
+    #[test]
+    fn test_hints() {
+        let doc = process("|<q>Question|<hint>Try |<e>b</e>|</hint>|</q>|");
+        assert_eq!(doc.questions[0].hints.len(), 1);
+        assert!(doc.questions[0].vars.iter().any(|var| var.name == "b"));
+        let doc = process_with_answers("|<q>|<v>a: int = [1,9]</v>|What is |<e>a</e>|*2?|<hint>Add |<e>a</e>| to itself</hint>||<hint>Or double it</hint>|</q>||<a>|<e>a*2</e>|</a>||<q>No hint</q>||<a>None</a>|");
+        let test = gen_form(&doc, Some(&vec![1, 0]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+        let a = test.questions[1].values["a"];
+        assert_eq!(test.content, format!("No hintWhat is {}*2?", a));
+        assert_eq!(test.questions[0].hint, "");
+        assert_eq!(test.hints(), format!("2. Add {} to itself\nOr double it", a));
+    }
+
+    #[test]
+    fn test_paginate() {
+        let doc = process("Name:|<q>1</q>||<q>2</q>|");
+        let tests = vec![gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap(), gen_form(&doc, Some(&vec![1, 0]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap()];
+        assert_eq!(paginate(&tests, "Form {{index}} of 2 ({{index}})"), "Form 1 of 2 (1)\nName:12\u{000C}Form 2 of 2 (2)\nName:21");
+        assert_eq!(paginate(&tests, ""), "Name:12\u{000C}Name:21");
+        assert_eq!(paginate(&[], "Header"), "");
+    }
+
+    #[test]
+    fn test_random_functions_match_between_question_and_answer() {
+        let doc = process_with_answers("|<q>|<v>n: int = [6,6]</v>|Roll |<e>randint(1,n)</e>| and |<e>randint(1, 20)</e>||<part>|<e>rand()</e>|</part>|</q>||<a>|<e>randint(1,6)*2</e>| |<e>randint(1,20)</e>| |<e>rand()</e>|</a>|");
+        assert_eq!(validate(&doc), Ok(()));
+        for _ in 0..20 {
+            let question = gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+            let content: Vec<&str> = question.content.split(' ').collect();
+            let die: i64 = content[1].parse().unwrap();
+            assert!((1..=6).contains(&die));
+            //randint(1, 20) and randint(1,20) are written differently so they are separate calls
+            assert_eq!(question.answer.split(' ').next().unwrap(), (die * 2).to_string());
+            assert_eq!(content[4], question.answer.split(' ').nth(2).unwrap());
+        }
+        //Repeating a call in one expression draws it again, and the answer gets the same draws in the same order
+        let doc = process_with_answers("|<q>|<e>randint(1,6)</e>| |<e>randint(1,6)+randint(1,6)</e>|</q>||<a>|<e>randint(1,6)+randint(1,6)</e>| |<e>randint(1,6)</e>|</a>|");
+        let mut sums = HashSet::new();
+        for _ in 0..50 {
+            let question = gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+            let (content, answer): (Vec<&str>, Vec<&str>) = (question.content.split(' ').collect(), question.answer.split(' ').collect());
+            assert_eq!((content[1], content[0]), (answer[0], answer[1]));
+            sums.insert(content[1].parse::<i64>().unwrap() % 2);
+        }
+        assert_eq!(sums.len(), 2);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let doc = process_with_answers("|<meta>\ntitle: Algebra Quiz\nauthor: Ms. Smith\ndate: 2022-05-01 10:00\nnot metadata\n</meta>|Name:|<q>1</q>||<a>1</a>|");
+        assert_eq!(doc.layout, vec!["Name:", ""]);
+        assert_eq!(doc.metadata.len(), 3);
+        assert_eq!(doc.meta("author"), Some("Ms. Smith"));
+        assert_eq!(doc.meta("date"), Some("2022-05-01 10:00"));
+        assert_eq!(doc.fill_metadata("{{title}} by {{author}} {{missing}}"), "Algebra Quiz by Ms. Smith {{missing}}");
+        assert!(process("|<q>1</q>|").metadata.is_empty());
+    }
+
+    #[test]
+    fn test_padded_expressions() {
+        let doc = process("|<q>[|<e width=5>12*3</e>|][|<e width=5 align=left>0-1.5</e>|][|<e width=6 fill=0>0-42</e>|][|<e width=2>123456</e>|][|<e width=10 currency>1234</e>|]</q>|");
+        assert_eq!(doc.questions[0].expressions[1].align, Align::Left);
+        let content = gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content;
+        assert_eq!(content, "[   36][-1.5 ][-00042][123456][ $1,234.00]");
+    }
+
+    #[test]
+    fn test_bundle() {
+        let doc = process_with_answers("|<q>1</q>||<a>A1</a>|,|<q>2</q>||<a>A2</a>|");
+        let tests = vec![gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap(), gen_form(&doc, Some(&vec![1, 0]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap()];
+        let expected = format!("Test 1\n1,2\n{0}\nTest 1 Answers\nA1,A2\u{000C}Test 2\n2,1\n{0}\nTest 2 Answers\nA2,A1", CUT_LINE);
+        assert_eq!(bundle(&tests), expected);
+        assert_eq!(bundle(&[]), "");
+    }
+
+    #[test]
+    fn test_answer_only_variables_are_reported() {
+        let error = try_process_with_answers("|<q>1</q>||<a>1</a>||<q>|<e>a</e>|</q>||<a>|<e>a+b</e>|</a>|").err();
+        assert_eq!(error, Some(MorphiusError::UnknownVariable { name: String::from("b"), question: 1 }));
+        assert_eq!(error.unwrap().to_string(), "The variable 'b' is used in question 2 but isn't used in the question itself");
+        assert!(try_process_with_answers("|<v>g: int = [1,2]</v>||<q>|<e>a</e>|</q>||<a>|<e>a+g</e>|</a>|").is_ok());
+    }
+
+    #[test]
+    fn test_to_template_round_trip() {
+        let template = "|<meta>author: Ms. Smith\ntitle: Quiz</meta>||<v>g: int = [1,5]</v>|Name:\n|<q>|<v>a: real = [0.5,2.5]</v>||<v>x: int linked g ± 2</v>|Find |<e currency width=10>a*x</e>|:|<part>|<e>b</e>|</part>||<part>|<e width=4 align=left fill=0>g</e>|</part>||<hint>Use |<e>b</e>|</hint>|</q>||<a>|<part>|<e>b</e>|</part>||<part>|<e>g</e>|</part>|</a>|\n|<q if=a,b>|<v>c: int = 3</v>|Value |<e>c</e>|</q>||<a>None</a>|";
+        let doc = process_with_answers(template);
+        assert_eq!(doc.to_template(), template);
+        let again = process_with_answers(&doc.to_template());
+        assert_eq!(again.to_template(), template);
+        assert_eq!(process("No questions").to_template(), "No questions");
+    }
+
+    #[test]
+    fn test_mod() {
+        let doc = process("|<q>|<v>a: int = [17,17]</v>||<e>mod(a, 5)</e>| |<e>mod(0-7,3)</e>| |<e>mod(7,0-3)</e>| |<e>mod(mod(a,10)+1, 5)*2</e>| |<e>mod(7.6,3)</e>|</q>|");
+        assert_eq!(validate(&doc), Ok(()));
+        let content = gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content;
+        assert_eq!(content, "2 2 1 6 2");
+        //The arguments can be in parentheses or call other functions
+        let doc = process("|<q>|<v>a: int = [-4,-4]</v>||<v>b: int = [3,3]</v>||<e>mod((a+1),3)</e>| |<e>mod(abs(a),b)</e>| |<e>mod(a, max(b,2))</e>| |<e>mod(mod(a,b), (b-1))</e>|</q>|");
+        let content = gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().content;
+        assert_eq!(content, "0 1 2 0");
+    }
+
+    #[test]
+    fn test_grain() {
+        let doc = process("|<q>|<v>x: real = [0,10] grain 0.25</v>||<v>y: real = [0,1] grain 0.1</v>||<v>n: int = [1,100] grain 5</v>||<e>x</e>| |<e>y</e>| |<e>n</e>|</q>|");
+        assert_eq!(doc.questions[0].vars.iter().find(|var| var.name == "x").unwrap().grain, Some(String::from("0.25")));
+        for _ in 0..50 {
+            let question = gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+            let x = question.values["x"];
+            assert!((0.0..=10.0).contains(&x) && (x * 4.0).fract() == 0.0);
+            let y: Vec<&str> = question.content.split(' ').collect();
+            assert!(y[1].len() <= 3);
+            let n = question.values["n"];
+            assert!((5.0..=100.0).contains(&n) && n % 5.0 == 0.0);
+        }
+        assert_eq!(generate_all(&process("|<q>|<v>x: real = [0,1] grain 0.5</v>||<e>x</e>|</q>|"), None, 10).unwrap().iter().map(|test| &test.content[..]).collect::<Vec<&str>>(), vec!["0", "0.5", "1"]);
+        assert!(try_process("|<q>|<v>x: real = [0.1,0.2] grain 0.5</v>||<e>x</e>|</q>|").is_err());
+        let template = "|<q>|<v>x: real = [0,10] grain 0.25</v>||<e>x</e>|</q>|";
+        assert_eq!(process(template).to_template(), template);
+    }
+
+    #[test]
+    fn test_named_expressions() {
+        let doc = process_with_answers("|<q>|<v>a: int = [1,9]</v>||<e name=shown>a</e>| and |<e>randint(1,9)</e>||<part>|<e name=part>a+1</e>|</part>|</q>||<a>|<e name=total currency>a+randint(1,9)</e>|</a>||<q>2</q>||<a>2</a>|");
+        assert_eq!(doc.questions[0].answer.as_ref().unwrap().expressions[0].name, Some(String::from("total")));
+        let test = gen_form(&doc, Some(&vec![1, 0]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+        let a = test.questions[1].values["a"];
+        assert_eq!(test.expression_value(1, "shown"), Some(&a.to_string()[..]));
+        assert_eq!(test.expression_value(1, "part"), Some(&(a + 1.0).to_string()[..]));
+        assert_eq!(test.expression_value(1, "total"), Some(&test.questions[1].answer[..]));
+        assert_eq!(test.expression_value(0, "total"), None);
+        assert_eq!(test.expression_value(5, "total"), None);
+    }
+
+    #[test]
+    fn test_try_generate_errors() {
+        let math_error = |template: &str| match try_generate(&process(template), 1, None) {
+            Err(MorphiusError::Math { expression, .. }) => expression,
+            _ => panic!("Expected a math error for {}", template)
+        };
+        assert_eq!(math_error("|<q>|<v>a: int = [2,2]</v>||<e>a+*3</e>|</q>|"), "2+*3");
+        assert_eq!(math_error("|<q>|<e>sqrt(4)</e>|</q>|"), "sqrt(4)");
+        assert_eq!(math_error("|<q>|<e>mod(5, 0)</e>|</q>|"), "mod(5, 0)");
+        let mut doc = process("|<q>|<e>a</e>|</q>|");
+        doc.questions[0].vars = HashSet::from([Var { name: String::from("a"), num_type: String::from("int"), min: String::from("one"), max: String::from("2"), base: None, grain: None, clamp: None, set: None, cycle: None }]);
+        assert!(matches!(try_generate_one(&doc, None), Err(MorphiusError::InvalidBounds { .. })));
+        assert!(matches!(gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()), Err(MorphiusError::InvalidBounds { .. })));
+        assert_eq!(try_generate_one(&process("|<q>|<v>a: int = [3,3]</v>||<e>a*2</e>|</q>|"), None).unwrap().content, "6");
+    }
+
     #[test]
-    fn test_var_bounds_are_processed() {
-        let doc = process("|<q>|<v>x: real = [5,55]</v>||<e>x/x</e>|</q>|");
-        for result in generate(&doc, 3, Some(1)) {
-            assert!(result.content == "1");
+    #[should_panic(expected = "Couldn't evaluate")]
+    fn test_generate_panics_with_the_error() {
+        generate(&process("|<q>|<e>1+*3</e>|</q>|"), 1, None);
+    }
+
+    #[test]
+    fn test_partitions() {
+        let doc = process("|<q>|<v>[a, b,c]: partition(20)</v>|Split 20 into |<e>a</e>|, |<e>b</e>| and |<e>c</e>|</q>|");
+        assert_eq!(doc.questions[0].partitions[0].names, vec!["a", "b", "c"]);
+        assert_eq!(doc.questions[0].partitions[0].total, 20);
+        let mut seen = HashSet::new();
+        for _ in 0..100 {
+            let question = gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+            let values: Vec<f64> = ["a", "b", "c"].iter().map(|name| question.values[*name]).collect();
+            assert_eq!(values.iter().sum::<f64>(), 20.0);
+            assert!(values.iter().all(|value| *value >= 0.0));
+            seen.insert(question.content);
+        }
+        assert!(seen.len() > 10);
+        assert_eq!(gen_partition(1, 7, &mut rand::thread_rng()), vec![7]);
+        assert_eq!(gen_partition(3, 0, &mut rand::thread_rng()), vec![0, 0, 0]);
+        assert_eq!(doc.to_template(), "|<q>|<v>[a,b,c]: partition(20)</v>|Split 20 into |<e>a</e>|, |<e>b</e>| and |<e>c</e>|</q>|");
+    }
+
+    #[test]
+    fn test_real_decimals() {
+        let doc = process("|<q>|<v>x: real = [5,5]</v>||<v>n: int = [5,5]</v>||<e>x</e>| |<e>x*n/3</e>| |<e>n</e>| |<e>n/2</e>|</q>|");
+        let generate_content = |options: &GenerateOptions| gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), options, &mut rand::thread_rng()).unwrap().content;
+        assert_eq!(generate_content(&GenerateOptions::default()), "5 8.333 5 2.5");
+        assert_eq!(generate_content(&GenerateOptions { real_decimals: Some(1), ..Default::default() }), "5.0 8.3 5 2.5");
+        assert_eq!(generate_content(&GenerateOptions { real_decimals: Some(0), ..Default::default() }), "5 8 5 2.5");
+    }
+
+    #[test]
+    fn test_builders() {
+        let var = Var { name: String::from("a"), num_type: String::from("int"), min: String::from("4"), max: String::from("4"), base: None, grain: None, clamp: None, set: None, cycle: None };
+        let question = QuestionBuilder::new().text("a=").expression("a").text(", b=").expression("b*2").var(var).flag("honors").answer_text("Sum: ").answer_expression("a+b").build();
+        assert_eq!(question.vars.len(), 2);
+        assert_eq!(question.layout, vec!["a=", ", b=", ""]);
+        let doc = DocumentBuilder::new().meta("title", "Quiz").layout("Start").question(question).question(QuestionBuilder::new().text("Plain").build()).layout("End").build();
+        assert_eq!(doc.layout, vec!["Start", "", "End"]);
+        let template = doc.to_template();
+        assert_eq!(template, "|<meta>title: Quiz</meta>|Start|<q if=honors>|<v>a: int = 4</v>|a=|<e>a</e>|, b=|<e>b*2</e>|</q>||<a>Sum: |<e>a+b</e>|</a>||<q>Plain</q>|End");
+        let options = GenerateOptions { flags: vec![String::from("honors")], ..Default::default() };
+        let test = gen_form(&doc, None, &HashMap::new(), &options, &mut rand::thread_rng()).unwrap();
+        let b = test.questions[0].values["b"];
+        assert_eq!(test.content, format!("Starta=4, b={}PlainEnd", b * 2.0));
+        assert_eq!(test.questions[0].answer, format!("Sum: {}", 4.0 + b));
+        assert_eq!(DocumentBuilder::new().build().layout, vec![""]);
+    }
+
+    #[test]
+    fn test_questions_share_variables_by_id() {
+        let doc = process_with_answers("|<q id=speed>|<v>v: int = [10,20]</v>|Speed |<e>v</e>|</q>||<a>|<e>v</e>|</a>||<q id=time shares=speed>|<v>t: int = [2,5]</v>|Distance after |<e>t</e>|?</q>||<a>|<e>v*t</e>|</a>||<q shares=time>Double |<e>v*t*2</e>|</q>||<a>|<e>v</e>|</a>|");
+        assert!(validate(&doc).is_ok());
+        assert!(doc.questions[1].vars.iter().all(|var| var.name != "v"));
+        assert_eq!(doc.to_template(), "|<q id=speed>|<v>v: int = [10,20]</v>|Speed |<e>v</e>|</q>||<a>|<e>v</e>|</a>||<q id=time shares=speed>|<v>t: int = [2,5]</v>|Distance after |<e>t</e>|?</q>||<a>|<e>v*t</e>|</a>||<q shares=time>Double |<e>v*t*2</e>|</q>||<a>|<e>v</e>|</a>|");
+        let test = gen_form(&doc, Some(&vec![2, 1]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+        let v = test.questions[0].answer.parse::<f64>().unwrap();
+        let t = test.questions[1].values["t"];
+        assert_eq!(test.questions[1].answer, format!("{}", v * t));
+        assert_eq!(test.questions[0].content, format!("Double {}", v * t * 2.0));
+        let doc = process("|<q shares=later>|<e>a</e>|</q>||<q id=later>|<e>a</e>|</q>|");
+        assert_eq!(validate(&doc), Err(MorphiusError::UnknownQuestion { id: String::from("later"), question: 0 }));
+        assert!(gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).is_err());
+    }
+
+    #[test]
+    fn test_scope_is_cleared_between_questions() {
+        let mut doc = process("|<q>|<v>a: int = [3,3]</v>||<e>a</e>|</q>||<q>|<e>a</e>|</q>|");
+        assert_eq!(gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().questions[0].content, "3");
+        doc.questions[1].vars.clear();
+        let error = gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).err();
+        assert_eq!(error, Some(MorphiusError::UnknownVariable { name: String::from("a"), question: 1 }));
+    }
+
+    #[test]
+    fn test_missing_answer_placeholder() {
+        let doc = process("Key: |<q>Question</q>|");
+        let answers = |options: &GenerateOptions| gen_form(&doc, None, &HashMap::new(), options, &mut rand::thread_rng()).unwrap().answers;
+        assert_eq!(answers(&GenerateOptions::default()), "Key: No Answers Provided");
+        assert_eq!(answers(&GenerateOptions { missing_answer: Some(String::from("Sin respuesta")), ..Default::default() }), "Key: Sin respuesta");
+        assert_eq!(answers(&GenerateOptions { missing_answer: None, ..Default::default() }), "Key: ");
+    }
+
+    #[test]
+    fn test_pick_chooses_a_consistent_alternative() {
+        let doc = process_with_answers("|<q>The |<e pick>cat|dog|bird</e>| has |<e>a</e>| toys</q>||<a>|<e pick>cat|dog|bird</e>|: |<e>a</e>|</a>|");
+        assert_eq!(doc.questions[0].vars.len(), 1);
+        assert_eq!(doc.to_template(), "|<q>The |<e pick>cat|dog|bird</e>| has |<e>a</e>| toys</q>||<a>|<e pick>cat|dog|bird</e>|: |<e>a</e>|</a>|");
+        let mut seen = HashSet::new();
+        for _ in 0..50 {
+            let question = gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+            let animal = question.answer.split(':').next().unwrap().to_string();
+            assert!(["cat", "dog", "bird"].contains(&&animal[..]));
+            assert_eq!(question.content, format!("The {} has {} toys", animal, question.values["a"]));
+            seen.insert(animal);
+        }
+        assert_eq!(seen.len(), 3);
+    }
+
+    #[test]
+    fn test_max_decimals() {
+        let doc = process("|<q>|<v>x: real = [5,5]</v>||<e>x/3</e>| |<e>0-1/3</e>| |<e>2/3</e>| |<e currency>x/3</e>|</q>|");
+        let generate_content = |options: &GenerateOptions| gen_question_text(&doc.questions[0], 0, &HashMap::new(), &HashMap::new(), options, &mut rand::thread_rng()).unwrap().content;
+        assert_eq!(generate_content(&GenerateOptions { max_decimals: Some(1), ..Default::default() }), "1.7 -0.3 0.7 $1.67");
+        assert_eq!(generate_content(&GenerateOptions { max_decimals: Some(0), ..Default::default() }), "2 0 1 $1.67");
+        assert_eq!(generate_content(&GenerateOptions { max_decimals: Some(1), real_decimals: Some(2), ..Default::default() }), "1.7 -0.3 0.7 $1.67");
+        let doc = process_with_answers("|<q>|<v>x: real = [0,10]</v>||<e>x</e>|</q>||<a>|<e>x*2</e>|</a>||<q>|<e>a/7</e>|</q>||<a>|<e>a</e>|</a>|");
+        for (standard, simple) in generate_simplified(&doc, 10, Some(2), &GenerateOptions::default(), 0).unwrap() {
+            assert_eq!(standard.questions.iter().map(|q| q.index).collect::<Vec<usize>>(), simple.questions.iter().map(|q| q.index).collect::<Vec<usize>>());
+            for (standard, simple) in standard.questions.iter().zip(simple.questions.iter()) {
+                assert_eq!(standard.values, simple.values);
+                assert!(!simple.content.contains('.'));
+            }
+        }
+        assert!(matches!(generate_simplified(&process("|<q>|<e>(a</e>|</q>|"), 1, None, &GenerateOptions::default(), 0), Err(MorphiusError::Math { .. })));
+    }
+
+    #[test]
+    fn test_duplicate_declarations() {
+        let template = "|<q>|<e>b</e>|</q>||<q>|<v>a: int = [0,9]</v>||<v>a: int = [0,5]</v>||<e>a</e>|</q>|";
+        let error = MorphiusError::DuplicateDeclaration { name: String::from("a"), question: 1, first: String::from("a: int = [0,9]"), second: String::from("a: int = [0,5]") };
+        assert_eq!(try_process(template).err(), Some(error));
+        assert_eq!(process(template).questions[1].vars.iter().next().unwrap().max, "5");
+        assert!(try_process("|<q>|<v>a: int = [0,9]</v>||<v>a: int = [0,9]</v>||<e>a</e>|</q>||<q>|<v>a: int = [0,5]</v>||<e>a</e>|</q>|").is_ok());
+    }
+
+    #[test]
+    fn test_compact_tags() {
+        let compact = "{{meta}}title: Quiz{{/meta}}{{unit pair}}{{q}}{{e}}a{{/e}}{{/q}}{{/unit}}{{q}}{{part}}{{e pick}}x|y{{/e}}{{/part}}{{hint}}Page {{index}}{{/hint}}{{/q}}{{use pair /}}";
+        assert_eq!(expand_compact(compact), "|<meta>title: Quiz</meta>||<unit pair>|<q>|<e>a</e>|</q>|</unit>||<q>|<part>|<e pick>x|y</e>|</part>||<hint>Page {{index}}</hint>|</q>||<use pair/>|");
+        assert_eq!(expand_compact("|<q>Standard</q>|{{q}}Compact{{/q}}"), "|<q>Standard</q>||<q>Compact</q>|");
+        let doc = process_with_answers(&expand_compact("{{q id=one}}{{v}}a: int = [2,2]{{/v}}{{e}}a{{/e}} {{e currency}}a*3{{/e}}{{/q}}{{a}}{{e}}a+1{{/e}}{{/a}}"));
+        let test = gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap();
+        assert_eq!(test.content, "2 $6.00");
+        assert_eq!(test.answers, "3");
+    }
+
+    #[test]
+    fn test_sample_question() {
+        let doc = process_with_answers("|<q>|<v>x: real = [0,1]</v>||<e>x</e>| and |<e>n</e>|</q>||<a>|<e>x+n</e>|</a>||<q>Plain</q>||<a>Done</a>|");
+        let samples: HashSet<(String, String)> = (0..20).map(|seed| doc.questions[0].sample_seeded(seed)).collect();
+        assert!(samples.len() > 1);
+        assert_eq!(doc.questions[0].sample_seeded(3), doc.questions[0].sample_seeded(3));
+        let (content, answer) = doc.questions[0].sample();
+        let values: Vec<f64> = content.split(" and ").map(|value| value.parse().unwrap()).collect();
+        assert!((answer.parse::<f64>().unwrap() - (values[0] + values[1])).abs() < 1e-2);
+        assert_eq!(doc.questions[1].sample(), (String::from("Plain"), String::from("Done")));
+        assert_eq!(doc.questions[0].try_sample_seeded(3), Ok(doc.questions[0].sample_seeded(3)));
+        assert!(matches!(process("|<q>|<e>a/0</e>|</q>|").questions[0].try_sample(), Err(MorphiusError::NotFinite { .. })));
+    }
+
+    #[test]
+    fn test_text_expressions_are_not_evaluated() {
+        let doc = process_with_answers("|<q>Capital of |<e>France</e>|? |<e>a+b</e>|</q>||<a>|<e>The capital is Paris</e>|, |<e width=8>Paris, France</e>|</a>|");
+        assert_eq!(doc.questions[0].vars.len(), 3);
+        let answer = doc.questions[0].answer.as_ref().unwrap();
+        assert!(answer.expressions.iter().all(|expression| expression.mode == ExpMode::Text));
+        assert!(validate(&doc).is_ok());
+        let (_, answer) = doc.questions[0].sample();
+        assert_eq!(answer, "The capital is Paris, Paris, France");
+        assert!(process("|<q>|<e>(a+b</e>|</q>|").questions[0].expressions[0].mode == ExpMode::Number);
+        assert_eq!(doc.to_template(), "|<q>Capital of |<e>France</e>|? |<e>a+b</e>|</q>||<a>|<e>The capital is Paris</e>|, |<e width=8>Paris, France</e>|</a>|");
+    }
+
+    #[test]
+    fn test_answer_numbering_matches_content_numbering() {
+        let doc = process_with_answers("1. |<q>A|<e>a</e>|</q>||<a>A|<e>a</e>|</a>|\n2. |<q if=extra>B|<e>a</e>|</q>||<a>B|<e>a</e>|</a>|\n3. |<q>C|<e>a</e>|</q>||<a>C|<e>a</e>|</a>|\n4. |<q>D|<e>a</e>|</q>||<a>D|<e>a</e>|</a>|\n");
+        let balanced = GenerateOptions { balanced: true, ..Default::default() };
+        let flagged = GenerateOptions { flags: vec![String::from("extra")], ..Default::default() };
+        let mut batches = vec![generate(&doc, 10, None), generate(&doc, 10, Some(2)), generate(&doc, 10, Some(3))];
+        batches.push(generate_with_options(&doc, 10, Some(2), &balanced));
+        batches.push(generate_with_options(&doc, 10, Some(4), &flagged));
+        batches.push(generate_named_forms(&doc, 4, Some(2)).unwrap().into_iter().map(|(_, test)| test).collect());
+        for test in batches.iter().flatten() {
+            assert_eq!(test.content, test.answers);
+            for (pos, question) in test.questions.iter().enumerate() {
+                let line = format!("{}. {}", pos + 1, question.content);
+                assert!(test.content.lines().any(|content_line| content_line == line));
+                assert!(test.answers.lines().any(|answer_line| answer_line.starts_with(&format!("{}. ", pos + 1)) && answer_line.ends_with(&question.answer)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_dependent_bounds_are_clamped() {
+        let doc = process("|<q>|<v>x: real = [0,10]</v>||<v>y: real = [x-5, x+5] clamp [0,10]</v>||<v>n: int = [k, k*2]</v>||<e>x</e>| |<e>y</e>| |<e>k</e>| |<e>n</e>|</q>|");
+        assert!(validate(&doc).is_ok());
+        assert_eq!(doc.questions[0].vars.len(), 4);
+        for _ in 0..100 {
+            let values = &gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().questions[0].values;
+            assert!(values["y"] >= (values["x"] - 5.0).max(0.0) && values["y"] <= (values["x"] + 5.0).min(10.0));
+            assert!(values["n"] >= values["k"] && values["n"] <= values["k"] * 2.0);
+        }
+        assert!(doc.to_template().contains("|<v>y: real = [x-5,x+5] clamp [0,10]</v>|"));
+        //The range of y is only empty when x is 9 or 10, so x is sampled again
+        let doc = process("|<q>|<v>x: int = [0,10]</v>||<v>y: int = [x+1, 9]</v>||<e>x</e>| |<e>y</e>|</q>|");
+        for _ in 0..50 {
+            let values = &gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap().questions[0].values;
+            assert!(values["x"] < values["y"]);
+        }
+        let doc = process("|<q>|<v>x: int = [0,10]</v>||<v>y: int = [x+20, 30] clamp [0,5]</v>||<e>y</e>|</q>|");
+        assert!(matches!(gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()), Err(MorphiusError::InvalidBounds { .. })));
+        assert!(matches!(generate_all(&doc, None, 100), Err(MorphiusError::NotDiscrete { .. })));
+    }
+
+    #[test]
+    fn test_forbidden_orders_are_never_used() {
+        let doc = process("|<q>0</q>||<q>1</q>||<q>2</q>|");
+        let options = GenerateOptions { forbidden_orders: vec![vec![0, 1, 2], vec![2, 1, 0]], ..Default::default() };
+        for test in generate_tests(&doc, 50, Some(3), &options, &mut rand::thread_rng()).unwrap() {
+            assert!(test.content != "012" && test.content != "210");
+        }
+        let balanced = GenerateOptions { balanced: true, forbidden_orders: vec![vec![0, 1, 2], vec![1, 2, 0]], ..Default::default() };
+        for test in generate_tests(&doc, 30, Some(3), &balanced, &mut rand::thread_rng()).unwrap() {
+            assert!(test.content != "012" && test.content != "120");
+        }
+        let options = GenerateOptions { forbidden_orders: vec![vec![0, 1], vec![1, 0]], ..Default::default() };
+        assert_eq!(generate_tests(&process("|<q>0</q>||<q>1</q>|"), 1, Some(2), &options, &mut rand::thread_rng()).err(), Some(MorphiusError::NoAllowedOrder));
+        let options = GenerateOptions { forbidden_orders: vec![vec![0, 1, 2]], ..Default::default() };
+        assert_eq!(generate_tests(&doc, 1, None, &options, &mut rand::thread_rng()).err(), Some(MorphiusError::NoAllowedOrder));
+    }
+
+    #[test]
+    fn test_points_follow_their_questions() {
+        let doc = process("|<q points=3>A |<points/>|</q>||<q points=1.5 if=extra>B |<points/>|</q>||<q>C|<points/>|</q>|");
+        assert_eq!(doc.questions[1].points, Some(1.5));
+        assert_eq!(doc.to_template(), "|<q points=3>A |<points/>|</q>||<q if=extra points=1.5>B |<points/>|</q>||<q>C|<points/>|</q>|");
+        let options = GenerateOptions { flags: vec![String::from("extra")], ..Default::default() };
+        for test in generate_with_options(&doc, 10, Some(3), &options) {
+            assert_eq!(test.total_points(), 4.5);
+            for question in test.questions.iter() {
+                let expected = match question.index { 0 => ("A 3", Some(3.0)), 1 => ("B 1.5", Some(1.5)), _ => ("C", None) };
+                assert_eq!((&question.content[..], question.points), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_to_dir() {
+        let dir = std::env::temp_dir().join(format!("morphius_test_generate_to_dir_{}", std::process::id()));
+        let doc = process("Test: |<q>|<v>a: int = [1,3]</v>||<e>a</e>|</q>|");
+        generate_to_dir(&doc, 3, None, &dir, &GenerateOptions::default()).unwrap();
+        let mut files: Vec<String> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        files.sort();
+        assert_eq!(files, vec!["test_1.txt", "test_2.txt", "test_3.txt"]);
+        assert!(["Test: 1", "Test: 2", "Test: 3"].contains(&&fs::read_to_string(dir.join("test_2.txt")).unwrap()[..]));
+        let options = GenerateOptions { forbidden_orders: vec![vec![0]], ..Default::default() };
+        assert_eq!(generate_to_dir(&doc, 1, None, &dir, &options).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_generate_keys_matches_seeded_tests() {
+        let doc = process_with_answers("|<v>g: int = [1,9]</v>||<q>|<e>a</e>||<part>|<e>b</e>|</part>||<part>|<e>randint(1,6)</e>|</part>||<hint>|<e>a+1</e>|</hint>|</q>||<a>|<part>|<e>b</e>|</part>||<part>|<e>randint(1,6)</e>|</part>|</a>||<q>|<e pick>x|y|z</e>|</q>||<a>|<e pick>x|y|z</e>| |<e>g</e>|</a>|");
+        let seeds: Vec<u64> = (0..20).collect();
+        let keys = generate_keys(&doc, &seeds, Some(2));
+        for (seed, key) in seeds.iter().zip(keys) {
+            let test = generate_with_rng(&doc, 1, Some(2), &mut rand::rngs::StdRng::seed_from_u64(*seed)).remove(0);
+            assert_eq!(test.answers, key);
+        }
+        let test = gen_form_with(&doc, None, &HashMap::new(), &GenerateOptions::default(), false, None, &mut rand::thread_rng()).unwrap();
+        assert!(test.content.is_empty() && test.questions.iter().all(|question| question.content.is_empty() && question.hint.is_empty()));
+        assert!(matches!(try_generate_keys(&process_with_answers("|<q>1</q>||<a>|<e>b</e>|</a>|"), &[1], None), Err(MorphiusError::UnknownVariable { .. })));
+    }
+
+    #[test]
+    fn test_special_sets() {
+        let doc = process("|<q>|<v>p: int = prime[2,50]</v>||<v>s: int = set(square, [1,100])</v>||<v>c: int = cube[-10, 30]</v>||<e>p</e>| |<e>s</e>| |<e>c</e>|</q>|");
+        assert_eq!(doc.questions[0].vars.iter().find(|var| var.name == "s").unwrap().set, Some(String::from("square")));
+        for test in generate(&doc, 50, None) {
+            let values: Vec<i64> = test.content.split(' ').map(|value| value.parse().unwrap()).collect();
+            assert!(values[0] >= 2 && values[0] <= 50 && (2..values[0]).all(|d| values[0] % d != 0));
+            assert!([1, 4, 9, 16, 25, 36, 49, 64, 81, 100].contains(&values[1]));
+            assert!([0, 1, 8, 27].contains(&values[2]));
+        }
+        let primes: Vec<String> = generate_all(&process("|<q>|<v>p: int = prime[10,30]</v>||<e>p</e>|</q>|"), None, 10).unwrap().into_iter().map(|test| test.content).collect();
+        assert_eq!(primes, vec!["11", "13", "17", "19", "23", "29"]);
+        assert!(try_process("|<q>|<v>p: int = prime[24,28]</v>||<e>p</e>|</q>|").is_err());
+        assert_eq!(doc.to_template(), "|<q>|<v>c: int = cube[-10,30]</v>||<v>p: int = prime[2,50]</v>||<v>s: int = square[1,100]</v>||<e>p</e>| |<e>s</e>| |<e>c</e>|</q>|");
+        //Huge ranges are drawn from without listing their members
+        let doc = try_process("|<q>|<v>p: int = prime[2,1e12]</v>||<v>e: int = set(even,[0,1e15])</v>||<v>o: int = odd[-1e18,1e18]</v>||<v>s: int = square[0,9e18]</v>||<e>p</e>| |<e>e</e>| |<e>o</e>| |<e>s</e>|</q>|").unwrap();
+        for test in try_generate(&doc, 20, None).unwrap() {
+            let values: Vec<i64> = test.content.split(' ').map(|value| value.parse::<f64>().unwrap() as i64).collect();
+            assert!((2..=1_000_000_000_000).contains(&values[0]) && is_prime(values[0]));
+            let (e, o, s) = (test.questions[0].values["e"], test.questions[0].values["o"], test.questions[0].values["s"]);
+            assert!((0.0..=1e15).contains(&e) && e % 2.0 == 0.0);
+            assert!(o.abs() <= 1e18);
+            assert!(s >= 0.0 && (s.sqrt().round().powi(2) - s).abs() <= s * 1e-12);
+        }
+        assert_eq!(generate_all(&doc, None, 10).err(), Some(MorphiusError::SetTooLarge { name: String::from("p") }));
+        assert!(matches!(generate_all(&process("|<q>|<v>e: int = even[0,1e15]</v>||<e>e</e>|</q>|"), None, 10), Err(MorphiusError::TooManyTests { .. })));
+        assert!(try_process("|<q>|<v>p: int = prime[1e12,1e12]</v>||<e>p</e>|</q>|").is_err());
+        //3215031751 passes the test for the bases 2, 3, 5 and 7 but isn't prime
+        assert_eq!((is_prime(1_000_000_007), is_prime(3_215_031_751), is_prime(i64::MAX)), (true, false, false));
+    }
+
+    #[test]
+    fn test_debug_dump() {
+        let doc = process_with_answers("|<meta>title: Quiz</meta>||<v>g: int = [1,3]</v>|Intro|<q points=2>|<v>a: int = [1,5]</v>|What is |<e>a+g</e>|?|<part>|<e pick>x|y</e>|</part>||<hint>Add</hint>|</q>||<a>|<e>a+g</e>|</a>|");
+        assert_eq!(doc.debug_dump(), "\
+Document
+  layout: [\"Intro\", \"\"]
+  globals:
+    g: int = [1,3]
+  metadata:
+    title: \"Quiz\"
+  question 1:
+    points: 2
+    vars:
+      a: int = [1,5]
+    layout: [\"What is \", \"?{{part}}\"]
+    expressions:
+      a+g [var a, other \"+\", var g] Number
+    part:
+      layout: [\"\", \"\"]
+      expressions:
+        x|y [other \"x|y\"] Pick
+    hint:
+      layout: [\"Add\"]
+    answer:
+      layout: [\"\", \"\"]
+      expressions:
+        a+g [var a, other \"+\", var g] Number
+");
+    }
+
+    #[test]
+    fn test_generate_filtered() {
+        let doc = process("|<q tags=algebra>A</q>|-|<q tags=geometry>G</q>|-|<q tags=algebra,fractions if=honors>F</q>|-|<q>U</q>|");
+        assert_eq!(doc.questions[2].tags, vec!["algebra", "fractions"]);
+        let tests = generate_filtered(&doc, &["algebra"], 10, Some(3), &GenerateOptions::default());
+        assert!(tests.iter().all(|test| test.content == "A---"));
+        let options = GenerateOptions { flags: vec![String::from("honors")], ..Default::default() };
+        let tests = generate_filtered(&doc, &["algebra", "geometry"], 20, Some(3), &options);
+        assert!(tests.iter().all(|test| test.questions.len() == 3 && test.questions.iter().all(|question| question.index != 3)));
+        assert!(generate_filtered(&doc, &["fractions"], 1, None, &options)[0].content.contains('F'));
+        assert_eq!(generate_filtered(&doc, &[], 1, None, &GenerateOptions::default())[0].content, "A-G-U-");
+        assert_eq!(doc.to_template(), "|<q tags=algebra>A</q>|-|<q tags=geometry>G</q>|-|<q if=honors tags=algebra,fractions>F</q>|-|<q>U</q>|");
+    }
+
+    #[test]
+    fn test_template_seed() {
+        let template = "|<seed>12345</seed>|Quiz|<q>|<e>a</e>| |<e>rand()</e>|</q>||<q>|<e>b</e>|</q>|";
+        let doc = process(template);
+        assert_eq!(doc.seed, Some(12345));
+        assert_eq!(doc.layout, vec!["Quiz", "", ""]);
+        let contents = |doc: &Document| generate(doc, 5, Some(2)).into_iter().map(|test| test.content).collect::<Vec<String>>();
+        assert_eq!(contents(&doc), contents(&process(template)));
+        let expected: Vec<String> = generate_with_rng(&doc, 5, Some(2), &mut rand::rngs::StdRng::seed_from_u64(12345)).into_iter().map(|test| test.content).collect();
+        assert_eq!(contents(&doc), expected);
+        assert_eq!(try_generate(&doc, 5, Some(2)).unwrap().into_iter().map(|test| test.content).collect::<Vec<String>>(), expected);
+        assert_eq!(doc.to_template(), template);
+        assert_eq!(process(&expand_compact("{{seed}}7{{/seed}}{{q}}Q{{/q}}")).seed, Some(7));
+        assert_eq!(process("|<q>Q</q>|").seed, None);
+    }
+
+    #[test]
+    fn test_degrees() {
+        let doc = process("|<q>|<e degrees>sin(30)</e>| |<e degrees>cos((a-a)+60)*2</e>| |<e degrees>asin(sin(45))</e>| |<e degrees>atant(1, 1)</e>| |<e>sin(0)</e>|</q>|");
+        assert!(doc.questions[0].expressions[0].degrees && !doc.questions[0].expressions[4].degrees);
+        assert_eq!(generate(&doc, 1, None)[0].content, "0.500 1.000 45.000 45 0");
+        let doc = process("|<q>|<e>tan(45)</e>|</q>|");
+        let options = GenerateOptions { degrees: true, ..Default::default() };
+        assert_eq!(generate_with_options(&doc, 1, None, &options)[0].content, "1.000");
+        assert_eq!(to_radians("2*sin(x)+tan(1"), format!("2*sin((x)*{0})+tan((1)*{0})", std::f64::consts::PI / 180.0));
+        assert_eq!(process("|<q>|<e degrees>sin(a)</e>|</q>|").to_template(), "|<q>|<e degrees>sin(a)</e>|</q>|");
+    }
+
+    #[test]
+    fn test_variable_usage() {
+        let doc = process("|<v>g: int = [1,5]</v>||<q>|<v>a: int = [1,5]</v>||<e>a+b+g</e>|</q>||<q>|<e>a</e>||<v>[b,c]: partition(5)</v>|</q>||<q>|<v>g: int = [1,2]</v>||<e>g</e>|</q>||<q>None</q>|");
+        let usage = doc.variable_usage();
+        assert_eq!(usage, HashMap::from([(String::from("a"), 2), (String::from("b"), 2), (String::from("c"), 1), (String::from("g"), 1)]));
+        assert!(process("|<q>None</q>|").variable_usage().is_empty());
+    }
+
+    #[test]
+    fn test_symbolic_expressions() {
+        let doc = process_with_answers("|<q>|<v>a: int = 3</v>||<v>b: int = -2</v>||<v>x: real = 1.5</v>|Simplify |<e symbolic>a - b*x</e>||<part>|<e symbolic>randint(4,4) + a</e>|</part>|</q>||<a>|<e symbolic>a - b*x</e>| |<part>|<e symbolic>randint(4,4) + a</e>|</part>|</a>|");
+        assert_eq!(doc.questions[0].expressions[0].mode, ExpMode::Symbolic);
+        let test = generate(&doc, 1, None).remove(0);
+        assert_eq!(test.content, "Simplify 3 - (-2)*1.5a) 4 + 3");
+        assert_eq!(test.answers, "6 a) 7");
+        assert_eq!(doc.questions[0].evaluate_answer(&HashMap::from([(String::from("a"), 1.0), (String::from("b"), 1.0), (String::from("x"), 1.0)])).unwrap(), "0 a) 5");
+        assert_eq!(process("|<q>|<e symbolic>a+b</e>|</q>|").to_template(), "|<q>|<e symbolic>a+b</e>|</q>|");
+    }
+
+    #[test]
+    fn test_too_complex_expressions() {
+        let nested = format!("{}a{}", "(".repeat(5000), ")".repeat(5000));
+        let doc = process(&format!("|<q>|<e>{}</e>|</q>|", nested));
+        assert!(matches!(try_generate(&doc, 1, None), Err(MorphiusError::TooComplex { question: 0, .. })));
+        let doc = process(&format!("|<q>|<e>{}</e>|</q>|", vec!["a"; 6000].join("+")));
+        assert!(matches!(try_generate(&doc, 1, None), Err(MorphiusError::TooComplex { .. })));
+        let doc = process("|<q>|<e>((((a))))</e>|</q>|");
+        assert!(try_generate(&doc, 1, None).is_ok());
+        let options = GenerateOptions { max_expression_depth: 3, ..Default::default() };
+        assert!(matches!(generate_tests(&doc, 1, None, &options, &mut rand::thread_rng()), Err(MorphiusError::TooComplex { .. })));
+        assert_eq!(nesting_depth("(a+(b))*(c)"), 2);
+    }
+
+    #[test]
+    fn test_keys_by_form() {
+        let doc = process_with_answers("|<q>1</q>||<a>One</a>|\n|<q>2</q>||<a>Two</a>|\n|<q>3</q>||<a>Three</a>|");
+        let forms = generate_named_forms(&doc, 4, Some(3)).unwrap();
+        let keys = keys_by_form(&forms);
+        assert_eq!(keys.len(), 4);
+        for (label, test) in forms.iter() {
+            assert_eq!(keys[label], format!("Form {} Key\n{}", label, test.answers));
+        }
+        assert!(keys_by_form(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_multiple_names_in_declaration() {
+        let doc = process("|<v>g, h: int = 4</v>||<q>|<v>a, b,c: int = [1,10]</v>||<e>a</e>| |<e>b</e>| |<e>c</e>| |<e>g+h</e>|</q>|");
+        let vars: Vec<&Var> = doc.questions[0].vars.iter().sorted_by(|a, b| a.name.cmp(&b.name)).collect();
+        assert_eq!(vars.iter().map(|var| &var.name[..]).collect::<Vec<&str>>(), vec!["a", "b", "c"]);
+        assert!(vars.iter().all(|var| var.num_type == "int" && var.min == "1" && var.max == "10"));
+        assert_eq!(doc.globals.len(), 2);
+        assert!(generate(&doc, 20, None).iter().all(|test| test.content.ends_with(" 8")));
+        assert!(try_process("|<q>|<v>a, a: int = [1,10]</v>||<e>a</e>|</q>|").is_ok());
+        let doc = process("|<q>|<v>a, b: int = [1,10]</v>||<v>b: int = [20,30]</v>||<e>b</e>|</q>|");
+        assert_eq!(doc.questions[0].vars.iter().find(|var| var.name == "b").unwrap().min, "20");
+        assert!(matches!(try_process("|<q>|<v>a, b: int = [1,10]</v>||<v>b: int = [20,30]</v>||<e>b</e>|</q>|"), Err(MorphiusError::DuplicateDeclaration { .. })));
+        assert_eq!(process("|<q>|<v>a, b: int = [1,10] grain 2</v>|</q>|").questions[0].vars.len(), 2);
+        assert_eq!(process("|<q>|<v>a, b: nonsense</v>|</q>|").questions[0].layout, vec!["|<v>a, b: nonsense</v>|"]);
+    }
+
+    #[test]
+    fn test_generate_with_callback() {
+        let doc = process("|<seed>3</seed>||<q>|<e>a</e>|</q>||<q>|<e>b</e>|</q>|");
+        let mut seen = Vec::new();
+        assert_eq!(generate_with_callback(&doc, 5, Some(2), |index, test| {
+            seen.push((index, test.content.clone()));
+            true
+        }), Ok(5));
+        let expected: Vec<(usize, String)> = generate(&doc, 5, Some(2)).into_iter().map(|test| test.content).enumerate().collect();
+        assert_eq!(seen, expected);
+        assert_eq!(generate_with_callback(&doc, 5, Some(2), |_, _| false), Ok(1));
+        assert_eq!(generate_with_callback(&doc, 0, None, |_, _| true), Ok(0));
+        let doc = process("|<q>|<e>(a</e>|</q>|");
+        assert!(matches!(generate_with_callback(&doc, 5, None, |_, _| true), Err(MorphiusError::Math { .. })));
+    }
+
+    #[test]
+    fn test_alternative_answers() {
+        let template = "|<q>|<v>a: int = 2</v>|Halve |<e>a/2</e>|</q>||<a>|<e>a/4</e>|</a>||<a>1/|<e>4/a</e>|</a>|\n|<q>Next</q>||<a>Only</a>|";
+        let doc = process_with_answers(template);
+        let answer = doc.questions[0].answer.as_ref().unwrap();
+        assert_eq!(answer.alternatives.len(), 1);
+        assert_eq!(doc.layout, vec!["", "\n", ""]);
+        let test = generate(&doc, 1, None).remove(0);
+        assert_eq!(test.questions[0].answer, "0.5 or 1/2");
+        assert_eq!(test.questions[1].answer, "Only");
+        assert_eq!(doc.questions[0].evaluate_answer(&HashMap::from([(String::from("a"), 4.0)])).unwrap(), "1 or 1/1");
+        assert_eq!(doc.to_template(), template);
+        assert!(validate(&process_with_answers("|<q>|<e>a</e>|</q>||<a>|<e>a</e>|</a>||<a>|<e>b</e>|</a>|")).is_err());
+    }
+
+    #[test]
+    fn test_answer_range() {
+        let doc = process_with_answers("|<q>|<v>a: int = [1,9]</v>||<v>b: int = [1,9]</v>|What is |<e>a</e>| - |<e>b</e>|?</q>||<a range=[0,100]>|<e>a-b</e>|</a>|");
+        assert_eq!(doc.questions[0].answer.as_ref().unwrap().range, Some((0.0, 100.0)));
+        let tests = generate(&doc, 200, None);
+        assert!(tests.iter().any(|test| !test.questions[0].warnings.is_empty()));
+        for test in tests.iter() {
+            let negative = test.questions[0].answer.starts_with('-');
+            assert_eq!(test.questions[0].warnings.is_empty(), !negative);
+        }
+        assert!(matches!(try_generate(&doc, 200, None), Err(MorphiusError::AnswerOutOfRange { question: 0, .. })));
+        let doc = process_with_answers("|<q>|<v>a: int = [1,9]</v>||<e>a</e>|</q>||<a range=[1,9]>|<e>a</e>|</a>||<a range=[2,18]>|<e>a*2</e>|</a>|");
+        assert!(try_generate(&doc, 50, None).is_ok());
+        assert_eq!(doc.to_template(), "|<q>|<v>a: int = [1,9]</v>||<e>a</e>|</q>||<a range=[1,9]>|<e>a</e>|</a>||<a range=[2,18]>|<e>a*2</e>|</a>|");
+    }
+
+    #[test]
+    fn test_estimate_work() {
+        let doc = process("|<q>|<e>a</e>||<part>|<e>b</e>|</part>||<hint>|<e>c</e>|</hint>|</q>||<q>|<e>a</e>|</q>||<q>None</q>|");
+        assert_eq!(doc.estimate_work(4, None), 16);
+        assert_eq!(doc.estimate_work(3, Some(2)), 8);
+        assert_eq!(doc.estimate_work(3, Some(10)), 12);
+        assert_eq!(doc.estimate_work(0, None), 0);
+        assert_eq!(process("No questions").estimate_work(5, None), 0);
+    }
+
+    #[test]
+    fn test_tables() {
+        let path = std::env::temp_dir().join("morphius_test_cities.csv");
+        fs::write(&path, "name,pop,area\nParis,2100000,105.4\n\"Springfield, IL\",114000,\"155.8\"\r\n").unwrap();
+        let path = path.to_str().unwrap();
+        let template = format!("|<q>|<v>city: row(\"{}\")</v>|How dense is |<e>city.name</e>| with |<e>city.pop</e>| people?</q>||<a>|<e>city.pop/city.area</e>|</a>|", path);
+        let doc = process_with_answers(&template);
+        let table = &doc.questions[0].tables[0];
+        assert_eq!(table.columns, vec!["city.name", "city.pop", "city.area"]);
+        assert_eq!(table.rows[1], vec!["Springfield, IL", "114000", "155.8"]);
+        assert_eq!(doc.questions[0].vars.iter().map(|var| &var.name[..]).collect::<Vec<&str>>(), vec!["city"]);
+        assert!(validate(&doc).is_ok());
+        let mut seen = HashSet::new();
+        for test in generate(&doc, 50, None) {
+            let expected = if test.content.contains("Paris") { ("2100000", 2100000.0 / 105.4) } else { ("114000", 114000.0 / 155.8) };
+            assert!(test.content.contains(&format!("with {} people", expected.0)));
+            assert_eq!(test.answers, format_number(expected.1));
+            seen.insert(test.content);
+        }
+        assert_eq!(seen.len(), 2);
+        assert_eq!(doc.to_template(), template);
+        let doc = process(&format!("|<q>|<v>city: row(\"{}\")</v>||<e>city.name*2</e>|</q>|", path));
+        assert!(try_generate(&doc, 1, None).is_err());
+        assert!(matches!(try_process("|<q>|<v>city: row(\"missing/morphius.csv\")</v>|</q>|"), Err(MorphiusError::MissingTable { .. })));
+        assert_eq!(parse_csv("a,\"b \"\"c\"\"\"\n\n1,\"x\ny\""), vec![vec!["a", "b \"c\""], vec!["1", "x\ny"]]);
+    }
+
+    #[test]
+    fn test_inline_answers() {
+        let doc = process_with_answers("1. |<q>|<v>a: int = 3</v>|Double |<e>a</e>|</q>||<a>|<e>a*2</e>|</a>|\n2. |<q>Name a color</q>||<a>Red</a>|\n");
+        let options = GenerateOptions { inline_answers: Some(String::from(" [Answer: {answer}]")), ..Default::default() };
+        let test = generate_with_options(&doc, 1, Some(2), &options).remove(0);
+        assert!(test.content == "1. Double 3 [Answer: 6]\n2. Name a color [Answer: Red]\n" || test.content == "1. Name a color [Answer: Red]\n2. Double 3 [Answer: 6]\n");
+        assert!(test.questions.iter().all(|question| !question.content.contains("Answer")));
+        assert_eq!(generate(&doc, 1, None)[0].content, "1. Double 3\n2. Name a color\n");
+    }
+
+    #[test]
+    fn test_malformed_tags() {
+        let inputs = ["|<q>a</q>|</q>|", "|<q>|<q>a</q>|</q>|", "|<q>|<e>|<e>a</e>|</e>|</q>|", "|<q>a", "x</q>|", "|<q>|<e>a</q>|", "|<q>|<part>a</q>|", "|<q>|<hint>a</q>|", "|<q>|<v>a: nonsense</v>|</q>|"];
+        for input in inputs {
+            let doc = process(input);
+            assert_eq!(doc.layout.len(), doc.questions.len() + 1);
+            assert!(matches!(try_process(input), Err(MorphiusError::MalformedTag { .. })), "{}", input);
+        }
+        assert_eq!(try_process("|<q>a</q>|</q>|").err(), Some(MorphiusError::MalformedTag { tag: String::from("</q>|"), question: None }));
+        assert_eq!(try_process("|<q>|<e>a</q>|").err(), Some(MorphiusError::MalformedTag { tag: String::from("|<e>"), question: Some(0) }));
+        assert!(try_process("|<q>a (|<points/>| points)</q>||<a>Not parsed</a>|").is_ok());
+        assert!(try_process("|<q>a</q>||<a>|<e>a+1</e>| |<part>b</part>|</a>|").is_ok());
+        assert!(matches!(try_process_with_answers("|<q>a</q>||<a>b</a>||<q>No answer</q>|"), Err(MorphiusError::MalformedTag { question: None, .. })));
+        assert!(try_process_with_answers("|<q>a</q>||<a>b |<part>c</part>|</a>||<a>d</a>|").is_ok());
+    }
+
+    #[test]
+    fn test_generate_until_deadline() {
+        use std::time::{Duration, Instant};
+        let doc = process("|<q>|<v>a: int = [1,10]</v>||<v>b: int = [a,a+5]</v>||<e>a+b</e>|</q>||<q>2</q>|");
+        let (tests, completed) = generate_until(&doc, 20, Some(2), Instant::now() + Duration::from_secs(30)).unwrap();
+        assert!(completed);
+        assert_eq!(tests.len(), 20);
+        let (tests, completed) = generate_until(&doc, 20, Some(2), Instant::now() - Duration::from_millis(1)).unwrap();
+        assert!(!completed);
+        assert!(tests.is_empty());
+        let options = GenerateOptions { balanced: true, deadline: Some(Instant::now()), ..Default::default() };
+        let mut rng = rand::thread_rng();
+        assert_eq!(generate_tests(&doc, 3, Some(2), &options, &mut rng).err(), Some(MorphiusError::DeadlineExceeded));
+        let (tests, completed) = generate_until(&doc, 0, None, Instant::now()).unwrap();
+        assert!(completed && tests.is_empty());
+        let doc = process("|<q>|<e>(a</e>|</q>|");
+        assert!(matches!(generate_until(&doc, 5, None, Instant::now() + Duration::from_secs(30)), Err(MorphiusError::Math { .. })));
+    }
+
+    #[test]
+    fn test_cycle_variables() {
+        let doc = process("|<v>g: real = cycle[0.5, -1]</v>||<q>|<v>a: int = cycle[2,5,8]</v>||<e>a</e>| |<e>g</e>|</q>|");
+        let contents: Vec<String> = generate(&doc, 7, None).into_iter().map(|test| test.content).collect();
+        assert_eq!(contents, vec!["2 0.5", "5 -1", "8 0.5", "2 -1", "5 0.5", "8 -1", "2 0.5"]);
+        let var = doc.questions[0].vars.iter().find(|var| var.name == "a").unwrap();
+        assert_eq!((&var.min[..], &var.max[..]), ("2", "8"));
+        assert_eq!(process(&doc.to_template()).to_template(), doc.to_template());
+        assert!(doc.to_template().contains("|<v>a: int = cycle[2,5,8]</v>|"));
+        let mut all: Vec<String> = generate_all(&process("|<q>|<v>a: int = cycle[3,1]</v>||<e>a</e>|</q>|"), None, 10).unwrap().into_iter().map(|test| test.content).collect();
+        all.sort();
+        assert_eq!(all, vec!["1", "3"]);
+        assert!(try_process("|<q>|<v>a: int = cycle[]</v>||<e>a</e>|</q>|").is_err());
+    }
+
+    #[test]
+    fn test_consolidate_keys() {
+        let doc = process_with_answers("|<q>|<v>a: int = cycle[1,2,3]</v>||<e>a</e>|</q>||<a>|<e>a*10</e>|</a>|");
+        let first = generate(&doc, 2, None);
+        let second = generate(&doc, 3, None);
+        assert_eq!(consolidate_keys(&[&first, &second]), "Batch 1 Test 1 Key\n10\n\nBatch 1 Test 2 Key\n20\n\nBatch 2 Test 3 Key\n30");
+        assert_eq!(consolidate_keys(&[&[], &first[..1]]), "Batch 2 Test 1 Key\n10");
+        assert_eq!(consolidate_keys(&[]), "");
+    }
+
+    #[test]
+    fn test_random_sources() {
+        let doc = process("|<seed>7</seed>||<q>|<v>a: int = [1,1000000]</v>||<e>a</e>|</q>|");
+        let contents = |source: RandomSource| -> Vec<String> {
+            let options = GenerateOptions { random_source: source, ..Default::default() };
+            generate_with_options(&doc, 5, None, &options).into_iter().map(|test| test.content).collect()
+        };
+        assert_eq!(contents(RandomSource::Document), contents(RandomSource::Seed(7)));
+        assert_eq!(contents(RandomSource::Seed(3)), contents(RandomSource::Seed(3)));
+        assert_ne!(contents(RandomSource::Seed(3)), contents(RandomSource::Seed(4)));
+        assert_ne!(contents(RandomSource::Os), contents(RandomSource::Os));
+        assert_eq!(contents(RandomSource::Thread).len(), 5);
+        assert_eq!(GenerateOptions::default().random_source, RandomSource::Document);
+    }
+
+    #[test]
+    fn test_comparison_expressions() {
+        let doc = process_with_answers("|<q>|<v>a: int = [1,9]</v>||<v>b: int = [1,9]</v>|Is |<e>a</e>| > |<e>b</e>|?</q>||<a>|<e>a > b</e>|</a>|");
+        for _ in 0..20 {
+            let test = &generate(&doc, 1, None)[0];
+            let (a, b) = (test.questions[0].values["a"], test.questions[0].values["b"]);
+            assert_eq!(test.answers, if a > b { "True" } else { "False" });
+        }
+        let doc = process("|<q>|<e>0.1 + 0.2 == 0.3</e>| |<e>2 != 2</e>| |<e>max(1, 2) >= 2</e>| |<e>3 <= 2</e>| |<e>2*(1-3) < -3</e>|</q>|");
+        assert_eq!(generate(&doc, 1, None)[0].content, "True False True False True");
+        let options = GenerateOptions { true_text: String::from("T"), false_text: String::from("F"), ..Default::default() };
+        assert_eq!(generate_with_options(&doc, 1, None, &options)[0].content, "T F T F T");
+        assert_eq!(comparison("max(a < b) > 1"), Some((11, ">")));
+        assert_eq!(comparison("a+b"), None);
+    }
+
+    #[test]
+    fn test_reroll_question() {
+        let doc = process_with_answers("|<v>g: int = [1,1000]</v>||<q id=base>|<v>a: int = [1,1000]</v>||<e>a</e>|</q>||<a>|<e>a</e>|</a>|-|<q shares=base>|<v>b: int = [1,1000]</v>||<e>a+b+g</e>|</q>||<a>|<e>b</e>|</a>|-|<q>|<e>g</e>|</q>||<a>G</a>|");
+        let mut test = generate(&doc, 1, None).remove(0);
+        let before: Vec<String> = test.questions.iter().map(|q| q.content.clone()).collect();
+        let (a, g) = (test.questions[0].values["a"], test.globals["g"]);
+        test.reroll_question(&doc, 1, Some(1), &GenerateOptions::default()).unwrap();
+        let b = test.questions[1].values["b"];
+        assert_eq!(test.questions[1].content, format_number(a + b + g));
+        assert_eq!(test.content, format!("{}-{}-{}", before[0], test.questions[1].content, before[2]));
+        assert_eq!(test.answers, format!("{}-{}-G", a, b));
+        let mut other = generate(&doc, 1, None).remove(0);
+        other.globals = test.globals.clone();
+        other.questions[0].values = test.questions[0].values.clone();
+        other.reroll_question(&doc, 1, Some(1), &GenerateOptions::default()).unwrap();
+        assert_eq!(other.questions[1].content, test.questions[1].content);
+        assert_eq!(test.reroll_question(&doc, 3, None, &GenerateOptions::default()), Err(MorphiusError::InvalidPosition { pos: 3, len: 3 }));
+        let smaller = process("|<q>Only</q>|");
+        assert!(matches!(test.reroll_question(&smaller, 0, None, &GenerateOptions::default()), Err(MorphiusError::MismatchedDocument { len: 1, .. })));
+
+        //The rerolled test is put together with the options it was generated with, and keeps its cycles while updating its counts
+        let doc = process_with_answers("|<q>|<v>k: int = cycle[5, 6]</v>||<v>c: choice = [up, down]</v>||<v>x: real = [0,1]</v>||<e>k</e>| |<e>c</e>| |<e>x</e>|</q>||<a>|<e>k</e>|</a>|\n|<q summary>Ups</q>||<a>|<e>count_c_up</e>|</a>|");
+        let options = GenerateOptions { footnote_answers: true, line_ending: Some(String::from("\r\n")), real_decimals: Some(1), ..Default::default() };
+        let mut tests = generate_with_options(&doc, 2, None, &options);
+        for (pos, test) in tests.iter_mut().enumerate() {
+            for seed in 0..10 {
+                test.reroll_question(&doc, 0, Some(seed), &options).unwrap();
+                let question = &test.questions[0];
+                assert_eq!(question.values["k"], 5.0 + pos as f64);
+                assert_eq!(question.content.split(' ').nth(2).unwrap().len(), 3);
+                assert_eq!(test.globals["count_c_up"], if question.content.contains("up") { 1.0 } else { 0.0 });
+                assert!(test.content.starts_with(&format!("{} [1]\r\n", question.content)));
+                assert!(test.content.contains("\r\n\r\nAnswers\r\n[1] "));
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_finite_results() {
+        let doc = process("|<q>|<v>b: int = [0,1]</v>||<e>10/b</e>| |<e>2/(b-b+1)</e>|</q>|");
+        for _ in 0..20 {
+            assert_eq!(try_generate(&doc, 1, None).unwrap()[0].content, "10 2");
+        }
+        let doc = process("|<q>|<e>10^400</e>|</q>|");
+        assert_eq!(try_generate(&doc, 1, None).err(), Some(MorphiusError::NotFinite { expression: String::from("10^400"), question: 0 }));
+        let doc = process("|<q>First</q>||<q>|<v>b: int = 0</v>||<e>1/b > 1</e>|</q>|");
+        assert_eq!(try_generate(&doc, 1, None).err(), Some(MorphiusError::NotFinite { expression: String::from("1/0 "), question: 1 }));
+        let doc = process("|<q id=base>|<v>b: int = [0,1]</v>||<e>b</e>|</q>||<q shares=base>|<e>1/b</e>|</q>|");
+        for _ in 0..20 {
+            let result = try_generate(&doc, 1, Some(2));
+            assert!(matches!(result, Ok(_) | Err(MorphiusError::NotFinite { question: 1, .. })));
+        }
+    }
+
+    #[test]
+    fn test_choice_variables() {
+        let doc = process("|<q>|<v>grade: choice = [A:1, B:2, C:3, D:0, E]</v>|Grade |<e>grade</e>|</q>|");
+        assert_eq!(doc.questions[0].choices[0].options, vec![(String::from("A"), 1.0), (String::from("B"), 2.0), (String::from("C"), 3.0), (String::from("D"), 0.0), (String::from("E"), 1.0)]);
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for test in generate(&doc, 700, None) {
+            *counts.entry(test.content).or_default() += 1;
+        }
+        assert!(!counts.contains_key("Grade D"));
+        assert!(counts["Grade A"] < counts["Grade C"]);
+        assert!(counts["Grade A"] + counts["Grade E"] + counts["Grade B"] + counts["Grade C"] == 700);
+        assert_eq!(process(&doc.to_template()).to_template(), doc.to_template());
+        let doc = process("|<q>|<v>n: choice = [2:1, 4.5:0]</v>||<e>n*2</e>|</q>|");
+        assert_eq!(generate(&doc, 1, None)[0].content, "4");
+        assert_eq!(try_process("|<q>|<v>n: choice = [a:0, b:0]</v>||<e>n</e>|</q>|").err(), Some(MorphiusError::InvalidChoice { name: String::from("n"), values: String::from("a:0, b:0") }));
+        assert!(matches!(try_process("|<q>|<v>n: choice = [a:-1, b]</v>||<e>n</e>|</q>|"), Err(MorphiusError::InvalidChoice { .. })));
+        assert!(matches!(try_process("|<q>|<v>n: choice = [a:x]</v>||<e>n</e>|</q>|"), Err(MorphiusError::InvalidChoice { .. })));
+        let mut doc = process("|<q>|<v>n: choice = [a, b]</v>||<e>n</e>|</q>|");
+        doc.questions[0].choices[0].options = vec![(String::from("a"), 0.0), (String::from("b"), -1.0)];
+        assert_eq!(try_generate(&doc, 1, None).err(), Some(MorphiusError::InvalidChoice { name: String::from("n"), values: String::from("a:0, b:-1") }));
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let doc = process("|<q>|<v>a: int = [1,1]</v>|A long question about |<e>a</e>| apple</q>|\n|<q>Short</q>|");
+        let tests = generate(&doc, 2, None);
+        assert_eq!(side_by_side(&tests, 23), "Test 1     | Test 2\nA long     | A long\nquestion   | question\nabout 1    | about 1\napple      | apple\nShort      | Short");
+        assert_eq!(wrap_line("abcdefghij kl", 4), vec!["abcd", "efgh", "ij", "kl"]);
+        assert_eq!(wrap_line("ab abcdef", 4), vec!["ab", "abcd", "ef"]);
+        assert_eq!(wrap_line("", 4), vec![""]);
+        assert_eq!(side_by_side(&[], 80), "");
+        assert_eq!(side_by_side(&tests[..1], 0).lines().nth(1), Some("A"));
+    }
+
+    #[test]
+    fn test_answer_branches() {
+        let doc = process_with_answers("|<q>|<v>dir: choice = [north, south]</v>||<v>a: int = [1,2]</v>|Go |<e>dir</e>| |<e>a</e>|</q>||<a when=north>Up |<e>a</e>|</a>||<a when=south>Down</a>||<a when=a:2>Two</a>||<a>Default</a>|");
+        for test in generate(&doc, 40, None) {
+            let expected = match (&test.content[..], test.questions[0].values["a"] as i64) {
+                ("Go north 1", _) => "Up 1",
+                ("Go north 2", _) => "Up 2 or Two",
+                ("Go south 2", _) => "Down or Two",
+                _ => "Down"
+            };
+            assert_eq!(test.answers, expected);
+        }
+        assert_eq!(process_with_answers(&doc.to_template()).to_template(), doc.to_template());
+        let doc = process_with_answers("|<q>|<v>a: int = 1</v>||<e>a</e>|</q>||<a when=a:2>Two</a>||<a>Other</a>|");
+        assert_eq!(generate(&doc, 1, None)[0].answers, "Other");
+        let doc = process_with_answers("|<q>|<v>a: int = 1</v>||<e>a</e>|</q>||<a when=a:2>Two</a>|");
+        assert_eq!(generate(&doc, 1, None)[0].answers, "No Answers Provided");
+    }
+
+    #[test]
+    fn test_footnote_answers() {
+        let doc = process_with_answers("|<q>1 + 1?</q>||<a>2</a>|\n|<q>Name a color</q>||<a></a>|\n|<q>2 + 2?</q>||<a>4</a>|");
+        let options = GenerateOptions { footnote_answers: true, ..Default::default() };
+        let test = &generate_with_options(&doc, 1, None, &options)[0];
+        assert_eq!(test.content, "1 + 1? [1]\nName a color\n2 + 2? [2]\n\nAnswers\n[1] 2\n[2] 4");
+        assert_eq!(test.answers, "2\n\n4");
+        assert_eq!(generate(&doc, 1, None)[0].content, "1 + 1?\nName a color\n2 + 2?");
+    }
+
+    #[test]
+    fn test_escaped_expressions() {
+        let doc = process_with_answers("|<q>|<v>a: int = 3</v>||<v>b: int = 4</v>||<e>a \\+ b</e>| |<e>\\$a*2</e>| |<e>a\\\\b</e>|</q>||<a range=[0,5]>|<e>a\\+b</e>|</a>|");
+        let test = &generate(&doc, 1, None)[0];
+        assert_eq!(test.content, "3 + 4 $6 3\\4");
+        assert_eq!(test.answers, "3+4");
+        assert!(test.questions[0].warnings.is_empty());
+        assert!(matches!(doc.questions[0].expressions[1].expression[0], ExpComp::Literal(ref text) if text == "$"));
+        assert_eq!(process_with_answers(&doc.to_template()).to_template(), doc.to_template());
+        let doc = process("|<q>|<v>a: int = 5</v>||<e width=16>The price is \\$a</e>|</q>|");
+        assert_eq!(generate(&doc, 1, None)[0].content, " The price is $5");
+        let doc = process_with_answers("|<q>|<v>a: int = 9</v>||<e>a</e>|</q>||<a range=[0,5]>|<e>1\\,a</e>|</a>|");
+        assert_eq!(generate(&doc, 1, None)[0].questions[0].warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_attempts_travel_with_questions() {
+        let doc = process_with_answers("|<q attempts=3>A</q>||<a>1</a>|\n|<q points=1.5>\"B\"\tb</q>||<a>2</a>|");
+        assert_eq!(doc.questions[0].attempts, Some(3));
+        assert_eq!(doc.questions[1].attempts, None);
+        assert_eq!(process_with_answers(&doc.to_template()).to_template(), doc.to_template());
+        for test in generate(&doc, 10, Some(2)) {
+            for question in test.questions.iter() {
+                assert_eq!(question.attempts, if question.index == 0 { Some(3) } else { None });
+            }
+            assert!(test.to_json().contains(r#"{"index":1,"content":"\"B\"\tb","answer":"2","points":1.5,"attempts":null}"#));
+            assert!(test.to_json().contains(r#"{"index":0,"content":"A","answer":"1","points":null,"attempts":3}"#));
+        }
+        assert_eq!(json_string("a\\b\n\u{1}"), r#""a\\b\n\u0001""#);
+    }
+
+    #[test]
+    fn test_roster_manifest() {
+        let doc = process("|<q>A</q>||<q>B</q>||<q>C</q>|");
+        let tests = vec![gen_form(&doc, Some(&vec![2, 0]), &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap(), gen_form(&doc, None, &HashMap::new(), &GenerateOptions::default(), &mut rand::thread_rng()).unwrap()];
+        let assignments: Vec<(String, Test)> = vec![String::from("Bo"), String::from("Alexandra")].into_iter().zip(tests).collect();
+        assert_eq!(roster_manifest(&assignments), "Name       Questions\nBo         3, 1\nAlexandra  1, 2, 3");
+        assert_eq!(roster_manifest(&[]), "Name  Questions");
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let cases = [(0.0005, [0.001, 0.0, 0.0, 0.001]), (0.0015, [0.002, 0.002, 0.001, 0.002]), (-0.0015, [-0.002, -0.002, -0.001, -0.001]),
+            (0.00151, [0.002, 0.002, 0.001, 0.002]), (2.0025, [2.003, 2.002, 2.002, 2.003]), (9.9995, [10.0, 10.0, 9.999, 10.0]), (1.5, [1.5, 1.5, 1.5, 1.5])];
+        for (num, expected) in cases {
+            let rounded: Vec<f64> = [Rounding::HalfUp, Rounding::HalfEven, Rounding::Truncate, Rounding::Ceiling].iter().map(|rounding| round_decimal(num, 3, *rounding)).collect();
+            assert_eq!(rounded, expected, "{}", num);
+        }
+        assert_eq!(round_decimal(0.5, 0, Rounding::HalfEven), 0.0);
+        assert_eq!(round_decimal(2.5, 0, Rounding::HalfUp), 3.0);
+        let doc = process("|<q>|<v>a: real = 0.0005</v>||<v>b: real = 2.0025</v>||<e>a</e>| |<e>b</e>| |<e currency>b</e>|</q>|");
+        let content = |rounding: Option<Rounding>| {
+            let options = GenerateOptions { rounding, max_decimals: Some(3), ..Default::default() };
+            generate_with_options(&doc, 1, None, &options).remove(0).content
+        };
+        assert_eq!(content(Some(Rounding::HalfUp)), "0.001 2.003 $2.00");
+        assert_eq!(content(Some(Rounding::HalfEven)), "0 2.002 $2.00");
+        assert_eq!(content(Some(Rounding::Ceiling)), "0.001 2.003 $2.01");
+        let options = GenerateOptions { rounding: Some(Rounding::HalfEven), real_decimals: Some(3), ..Default::default() };
+        assert_eq!(generate_with_options(&doc, 1, None, &options)[0].content, "0.000 2.002 $2.00");
+    }
+
+    #[test]
+    fn test_summary_questions_count_choices() {
+        let doc = process_with_answers("|<q summary>North: |<e>count_dir_north</e>|, south: |<e>count_dir_south</e>|</q>||<a>|<e>count_dir_north + count_dir_south</e>|</a>|\n\
+            |<q>|<v>dir: choice = [north, south]</v>|Go |<e>dir</e>|</q>||<a>1</a>|\n|<q>|<v>dir: choice = [north:1, south:0]</v>|Go |<e>dir</e>|</q>||<a>2</a>|\n\
+            |<q>|<v>n: choice = [1, 2:0]</v>||<e>n</e>|</q>||<a>3</a>|");
+        assert!(validate(&doc).is_ok());
+        assert!(doc.questions[0].summary && !doc.questions[1].summary);
+        assert!(doc.to_template().contains("|<q summary>North: "));
+        for test in generate(&doc, 20, None) {
+            let north = test.content.matches("Go north").count();
+            assert_eq!(test.content.lines().next().unwrap(), format!("North: {}, south: {}", north, 2 - north));
+            assert!(test.answers.starts_with("2\n"));
+            assert_eq!((test.globals["count_dir_north"], test.globals["count_n_1"], test.globals["count_n_2"]), (north as f64, 1.0, 0.0));
+        }
+        let doc = process("|<q>|<v>dir: choice = [north, south]</v>|</q>||<q>|<e>count_dir_north</e>|</q>|");
+        assert!(doc.questions[1].vars.contains(&default_var("count_dir_north")));
+    }
+
+    #[test]
+    fn test_required_questions_are_always_selected() {
+        let doc = process("|<q required>A</q>|\n|<q>B</q>|\n|<q>C</q>|\n|<q required>D</q>|\n|<q>E</q>|");
+        assert!(doc.questions[0].required && !doc.questions[1].required);
+        assert!(doc.to_template().starts_with("|<q required>A</q>|"));
+        for test in generate(&doc, 30, Some(3)) {
+            assert!(test.content.contains('A') && test.content.contains('D'));
+        }
+        let options = GenerateOptions { balanced: true, ..Default::default() };
+        let tests = generate_with_options(&doc, 30, Some(3), &options);
+        assert!(tests.iter().all(|test| test.content.contains('A') && test.content.contains('D')));
+        assert!(["B", "C", "E"].iter().all(|other| tests.iter().filter(|test| test.content.contains(other)).count() == 10));
+        //When there isn't room for every required question, the test only has required questions
+        for test in generate(&doc, 10, Some(1)) {
+            assert!(test.content.trim() == "A" || test.content.trim() == "D");
+        }
+        assert!(generate_named_forms(&doc, 2, Some(2)).unwrap().iter().all(|(_, test)| test.content.contains('A') && test.content.contains('D')));
+    }
+
+    #[test]
+    fn test_grading_spec() {
+        let doc = process_with_answers("|<q>|<v>a: int = [1,9]</v>|Half of |<e>a</e>|?</q>||<a tolerance=0.05>|<e>a/2</e>|</a>||<a>|<e>a</e>|/2</a>|\n\
+            |<q points=3>Name?</q>||<a>Any</a>|\n|<q>|<v>b: int = [1,2]</v>||<e>b</e>|</q>||<a when=b:5>Never</a>|");
+        assert_eq!(doc.questions[0].answer.as_ref().unwrap().tolerance, Some(0.05));
+        assert!(doc.to_template().contains("|<a tolerance=0.05>"));
+        let test = generate(&doc, 1, None).remove(0);
+        let a = test.questions[0].values["a"];
+        assert_eq!(test.questions[0].forms, vec![format_number(a / 2.0), format!("{}/2", a)]);
+        assert!(test.questions[2].forms.is_empty());
+        assert_eq!(test.grading_spec(), vec![
+            QuestionGrade { index: 0, answer: format_number(a / 2.0), tolerance: Some(0.05), alternatives: vec![format!("{}/2", a)], points: None },
+            QuestionGrade { index: 1, answer: String::from("Any"), tolerance: None, alternatives: Vec::new(), points: Some(3.0) }
+        ]);
+    }
+
+    #[test]
+    fn test_mirror_pairs_reverse_the_order() {
+        let doc = process_with_answers("Start\n|<q>|<v>a: int = [1,99]</v>|A|<e>a</e>|</q>||<a>|<e>a</e>|</a>|\n|<q>B</q>||<a>b</a>|\n|<q>C</q>||<a>c</a>|\n|<q>D</q>||<a>d</a>|\nEnd");
+        let pairs = generate_mirror_pair(&doc, 10, Some(3), &GenerateOptions::default()).unwrap();
+        assert_eq!(pairs.len(), 10);
+        for (test, mirror) in pairs {
+            let indices = |test: &Test| test.questions.iter().map(|q| q.index).collect::<Vec<usize>>();
+            assert_eq!(indices(&mirror), indices(&test).into_iter().rev().collect::<Vec<usize>>());
+            let lines = |text: &str| text.lines().map(String::from).collect::<Vec<String>>();
+            let (content, mirrored) = (lines(&test.content), lines(&mirror.content));
+            assert_eq!((&mirrored[0], mirrored.last().unwrap()), (&String::from("Start"), &String::from("End")));
+            assert_eq!(content[1..4].iter().rev().collect::<Vec<&String>>(), mirrored[1..4].iter().collect::<Vec<&String>>());
+            assert_eq!(lines(&test.answers)[1..4].iter().rev().collect::<Vec<&String>>(), lines(&mirror.answers)[1..4].iter().collect::<Vec<&String>>());
+        }
+        //The mirror is generated with the same options, and keeps the reverse order when they sort by difficulty
+        let doc = process_with_answers("|<q difficulty=2>|<v>n: choice = [x, y, z]</v>||<v>[p, q]: partition(10)</v>|One |<e>n</e>| |<e>p</e>|</q>||<a>|<e>p</e>|</a>|\n|<q difficulty=1>Two</q>||<a>2</a>|");
+        let options = GenerateOptions { inline_answers: Some(String::from(" [{answer}]")), line_ending: Some(String::from("\r\n")), by_difficulty: true, ..Default::default() };
+        for (test, mirror) in generate_mirror_pair(&doc, 10, None, &options).unwrap() {
+            assert!(test.content.starts_with("Two [2]\r\nOne "));
+            assert_eq!(mirror.content, test.content.rsplit("\r\n").join("\r\n"));
+            assert_eq!(mirror.questions[0].values, test.questions[1].values);
+        }
+    }
+
+    #[test]
+    fn test_show_expressions_show_the_worked_step() {
+        let doc = process_with_answers("|<q>|<v>a: int = [3,3]</v>||<v>b: int = [-4,-4]</v>||<v>x: real = [1.5,1.5]</v>|Find |<e>a - b</e>|</q>||<a>|<e show>a - b</e>|, |<e show> a*x </e>|, |<e show>a > b</e>|</a>|");
+        assert_eq!(doc.questions[0].answer.as_ref().unwrap().expressions[0].mode, ExpMode::Show);
+        assert!(doc.to_template().contains("|<e show>a - b</e>|"));
+        let options = GenerateOptions { real_decimals: Some(2), ..Default::default() };
+        let test = generate_with_options(&doc, 1, None, &options).remove(0);
+        assert_eq!(test.answers, "a - b = 3 - (-4) = 7, a*x = 3*1.5 = 4.50, a > b = 3 > (-4) = True");
+    }
+
+    #[test]
+    fn test_correct_parts_rotate_across_a_batch() {
+        let question = "|<q>Pick |<part>W</part>| |<part correct>Right</part>| |<part>X</part>| |<part>Y</part>|</q>|";
+        let doc = process(&format!("{}\n{}", question, question));
+        assert!(doc.questions[0].parts[1].correct && !doc.questions[0].parts[0].correct);
+        assert!(doc.to_template().contains("|<part correct>Right</part>|"));
+        let letter = |line: &str| line.chars().nth(line.find("Right").unwrap() - 3).unwrap();
+        let options = GenerateOptions { rotate_correct: true, ..Default::default() };
+        let mut uses: HashMap<char, usize> = HashMap::new();
+        for test in generate_with_options(&doc, 40, None, &options) {
+            let letters: Vec<char> = test.content.lines().map(letter).collect();
+            assert_ne!(letters[0], letters[1]);
+            for letter in letters {
+                *uses.entry(letter).or_default() += 1;
+            }
+        }
+        assert_eq!(uses, HashMap::from([('a', 20), ('b', 20), ('c', 20), ('d', 20)]));
+    }
+
+    #[test]
+    fn test_macros_expand_in_expressions() {
+        let doc = process_with_answers("|<macro TAX>price*0.08</macro>||<macro TOTAL>price + TAX</macro>|\n\
+            |<q>|<v>price: int = [50,50]</v>|Tax on |<e currency>price</e>|: |<e currency>TAX</e>|, taxed |<e>TAX*2</e>|</q>||<a>|<e>TOTAL</e>|</a>|");
+        assert_eq!(doc.macros["TOTAL"], "price + TAX");
+        assert!(validate(&doc).is_ok());
+        let test = generate(&doc, 1, None).remove(0);
+        assert_eq!(test.content, "\nTax on $50.00: $4.00, taxed 8");
+        assert_eq!(test.answers, "\n54");
+        assert!(doc.to_template().starts_with("|<macro TAX>price*0.08</macro>||<macro TOTAL>price + TAX</macro>|"));
+        assert_eq!(process_with_answers(&doc.to_template()).macros, doc.macros);
+        let error = try_process("|<macro A>B + 1</macro>||<macro B>A * 2</macro>||<q>|<e>A</e>|</q>|").err();
+        assert!(matches!(error, Some(MorphiusError::RecursiveMacro { .. })));
+    }
+
+    #[test]
+    fn test_top_up_distinct() {
+        let doc = process("|<seed>4</seed>||<q>|<v>a: int = [1,3]</v>||<e>a</e>|</q>||<q>|<v>b: int = [1,2]</v>||<e>b</e>|</q>|");
+        let existing = generate(&doc, 3, None);
+        let options = GenerateOptions::default();
+        let added = top_up_distinct(&existing, &doc, 5, None, &options).unwrap();
+        let contents: HashSet<&str> = existing.iter().chain(added.iter()).map(|test| &test.content[..]).collect();
+        assert_eq!(contents.len(), 5);
+        assert_eq!(added.len(), 5 - existing.iter().map(|test| &test.content).unique().count());
+        //There are only 6 distinct tests, so asking for more stops once all of them are found
+        let added = top_up_distinct(&existing, &doc, 10, None, &options).unwrap();
+        assert_eq!(existing.iter().chain(added.iter()).map(|test| &test.content).unique().count(), 6);
+        assert!(top_up_distinct(&existing, &doc, usize::MAX, None, &options).unwrap().len() <= 6);
+        assert!(top_up_distinct(&existing, &doc, 2, None, &options).unwrap().is_empty());
+        assert!(top_up_distinct(&[], &process("|<q>|<e>a/0</e>|</q>|"), 2, None, &options).is_err());
+        //The seed of the template gives the same new tests, unless the options draw from another source
+        let contents = |options: &GenerateOptions| top_up_distinct(&existing, &doc, 5, None, options).unwrap().into_iter().map(|test| test.content).collect::<Vec<String>>();
+        assert_eq!(contents(&options), contents(&options));
+        let seeded = GenerateOptions { random_source: RandomSource::Seed(9), ..Default::default() };
+        assert_eq!(contents(&seeded), contents(&seeded));
+    }
+
+    #[test]
+    fn test_given_lists_the_values_of_the_question() {
+        let doc = process("|<v>g: int = [5,5]</v>||<q>|<v>b: int = [7,7]</v>||<v>a: real = [2.5,2.5]</v>||<v>dir: choice = [north]</v>|Given: |<given/>|. Find |<e>a*b + g</e>| |<e>dir</e>|</q>|");
+        assert_eq!(generate(&doc, 1, None)[0].content, "Given: a = 2.5, b = 7, dir = north. Find 22.5 north");
+        assert_eq!(expand_compact("{{q}}{{given/}}{{/q}}"), "|<q>|<given/>|</q>|");
+        assert!(try_process("|<q>|<given/>|</q>|").is_ok());
+    }
+
+    #[test]
+    fn test_line_endings_are_normalized() {
+        let doc = process_with_answers("Quiz\r\n\r\n|<q>One\nline</q>||<a>1</a>|\n|<q>Two</q>||<a>2\r\n</a>|\nEnd");
+        let options = GenerateOptions { line_ending: Some(String::from("\r\n")), ..Default::default() };
+        let test = generate_with_options(&doc, 1, None, &options).remove(0);
+        assert_eq!(test.content, "Quiz\r\n\r\nOne\r\nline\r\nTwo\r\nEnd");
+        assert_eq!(test.answers, "Quiz\r\n\r\n1\r\n2\r\n\r\nEnd");
+        let options = GenerateOptions { line_ending: Some(String::from("\n")), ..Default::default() };
+        assert_eq!(generate_with_options(&doc, 1, None, &options)[0].content, "Quiz\n\nOne\nline\nTwo\nEnd");
+        assert!(generate(&doc, 1, None)[0].content.starts_with("Quiz\r\n\r\nOne\nline"));
+    }
+
+    #[test]
+    fn test_stable_ids() {
+        let template = "|<q>|<v>b: real = [0,1]</v>||<v>a: int = [1,9]</v>|Find |<e>a+b</e>||<part>x</part>||<hint>Add</hint>|</q>|\n|<q>Second</q>|";
+        let doc = process(template);
+        let ids: Vec<String> = doc.questions.iter().map(Question::stable_id).collect();
+        assert_eq!(ids[0].len(), 16);
+        assert_ne!(ids[0], ids[1]);
+        for _ in 0..5 {
+            assert_eq!(process(template).questions[0].stable_id(), ids[0]);
+        }
+        assert_eq!(process(&doc.to_template()).questions[0].stable_id(), ids[0]);
+        let moved = process("|<q>Second</q>|\n|<q if=extra>|<v>a: int = [1,9]</v>||<v>b: real = [0,1]</v>|Find |<e>a+b</e>||<part>x</part>||<hint>Add</hint>|</q>|");
+        assert_eq!((moved.questions[0].stable_id(), moved.questions[1].stable_id()), (ids[1].clone(), ids[0].clone()));
+        let edited = process("|<q>|<v>b: real = [0,2]</v>||<v>a: int = [1,9]</v>|Find |<e>a+b</e>||<part>x</part>||<hint>Add</hint>|</q>|");
+        assert_ne!(edited.questions[0].stable_id(), ids[0]);
+    }
+
+    #[test]
+    fn test_questions_ordered_by_difficulty() {
+        let doc = process("|<q difficulty=3>C</q>||<q>X</q>||<q difficulty=1>A</q>||<q difficulty=2>B</q>||<q difficulty=1>A2</q>||<q>Y</q>|");
+        assert_eq!(doc.questions[0].difficulty, Some(3));
+        assert_eq!(doc.questions[1].difficulty, None);
+        assert!(doc.to_template().starts_with("|<q difficulty=3>C</q>|"));
+        let options = GenerateOptions { by_difficulty: true, ..Default::default() };
+        assert_eq!(generate_with_options(&doc, 1, None, &options)[0].content, "AA2BCXY");
+        assert_eq!(generate_with_options(&doc, 1, Some(6), &options)[0].content, "AA2BCXY");
+        for test in generate_with_options(&doc, 20, Some(3), &options) {
+            let order: Vec<usize> = test.questions.iter().map(|question| question.index).collect();
+            let key = |index: &usize| (doc.questions[*index].difficulty.is_none(), doc.questions[*index].difficulty, *index);
+            assert!(order.windows(2).all(|pair| key(&pair[0]) < key(&pair[1])));
+        }
+    }
+
+    #[test]
+    fn test_samplers_give_variable_values() {
+        fn heights(_: &str, rng: &mut dyn RngCore) -> f64 {
+            *[150.5, 160.25, 170.0].choose(rng).unwrap()
+        }
+        fn seven(name: &str, _: &mut dyn RngCore) -> f64 {
+            assert!(name == "n" || name == "g");
+            6.6
+        }
+        let doc = process("|<v>g: int = [1,2]</v>||<q>|<v>h: real = [0,1]</v>||<v>n: int = [1,3]</v>||<v>m: int = [n,n]</v>||<e>h</e>| |<e>m</e>| |<e>g</e>|</q>|");
+        let mut samplers: HashMap<String, Sampler> = HashMap::new();
+        samplers.insert(String::from("h"), heights);
+        samplers.insert(String::from("n"), seven);
+        samplers.insert(String::from("g"), seven);
+        let options = GenerateOptions { samplers, ..Default::default() };
+        for test in generate_with_options(&doc, 20, None, &options) {
+            let words: Vec<&str> = test.content.split(' ').collect();
+            assert!(["150.5", "160.25", "170"].contains(&words[0]));
+            assert_eq!(&words[1..], ["7", "7"]);
+        }
+        let assignment = HashMap::from([((Some(0), "n"), Num::Int(2))]);
+        let test = gen_form(&doc, None, &assignment, &options, &mut rand::thread_rng()).unwrap();
+        assert!(test.content.ends_with(" 2 7"));
+    }
+
+    #[test]
+    fn test_part_points_in_answer_key() {
+        let doc = process_with_answers("|<q>|<part points=2>One</part>| |<part points=1>Two</part>| |<part>Three</part>|</q>||<a>|<part>1</part>| |<part>2</part>| |<part>3</part>|</a>|\
+            |<q>|<part points=4>Only</part>|</q>||<a>Whole</a>|");
+        assert_eq!(doc.questions[0].parts[0].points, Some(2.0));
+        assert!(doc.to_template().starts_with("|<q>|<part points=2>One</part>| |<part points=1>Two</part>| |<part>Three</part>|</q>|"));
+        for test in generate(&doc, 10, None) {
+            assert!(!test.content.contains("pt"));
+            let labels = ["a", "b", "c"];
+            let words: Vec<&str> = test.questions[0].content.split(' ').collect();
+            for (pos, part) in words.chunks(2).enumerate() {
+                let (name, value) = (part[0], part[1]);
+                assert_eq!(name, format!("{})", labels[pos]));
+                let answer = test.questions[0].answer.split(&format!("{}) ", labels[pos])).nth(1).unwrap();
+                let expected = match value { "One" => "1 [2 pts]", "Two" => "2 [1 pt]", _ => "3" };
+                assert!(answer.starts_with(expected), "{} {}", answer, expected);
+            }
+            assert_eq!(test.questions[1].answer, "Whole");
+        }
+    }
+
+    #[test]
+    fn test_static_questions() {
+        let doc = process_with_answers("|<q>Fixed</q>||<a>|<e>2+2</e>|</a>||<q>Fixed</q>||<a>4</a>||<q>|<part>x</part>||<part>y</part>|</q>||<a>x</a>|\
+            |<q>|<part>Only</part>|</q>||<a>y</a>||<q>Hint|<hint>|<e pick>up|down</e>|</hint>|</q>||<a>z</a>|");
+        assert_eq!(doc.static_questions(), vec![1, 3]);
+        assert!(process("").static_questions().is_empty());
+    }
+
+    #[test]
+    fn test_hints_share_earlier_questions() {
+        let doc = process_with_answers("|<q id=trip>|<v>s: int = [10,20]</v>||<v>v: int = [1,5]</v>|Speed |<e>s</e>| |<e>v</e>|</q>||<a>|<e>s</e>|</a>|\n\
+            |<q>|<v>v: int = [6,9]</v>|Time |<e>v</e>||<hint shares=trip>Distance |<e>s*v</e>|</hint>||<hint>Own |<e>v</e>|</hint>|</q>||<a>|<e>v</e>|</a>|\n\
+            |<q>Roll|<hint shares=trip>|<e>randint(1,s)</e>|</hint>|</q>||<a>r</a>|");
+        let names: Vec<&str> = doc.questions[1].vars.iter().map(|var| &var.name[..]).collect();
+        assert_eq!(names, ["v"]);
+        assert_eq!(doc.questions[1].hints[0].shares, ["trip"]);
+        assert!(doc.to_template().contains("|<hint shares=trip>Distance |<e>s*v</e>|</hint>||<hint>Own |<e>v</e>|</hint>|"));
+        assert!(validate(&doc).is_ok());
+        for test in generate(&doc, 10, None) {
+            let question = |index: usize| test.questions.iter().find(|question| question.index == index).unwrap();
+            let (speed, time) = (question(0), question(1));
+            assert_eq!(time.hint, format!("Distance {}\nOwn {}", speed.values["s"] * time.values["v"], time.values["v"]));
+            assert!(time.values["v"] >= 6.0);
+            let roll: f64 = question(2).hint.parse().unwrap();
+            assert!((1.0..=speed.values["s"]).contains(&roll));
+        }
+        let doc = process("|<q>|<hint shares=none>|<e>a</e>|</hint>|</q>|");
+        assert_eq!(validate(&doc), Err(MorphiusError::UnknownQuestion { id: String::from("none"), question: 0 }));
+    }
+
+    #[test]
+    fn test_bubble_sheets() {
+        let doc = process("|<q>A |<part>w</part>| |<part>x</part>| |<part correct>y</part>| |<part>z</part>|</q>|\n|<q>Free</q>|\n|<q>B |<part correct>y</part>| |<part>n</part>|</q>|");
+        let seeds: Vec<u64> = (0..40).collect();
+        let sheets = generate_bubble_sheets(&doc, &seeds, None).unwrap();
+        let mut letters = HashSet::new();
+        for (seed, (test, key)) in seeds.iter().zip(sheets.iter()) {
+            let again = generate_with_rng(&doc, 1, None, &mut rand::rngs::StdRng::seed_from_u64(*seed)).remove(0);
+            assert_eq!((&again.content, &again.bubble_key()), (&test.content, key));
+            let expected: Vec<String> = test.questions.iter().enumerate().filter(|(_, question)| question.index != 1).map(|(pos, question)| {
+                let words: Vec<&str> = question.content.split(' ').collect();
+                let label = words[words.iter().position(|word| *word == "y").unwrap() - 1].chars().next().unwrap();
+                format!("{}-{}", pos + 1, label.to_ascii_uppercase())
+            }).collect();
+            assert_eq!(key, &expected.join(" "));
+            letters.insert(test.questions.iter().find(|question| question.index == 0).unwrap().correct);
+        }
+        assert_eq!(letters.len(), 4);
+        let doc = process("|<q>Sky |<part correct>Blue</part>| |<part>Red</part>|</q>|\n|<q>|<e>(a</e>|</q>|");
+        assert!(matches!(generate_bubble_sheets(&doc, &[1], None), Err(MorphiusError::Math { .. })));
+    }
+
+    #[test]
+    fn test_parts_that_show_the_same_text() {
+        let doc = process("|<q>Which is larger? |<part>|<e>10/3</e>|</part>| |<part>|<e>3.3334</e>|</part>|</q>|");
+        assert_eq!(try_generate(&doc, 1, None).err(), Some(MorphiusError::DuplicateParts { text: String::from("3.333"), question: 0 }));
+        //The values are generated again until the parts are different
+        let doc = process("|<q>|<v>a: int = [1,2]</v>|Pick |<part>|<e>a</e>|</part>| |<part>1</part>|</q>|");
+        for test in generate(&doc, 20, None) {
+            assert_eq!(test.questions[0].values["a"], 2.0);
         }
     }
 